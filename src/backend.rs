@@ -0,0 +1,396 @@
+use crate::{
+	bytecode,
+	codegen::{self, Codegen},
+	codegen_c, codegen_js, codegen_wasm,
+	diagnostics::Diagnostics,
+	expr::{Expr, Span},
+	interp::Interpreter,
+	value,
+	vm::Vm,
+};
+
+/// A resolved and optimized program, what every backend starts from.
+pub struct Program {
+	pub expr: Expr,
+}
+
+/// Where running a program writes what it prints.
+pub type Output = dyn std::io::Write + Send;
+
+#[derive(Debug)]
+pub enum RunError {
+	/// The backend produced code it can't run.
+	Invalid(String),
+	/// The program failed.
+	Runtime { message: String, span: Span },
+}
+
+impl From<value::Error> for RunError {
+	fn from(err: value::Error) -> Self {
+		Self::Runtime {
+			message: err.message,
+			span: err.span,
+		}
+	}
+}
+
+/// A way of running programs.
+pub trait Backend {
+	/// Prepares `program` to run, reporting what can't be compiled to `diagnostics`.
+	fn compile(&mut self, program: &Program, diagnostics: &mut Diagnostics);
+
+	/// Runs the compiled program, returning its value as the backend shows it.
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError>;
+
+	/// The compiled program as a standalone file, for backends that generate one.
+	fn emit(&self) -> Option<&[u8]> {
+		None
+	}
+}
+
+#[derive(Default)]
+pub struct Options {
+	/// Cache the results of pure recursive functions, where the backend can.
+	pub memoize: bool,
+}
+
+/// Names [`by_name`] knows, the default one first.
+pub const BACKENDS: [&str; 6] = ["hvm", "interp", "vm", "js", "c", "wasm"];
+
+pub fn by_name(name: &str, options: &Options) -> Option<Box<dyn Backend>> {
+	Some(match name {
+		"hvm" => Box::new(Hvm::new(options.memoize)),
+		"interp" => Box::new(Interp::default()),
+		"vm" => Box::new(Bytecode::default()),
+		"js" => Box::new(Js::default()),
+		"c" => Box::new(C::default()),
+		"wasm" => Box::new(Wasm::default()),
+		_ => return None,
+	})
+}
+
+/// Transpiles to HVM rules and reduces them, see [`codegen`].
+pub struct Hvm {
+	memoize: bool,
+	heap_size: usize,
+	code: String,
+}
+
+impl Hvm {
+	pub fn new(memoize: bool) -> Self {
+		Self {
+			memoize,
+			heap_size: hvm::runtime::default_heap_size(),
+			code: String::new(),
+		}
+	}
+}
+
+impl Backend for Hvm {
+	fn compile(&mut self, program: &Program, diagnostics: &mut Diagnostics) {
+		self.code = Codegen::new(diagnostics)
+			.memoize(self.memoize)
+			.transpile(program.expr.clone());
+	}
+
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError> {
+		codegen::normalize(&self.code, self.heap_size, out).map_err(|err| match err {
+			RunError::Invalid(err) => {
+				RunError::Invalid(format!("generated invalid HVM code\n{err}"))
+			}
+			err => err,
+		})
+	}
+
+	fn emit(&self) -> Option<&[u8]> {
+		Some(self.code.as_bytes())
+	}
+}
+
+/// Evaluates the tree directly, see [`crate::interp`].
+#[derive(Default)]
+pub struct Interp {
+	expr: Option<Expr>,
+}
+
+impl Backend for Interp {
+	fn compile(&mut self, program: &Program, _: &mut Diagnostics) {
+		self.expr = Some(program.expr.clone());
+	}
+
+	/// The interpreter recurses on the Rust stack for every call that isn't in tail
+	/// position.
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError> {
+		let expr = self.expr.as_ref().expect("running before compiling");
+		on_big_stack(|| {
+			Interpreter::new(out)
+				.run(expr)
+				.map(|value| value.to_string())
+				.map_err(RunError::from)
+		})
+	}
+}
+
+/// Compiles to bytecode for the stack machine, see [`crate::vm`].
+///
+/// The bytecode shares its strings with the values through `Rc`s, so it is compiled on the
+/// thread that runs it.
+#[derive(Default)]
+pub struct Bytecode {
+	expr: Option<Expr>,
+}
+
+impl Backend for Bytecode {
+	fn compile(&mut self, program: &Program, _: &mut Diagnostics) {
+		self.expr = Some(program.expr.clone());
+	}
+
+	/// Frames live on the heap, but showing, comparing and dropping nested tuples recurse.
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError> {
+		let expr = self.expr.as_ref().expect("running before compiling");
+		on_big_stack(|| {
+			let program = bytecode::compile(expr);
+			Vm::new(&program, out)
+				.run()
+				.map(|value| value.to_string())
+				.map_err(RunError::from)
+		})
+	}
+}
+
+/// Transpiles to JavaScript and runs it with `node`, see [`codegen_js`].
+#[derive(Default)]
+pub struct Js {
+	code: String,
+}
+
+impl Backend for Js {
+	fn compile(&mut self, program: &Program, _: &mut Diagnostics) {
+		self.code = codegen_js::transpile(&program.expr);
+	}
+
+	/// The program is piped to `node`.
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError> {
+		use std::{
+			io::Write,
+			process::{Command, Stdio},
+		};
+
+		let mut node = Command::new("node")
+			.args(["-", "--result"])
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|err| RunError::Invalid(format!("couldn't run `node`: {err}")))?;
+
+		// node only starts once the whole program is read, so this can't block on the output
+		let mut stdin = node.stdin.take().unwrap();
+		stdin
+			.write_all(self.code.as_bytes())
+			.and_then(|_| stdin.flush())
+			.map_err(|err| RunError::Invalid(format!("couldn't write to `node`: {err}")))?;
+		drop(stdin);
+
+		let output = node
+			.wait_with_output()
+			.map_err(|err| RunError::Invalid(format!("couldn't run `node`: {err}")))?;
+		finish("node", output, out)
+	}
+
+	fn emit(&self) -> Option<&[u8]> {
+		Some(self.code.as_bytes())
+	}
+}
+
+/// Transpiles to C, builds it with the system `cc`, or `$CC`, and runs the executable, see
+/// [`codegen_c`].
+#[derive(Default)]
+pub struct C {
+	code: String,
+}
+
+impl Backend for C {
+	fn compile(&mut self, program: &Program, _: &mut Diagnostics) {
+		self.code = codegen_c::transpile(&program.expr);
+	}
+
+	/// The program is built in a temporary directory, removed once it ran.
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError> {
+		use std::{
+			process::Command,
+			sync::atomic::{AtomicUsize, Ordering},
+		};
+
+		static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+		let dir = std::env::temp_dir().join(format!(
+			"rinha-{}-{}",
+			std::process::id(),
+			BUILDS.fetch_add(1, Ordering::Relaxed)
+		));
+		let mut run = || {
+			let invalid = |err: std::io::Error| RunError::Invalid(err.to_string());
+			std::fs::create_dir_all(&dir).map_err(invalid)?;
+			std::fs::write(dir.join("main.c"), &self.code).map_err(invalid)?;
+
+			let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+			let build = Command::new(&cc)
+				.args(["-O2", "-pthread", "-o", "main", "main.c"])
+				.current_dir(&dir)
+				.output()
+				.map_err(|err| RunError::Invalid(format!("couldn't run `{cc}`: {err}")))?;
+			if !build.status.success() {
+				let stderr = String::from_utf8_lossy(&build.stderr);
+				return Err(RunError::Invalid(format!("`{cc}` failed\n{stderr}")));
+			}
+
+			let output = Command::new(dir.join("main"))
+				.arg("--result")
+				.output()
+				.map_err(invalid)?;
+			finish("the program", output, out)
+		};
+
+		let result = run();
+		let _ = std::fs::remove_dir_all(&dir);
+		result
+	}
+
+	fn emit(&self) -> Option<&[u8]> {
+		Some(self.code.as_bytes())
+	}
+}
+
+/// Emits a WebAssembly module and runs it with the interpreter of [`crate::wasm`], see
+/// [`codegen_wasm`].
+#[derive(Default)]
+pub struct Wasm {
+	module: Vec<u8>,
+}
+
+impl Backend for Wasm {
+	fn compile(&mut self, program: &Program, _: &mut Diagnostics) {
+		self.module = codegen_wasm::transpile(&program.expr);
+	}
+
+	fn run(&mut self, out: &mut Output) -> Result<String, RunError> {
+		codegen_wasm::run(&self.module, out)
+	}
+
+	fn emit(&self) -> Option<&[u8]> {
+		Some(&self.module)
+	}
+}
+
+/// Runs `run` on a thread with a stack big enough for the values and calls of deep programs.
+fn on_big_stack<T: Send>(run: impl FnOnce() -> T + Send) -> T {
+	const STACK_SIZE: usize = 1 << 30;
+
+	std::thread::scope(|scope| {
+		std::thread::Builder::new()
+			.stack_size(STACK_SIZE)
+			.spawn_scoped(scope, run)
+			.unwrap()
+			.join()
+			.unwrap()
+	})
+}
+
+/// Reads what a generated program wrote, `--result` having it write its value, or the error
+/// it failed with, to the standard error.
+fn finish(
+	program: &str,
+	output: std::process::Output,
+	out: &mut Output,
+) -> Result<String, RunError> {
+	out.write_all(&output.stdout)
+		.map_err(|err| RunError::Invalid(err.to_string()))?;
+
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	if let Some(result) = stderr.strip_prefix("result: ") {
+		return Ok(result.to_owned());
+	}
+	let runtime = stderr
+		.strip_prefix("runtime error: ")
+		.and_then(|error| error.strip_suffix(')')?.rsplit_once(" (at "))
+		.and_then(|(message, span)| {
+			let (start, end) = span.split_once("..")?;
+			Some((message, Span::new(start.parse().ok()?, end.parse().ok()?)))
+		});
+	match runtime {
+		Some((message, span)) => Err(RunError::Runtime {
+			message: message.to_owned(),
+			span,
+		}),
+		None => Err(RunError::Invalid(format!("{program} failed\n{stderr}"))),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{by_name, Backend, Hvm, Options, Program, RunError, BACKENDS};
+	use crate::{diagnostics::Diagnostics, optimize, testing::parse};
+
+	fn compile(backend: &mut dyn Backend, src: &str) {
+		let mut diagnostics = Diagnostics::new();
+		let program = Program {
+			expr: optimize::optimize(parse(src)),
+		};
+		backend.compile(&program, &mut diagnostics);
+		assert!(!diagnostics.has_errors(), "{diagnostics:?}");
+	}
+
+	#[test]
+	fn backends_agree() {
+		let src = "
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			let pair = (fib(20), (\"fib\", fib(10) == 55));
+			let _ = print(pair);
+			pair
+		";
+
+		let mut hvm = Hvm {
+			heap_size: 1 << 24,
+			..Hvm::new(false)
+		};
+		compile(&mut hvm, src);
+		let mut out = vec![];
+		assert_eq!(
+			hvm.run(&mut out).unwrap(),
+			"(Pair (STD.int 6765) (Pair (STD.str \"fib\") (STD.bool 1)))"
+		);
+		assert_eq!(out, b"(6765, (fib, true))\n");
+		assert_eq!(hvm.emit(), Some(hvm.code.as_bytes()));
+
+		for name in &BACKENDS[1..] {
+			let mut backend = by_name(name, &Options::default()).unwrap();
+			compile(&mut *backend, src);
+			let mut out = vec![];
+			let result = match backend.run(&mut out) {
+				// `node` or `cc` isn't installed
+				Err(RunError::Invalid(err)) if err.starts_with("couldn't run") => continue,
+				result => result.unwrap(),
+			};
+			assert_eq!(result, "(6765, (fib, true))");
+			assert_eq!(out, b"(6765, (fib, true))\n", "{name}");
+		}
+		assert!(by_name("nope", &Options::default()).is_none());
+	}
+
+	#[test]
+	fn deep_values() {
+		let src = "
+			let build = fn (n, acc) => if (n == 0) { acc } else { build(n - 1, (n, acc)) };
+			let l = build(1000000, 0);
+			print(first(l))
+		";
+		for name in ["interp", "vm"] {
+			let mut backend = by_name(name, &Options::default()).unwrap();
+			compile(&mut *backend, src);
+			let mut out = vec![];
+			assert_eq!(backend.run(&mut out).unwrap(), "1", "{name}");
+			assert_eq!(out, b"1\n", "{name}");
+		}
+	}
+}
@@ -0,0 +1,364 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::expr::{BinOp, Binding, BindingId, Builtin, Expr, ExprKind, Ident, Span};
+
+/// An instruction of the stack machine in [`crate::vm`].
+///
+/// Jump targets are indices into the code of the same function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+	Int(i32),
+	Bool(bool),
+	Str(Rc<str>),
+	/// Pushes a slot of the current frame: the arguments, then the `let`s of the function.
+	Local(u32),
+	/// Pops into a slot of the current frame.
+	SetLocal(u32),
+	/// Pushes a value the running closure captured.
+	Capture(u32),
+	Global(u32),
+	SetGlobal(u32),
+	/// Pushes the running closure, for local functions calling themselves.
+	Current,
+	/// Pops both operands, pushing the result.
+	Binary(BinOp),
+	Jump(u32),
+	/// Pops a bool, jumping if it is false.
+	JumpUnless(u32),
+	/// Jumps, keeping the bool on top, if it is `value`. Pops it otherwise, so `&&` and
+	/// `||` can evaluate their right side.
+	Branch {
+		value: bool,
+		target: u32,
+	},
+	/// Fails if the top of the stack isn't a bool.
+	CheckBool,
+	/// Pops the second element, then the first one.
+	Tuple,
+	/// Pops the captured values, pushing a closure over `function`.
+	Closure {
+		function: u32,
+		captures: u32,
+	},
+	/// Pops the arguments, then the callee.
+	Call(u32),
+	/// Like [`Op::Call`], but replaces the current frame.
+	TailCall(u32),
+	Builtin(Builtin, u32),
+	Return,
+}
+
+#[derive(Debug, Default)]
+pub struct Function {
+	pub arity: u32,
+	/// Slots of a frame, arguments included.
+	pub locals: u32,
+	pub code: Vec<Op>,
+	/// Source of each instruction, for runtime errors.
+	pub spans: Vec<Span>,
+}
+
+#[derive(Debug)]
+pub struct Program {
+	/// Every function of the program, the top-level expression first.
+	pub functions: Vec<Function>,
+	/// Names of the top-level `let`s, which are globals.
+	pub globals: Vec<String>,
+}
+
+impl Program {
+	pub const MAIN: u32 = 0;
+}
+
+/// A function being compiled.
+#[derive(Default)]
+struct Scope {
+	function: Function,
+	slots: HashMap<BindingId, u32>,
+	/// Variables of enclosing functions, in the order the closure captures them.
+	captures: Vec<BindingId>,
+	/// The `let` the function is bound to, if it can refer to itself.
+	current: Option<BindingId>,
+}
+
+/// Lowers a resolved tree to bytecode.
+///
+/// The top-level `let` chain becomes globals, so top-level functions can call each other
+/// in any order. Other functions are closures, capturing the variables of enclosing
+/// functions they use when they are created.
+struct Compiler {
+	scopes: Vec<Scope>,
+	functions: Vec<Function>,
+	globals: HashMap<BindingId, u32>,
+	names: Vec<String>,
+}
+
+pub fn compile(expr: &Expr) -> Program {
+	let mut compiler = Compiler {
+		scopes: vec![Scope::default()],
+		functions: vec![Function::default()],
+		globals: HashMap::new(),
+		names: vec![],
+	};
+
+	let mut next = expr;
+	while let ExprKind::Let {
+		name, next: rest, ..
+	} = &next.kind
+	{
+		compiler
+			.globals
+			.insert(name.id(), compiler.names.len() as u32);
+		compiler.names.push(name.name.clone());
+		next = rest;
+	}
+
+	compiler.expr(expr, true);
+	compiler.emit(Op::Return, expr.span);
+	compiler.functions[Program::MAIN as usize] = compiler.scopes.pop().unwrap().function;
+
+	Program {
+		functions: compiler.functions,
+		globals: compiler.names,
+	}
+}
+
+impl Compiler {
+	fn scope(&mut self) -> &mut Scope {
+		self.scopes.last_mut().unwrap()
+	}
+
+	fn emit(&mut self, op: Op, span: Span) -> u32 {
+		let function = &mut self.scope().function;
+		function.code.push(op);
+		function.spans.push(span);
+		function.code.len() as u32 - 1
+	}
+
+	fn here(&mut self) -> u32 {
+		self.scope().function.code.len() as u32
+	}
+
+	/// Points the jump at `at` to the next instruction.
+	fn patch(&mut self, at: u32) {
+		let here = self.here();
+		match &mut self.scope().function.code[at as usize] {
+			Op::Jump(target) | Op::JumpUnless(target) | Op::Branch { target, .. } => *target = here,
+			op => unreachable!("{op:?} doesn't jump"),
+		}
+	}
+
+	fn slot(&mut self, id: BindingId) -> u32 {
+		let function = &mut self.scope().function;
+		let slot = function.locals;
+		function.locals += 1;
+		self.scope().slots.insert(id, slot);
+		slot
+	}
+
+	fn variable(&mut self, id: BindingId, span: Span) {
+		let global = self.globals.get(&id).copied();
+		let scope = self.scope();
+		let op = if let Some(slot) = scope.slots.get(&id) {
+			Op::Local(*slot)
+		} else if let Some(global) = global {
+			Op::Global(global)
+		} else if scope.current == Some(id) {
+			Op::Current
+		} else {
+			let index = match scope.captures.iter().position(|c| *c == id) {
+				Some(index) => index,
+				None => {
+					scope.captures.push(id);
+					scope.captures.len() - 1
+				}
+			};
+			Op::Capture(index as u32)
+		};
+		self.emit(op, span);
+	}
+
+	fn bind(&mut self, id: BindingId, span: Span) {
+		let op = match self.globals.get(&id) {
+			Some(global) => Op::SetGlobal(*global),
+			None => Op::SetLocal(self.slot(id)),
+		};
+		self.emit(op, span);
+	}
+
+	fn function(&mut self, params: &[Ident], body: &Expr, current: Option<BindingId>, span: Span) {
+		let mut scope = Scope {
+			current,
+			..Scope::default()
+		};
+		scope.function.arity = params.len() as u32;
+		self.scopes.push(scope);
+		for param in params {
+			self.slot(param.id());
+		}
+
+		self.expr(body, true);
+		self.emit(Op::Return, body.span);
+
+		let scope = self.scopes.pop().unwrap();
+		let function = self.functions.len() as u32;
+		self.functions.push(scope.function);
+
+		for capture in &scope.captures {
+			self.variable(*capture, span);
+		}
+		self.emit(
+			Op::Closure {
+				function,
+				captures: scope.captures.len() as u32,
+			},
+			span,
+		);
+	}
+
+	/// Pushes the value of `expr`. In `tail` position, calls replace the current frame.
+	fn expr(&mut self, expr: &Expr, tail: bool) {
+		let span = expr.span;
+
+		match &expr.kind {
+			ExprKind::Int(i) => {
+				self.emit(Op::Int(*i), span);
+			}
+			ExprKind::Bool(b) => {
+				self.emit(Op::Bool(*b), span);
+			}
+			ExprKind::Str(s) => {
+				self.emit(Op::Str(s.as_str().into()), span);
+			}
+			ExprKind::Variable(var) => match var.binding {
+				Binding::Local(id) => self.variable(id, span),
+				binding => unreachable!("`{}` is not a local binding: {binding:?}", var.name),
+			},
+			ExprKind::Binary {
+				lhs,
+				op: op @ (BinOp::And | BinOp::Or),
+				rhs,
+			} => {
+				self.expr(lhs, false);
+				let branch = self.emit(
+					Op::Branch {
+						value: *op == BinOp::Or,
+						target: 0,
+					},
+					span,
+				);
+				self.expr(rhs, false);
+				self.emit(Op::CheckBool, span);
+				self.patch(branch);
+			}
+			ExprKind::Binary { lhs, op, rhs } => {
+				self.expr(lhs, false);
+				self.expr(rhs, false);
+				self.emit(Op::Binary(op.clone()), span);
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				self.expr(condition, false);
+				let jump_unless = self.emit(Op::JumpUnless(0), span);
+				self.expr(then, tail);
+				let jump = self.emit(Op::Jump(0), span);
+				self.patch(jump_unless);
+				self.expr(otherwise, tail);
+				self.patch(jump);
+			}
+			ExprKind::Let { name, value, next } => {
+				match &value.kind {
+					// only local functions need `Op::Current`, globals can be loaded
+					ExprKind::Abstraction { args, body } => {
+						self.function(args, body, Some(name.id()), value.span)
+					}
+					_ => self.expr(value, false),
+				}
+				self.bind(name.id(), name.span);
+				self.expr(next, tail);
+			}
+			ExprKind::Tuple(first, second) => {
+				self.expr(first, false);
+				self.expr(second, false);
+				self.emit(Op::Tuple, span);
+			}
+			ExprKind::Abstraction { args, body } => self.function(args, body, None, span),
+			ExprKind::Application { callee, args } => {
+				let argc = args.len() as u32;
+				if let ExprKind::Variable(Ident {
+					binding: Binding::Builtin(builtin),
+					..
+				}) = callee.kind
+				{
+					args.iter().for_each(|arg| self.expr(arg, false));
+					self.emit(Op::Builtin(builtin, argc), span);
+					return;
+				}
+
+				self.expr(callee, false);
+				args.iter().for_each(|arg| self.expr(arg, false));
+				let op = match tail {
+					true => Op::TailCall(argc),
+					false => Op::Call(argc),
+				};
+				self.emit(op, span);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{compile, Op};
+	use crate::{diagnostics::Diagnostics, expr::BinOp, resolve, syntax};
+
+	#[test]
+	fn bytecode_functions() {
+		let mut expr = syntax::parse(
+			"
+			let count = fn (n) => if (n == 0) { 0 } else { count(n - 1) };
+			let make = fn (x) => { let go = fn (n) => if (n < x) { go(n + 1) } else { n }; go };
+			(count(3), make(2)(0))
+			",
+		)
+		.unwrap();
+		resolve::resolve(&mut expr, &mut Diagnostics::new());
+		let program = compile(&expr);
+
+		assert_eq!(program.globals, ["count", "make"]);
+		assert_eq!(program.functions.len(), 4);
+		assert_eq!(
+			program.functions[1].code,
+			[
+				Op::Local(0),
+				Op::Int(0),
+				Op::Binary(BinOp::Eq),
+				Op::JumpUnless(6),
+				Op::Int(0),
+				Op::Jump(11),
+				Op::Global(0),
+				Op::Local(0),
+				Op::Int(1),
+				Op::Binary(BinOp::Sub),
+				Op::TailCall(1),
+				Op::Return,
+			]
+		);
+
+		// `go` captures `x` from `make`, and calls itself
+		let go = &program.functions[2];
+		assert!(go.code.contains(&Op::Capture(0)), "{go:?}");
+		assert!(go.code.contains(&Op::Current), "{go:?}");
+		assert!(program.functions[3].code.starts_with(&[
+			Op::Local(0),
+			Op::Closure {
+				function: 2,
+				captures: 1
+			},
+			Op::SetLocal(1)
+		]));
+	}
+}
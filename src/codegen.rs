@@ -1,8 +1,19 @@
-use std::collections::HashMap;
+use std::{
+	collections::{BTreeSet, HashMap},
+	sync::Mutex,
+};
 
-use micromap::Map;
+use hvm::{
+	language::syntax::Term,
+	runtime::{ApplyObj, VisitObj},
+};
 
-use crate::expr::{Expr, Ident};
+use crate::{
+	backend::{Output, RunError},
+	diagnostics::Diagnostics,
+	effects::{Effect, Effects},
+	expr::{BinOp, Binding, BindingId, Expr, ExprKind, Ident, Span},
+};
 
 #[allow(non_snake_case)] // just for the luls
 fn ToPascalCase(string: String) -> String {
@@ -27,218 +38,1487 @@ fn ToPascalCase(string: String) -> String {
 	ret
 }
 
-pub struct Codegen {
-	main_func: Vec<String>,
-	builtins: Map<&'static str, &'static str, 3>,
-	variables: HashMap<String, String>,
+/// Separates `let`s in generated code; debug builds keep what `--emit` prints readable.
+#[cfg(debug_assertions)]
+const SEP: &str = "\n\t";
+#[cfg(not(debug_assertions))]
+const SEP: &str = "";
+
+/// A `let`-bound function lifted to a rule.
+///
+/// The variables it captures become extra leading parameters, so every call site passes
+/// them along with the arguments. Calling a top-level function defined further down captures
+/// a token for it too, see [`Codegen::lift_functions`].
+struct Function {
+	rule: String,
+	captures: Vec<BindingId>,
+	arity: usize,
+	/// How many top-level `let`s come before the one it is part of.
+	position: usize,
+	/// Whether the rule evaluates its arguments before rewriting, see [`tail_calls`].
+	strict: bool,
+	/// Whether the rule caches its results, see [`Codegen::lift_memoized`].
+	memo: bool,
 }
 
-// XXX: too buggy, need a whole rewrite
-impl Codegen {
-	const STD: &'static str = concat!('\n', include_str!("../std.hvm"));
+pub struct Codegen<'d> {
+	diagnostics: &'d mut Diagnostics,
+	rules: Vec<String>,
+	names: HashMap<BindingId, String>,
+	functions: HashMap<BindingId, Function>,
+	/// Top-level `let`s `Main` has not defined yet, by name.
+	undefined: HashMap<BindingId, String>,
+	/// The function whose rule body code is being generated for, `None` in `Main`.
+	rule: Option<BindingId>,
+	/// Arities of calls through closure values, each one needs its own `STD.call.N` rule.
+	call_arities: BTreeSet<usize>,
+	/// What functions and `let` values may do, analyzed once transpiling starts.
+	effects: Option<Effects>,
+	memoize: bool,
+	/// Whether `Main` reduces to its value as `print` shows it, see [`Codegen::show`].
+	show: bool,
+	/// Counter for the names of intermediate results in memoized rules.
+	memo_vars: usize,
+	/// Counter for the parameters of lifted functions wrapped back into closures.
+	closure_vars: usize,
+	/// Counter for the arguments and tuple fields evaluated first, see [`Codegen::sequenced`].
+	seq_vars: usize,
+}
 
-	pub fn new() -> Self {
-		let builtins = Map::from([
-			("print", "STD.print"),
-			("first", "STD.first"),
-			("second", "STD.second"),
-		]);
+impl<'d> Codegen<'d> {
+	const STD: &'static str = concat!('\n', include_str!("../std.hvm"));
 
+	pub fn new(diagnostics: &'d mut Diagnostics) -> Self {
 		Self {
-			main_func: vec![],
-			builtins,
-			variables: HashMap::from([
-				("print".into(), "STD.print".into()),
-				("first".into(), "STD.first".into()),
-				("second".into(), "STD.second".into()),
-			]),
+			diagnostics,
+			rules: vec![],
+			names: HashMap::new(),
+			functions: HashMap::new(),
+			undefined: HashMap::new(),
+			rule: None,
+			call_arities: BTreeSet::new(),
+			effects: None,
+			memoize: false,
+			show: false,
+			memo_vars: 0,
+			closure_vars: 0,
+			seq_vars: 0,
 		}
 	}
 
+	/// Caches the results of pure recursive functions, so naive recursions like `fib` run
+	/// in linear time. Off by default, as the cache costs time and memory on every call.
+	pub fn memoize(mut self, memoize: bool) -> Self {
+		self.memoize = memoize;
+		self
+	}
+
+	/// Has `Main` reduce to the string `print` would write for its value instead of the
+	/// value itself, so tests can compare it with the other backends.
+	#[cfg(test)]
+	pub fn show(mut self, show: bool) -> Self {
+		self.show = show;
+		self
+	}
+
 	pub fn transpile(mut self, expr: Expr) -> String {
-		let mut code = self.transpile_expr(expr, 0);
+		self.effects = Some(Effects::analyze(&expr));
+		self.lift_functions(&expr);
+		let mut main = self.toplevel(expr);
+		if self.show {
+			main = format!("(STD.show {main})");
+		}
+		let mut code = self.rules.join("\n");
+		for arity in self.call_arities {
+			let params = (0..arity).map(|i| format!(" x{i}")).collect::<String>();
+			let body = if arity == 0 {
+				"f".to_owned()
+			} else {
+				format!("(f{params})")
+			};
+			let call = format!("\n(STD.call.{arity} loc");
+			code.push_str(&format!("{call} (STD.closure {arity} f){params}) = {body}"));
+			code.push_str(&format!(
+				"{call} (STD.closure n _){params}) = (STD.error (STD.String.concat \"closure takes \" \
+				 (STD.i32.show \" arguments, but {arity} were given\" n)) loc)"
+			));
+			code.push_str(&format!(
+				"{call} f{params}) = (STD.error.expected loc \"a closure\" f)"
+			));
+		}
 		code.push_str(Self::STD);
-		code.push_str(&format!(
-			"(Main) = ({})",
-			self.main_func.into_iter().rev().collect::<String>()
-		));
+		code.push_str(&format!("(Main) = {main}"));
 		code
 	}
 
-	fn transpile_expr(&mut self, expr: Expr, depth: usize) -> String {
-		match expr {
-			Expr::Let { name, value, next } if depth == 0 => {
-				let Ident(name_) = name;
+	/// Gives a binder its HVM name.
+	///
+	/// Every binder gets its own name, suffixed with its id, so shadowed and shadowing
+	/// bindings never clash. Capitalized names get a `_` prefix, otherwise HVM would read
+	/// them as constructors.
+	fn bind(&mut self, ident: &Ident) -> String {
+		let prefix = if ident.name.starts_with(|c: char| c.is_ascii_uppercase()) {
+			"_"
+		} else {
+			""
+		};
+		let name = format!("{prefix}{}.{}", ident.name, ident.id().0);
+		self.names.insert(ident.id(), name.clone());
+		name
+	}
 
-				match *value {
-					Expr::Abstraction { args, body } => {
-						let name = ToPascalCase(name_.clone());
-						self.variables.insert(name_, name.clone());
+	/// Like [`Self::bind`], but for functions compiled to rules, which must be capitalized.
+	fn bind_rule(&mut self, ident: &Ident) -> String {
+		let name = ToPascalCase(ident.name.clone());
+		let prefix = if name.starts_with(|c: char| c.is_ascii_uppercase()) {
+			""
+		} else {
+			"Fn"
+		};
+		let name = format!("{prefix}{name}.{}", ident.id().0);
+		self.names.insert(ident.id(), name.clone());
+		name
+	}
 
-						for arg in args.iter() {
-							self.variables.insert(arg.0.clone(), arg.0.clone());
-						}
+	fn variable(&mut self, var: &Ident) -> String {
+		match var.binding {
+			// a lifted function used as a value gets wrapped back into a closure, whose
+			// parameters need fresh names: `$` ones would be global to the whole term
+			Binding::Local(id) => match self.functions.get(&id) {
+				Some(function) => {
+					let arity = function.arity;
+					let closure = self.closure_vars;
+					self.closure_vars += 1;
+					let params = (0..arity)
+						.map(|i| format!("closure.{closure}.{i}"))
+						.collect::<Vec<_>>();
+					let lambdas = params.iter().map(|p| format!("@{p} ")).collect::<String>();
+					format!(
+						"(STD.closure {arity} {lambdas}{})",
+						self.call_function(var, params)
+					)
+				}
+				None => self.names[&id].clone(),
+			},
+			Binding::Builtin(builtin) => builtin.to_string(),
+			Binding::Unresolved => {
+				self.diagnostics
+					.error(format!("unbound variable `{}`", var.val()), var.span);
+				var.name.clone()
+			}
+		}
+	}
 
-						let body = self.transpile_expr(*body, 1);
-
-						match next.as_ref() {
-							Expr::Let { value, .. }
-								if matches!(value.as_ref(), Expr::Abstraction { .. }) =>
-							{
-								let next = self.transpile_expr(*next, depth);
-								format!(
-									"({name}{}) = (STD.closure ({}))\n{next}",
-									args.iter().fold(String::new(), |mut acc, ident| {
-										acc.push(' ');
-										acc.push_str(ident.val());
-										acc
-									}),
-									body
-								)
-							}
-							_ => {
-								let next = self.transpile_expr(*next, depth);
-								self.main_func.insert(0, next);
-
-								format!(
-									"({name}{}) = (STD.closure ({}))",
-									args.iter().fold(String::new(), |mut acc, ident| {
-										acc.push(' ');
-										acc.push_str(ident.val());
-										acc
-									}),
-									body
-								)
-							}
-						}
-					}
-					expr => {
-						self.variables.insert(name_.clone(), name_.clone());
-						let next = self.transpile_expr(*next, depth);
-
-						// XXX: terrible workaround
-						// so i don't need to handle closures in global scope
-						// (only works for literals)
-						match &expr {
-							Expr::Str(_) | Expr::Int(_) | Expr::Bool(_) => {
-								let literal = self.transpile_expr(expr, depth + 1);
-								#[cfg(debug_assertions)]
-								self.main_func.push(format!("let {name_} = {literal};\n\t"));
-								#[cfg(not(debug_assertions))]
-								self.main_func.push(format!("let {name_} = {literal};"));
-								return format!("let {name_} = {literal};\n{next}");
-							}
-							_ => {}
-						}
+	/// Direct call of a lifted function, passing its captures before the arguments.
+	///
+	/// A rule calling a function defined further down checks its token first, so the call
+	/// fails like it does on the other backends if `Main` hasn't reached its `let` yet.
+	fn call_function(&mut self, var: &Ident, args: Vec<String>) -> String {
+		let id = var.id();
+		let loc = location(var.span);
+		if self.rule.is_none() && self.undefined.contains_key(&id) {
+			// only closures `Main` creates can refer to it, they are taken to run right away
+			return format!(
+				"(STD.error \"`{}` is used before its definition\" {loc})",
+				var.name
+			);
+		}
 
-						let val = self.transpile_expr(expr, depth + 1);
+		let function = &self.functions[&id];
+		let captures = function
+			.captures
+			.iter()
+			.map(|&capture| self.pass(capture))
+			.collect::<Vec<_>>();
 
-						#[cfg(not(debug_assertions))]
-						self.main_func.push(format!("let {name_} = {val};"));
-						#[cfg(debug_assertions)]
-						self.main_func.push(format!("let {name_} = {val};\n\t"));
-						next
-					}
+		let call = std::iter::once(&function.rule)
+			.chain(&captures)
+			.chain(&args)
+			.fold(String::from("("), |mut acc, part| {
+				if acc.len() > 1 {
+					acc.push(' ');
 				}
+				acc.push_str(part);
+				acc
+			}) + ")";
+
+		match self.rule {
+			Some(rule) if self.functions[&rule].captures.contains(&id) => format!(
+				"(STD.defined.check {} \"{}\" {loc} {call})",
+				self.capture_name(id),
+				var.name
+			),
+			_ => call,
+		}
+	}
+
+	/// What a call passes for `capture`.
+	///
+	/// A rule has the values it captures in scope. It only gets the tokens of functions
+	/// defined after its own, the others are defined by the time it runs. `Main` passes
+	/// what it hasn't defined yet as `STD.undefined`.
+	fn pass(&self, capture: BindingId) -> String {
+		let token = self.functions.contains_key(&capture);
+		match self.rule {
+			Some(rule) if token && !self.functions[&rule].captures.contains(&capture) => {
+				"STD.ok".to_owned()
+			}
+			Some(_) => self.capture_name(capture),
+			None if self.undefined.contains_key(&capture) => "STD.undefined".to_owned(),
+			None if token => "STD.ok".to_owned(),
+			None => self.names[&capture].clone(),
+		}
+	}
+
+	/// The parameter a rule gets `capture` as, a `defined.N` token for a function.
+	fn capture_name(&self, capture: BindingId) -> String {
+		match self.functions.contains_key(&capture) {
+			true => format!("defined.{}", capture.0),
+			false => self.names[&capture].clone(),
+		}
+	}
+
+	/// Lifts every `let`-bound function to a rule before any code is generated, so calls
+	/// can refer to functions defined further down, and local functions can call themselves.
+	///
+	/// A function captures the free variables of its body, and calling another lifted
+	/// function means passing its captures too. Mutually recursive functions depend on each
+	/// other's captures, so they are computed as a fixpoint.
+	///
+	/// Calling a top-level function defined after the one being called from also captures
+	/// a token for it, `STD.undefined` until `Main` reaches its `let`. Tokens of functions
+	/// defined before are dropped, a function only runs once `Main` has defined it.
+	fn lift_functions(&mut self, expr: &Expr) {
+		let mut free = vec![];
+		let mut next = expr;
+		let mut position = 0;
+		while let ExprKind::Let {
+			name,
+			value,
+			next: rest,
+		} = &next.kind
+		{
+			if !matches!(value.kind, ExprKind::Abstraction { .. }) {
+				self.bind(name);
 			}
-			expr if depth == 0 => match &expr {
-				Expr::Int(_)
-				| Expr::Str(_)
-				| Expr::Bool(_)
-				| Expr::Variable(_)
-				| Expr::Binary { .. }
-				| Expr::Application { .. }
-				| Expr::If { .. }
-				| Expr::Tuple(_, _) => {
-					let ret = self.transpile_expr(expr, depth + 1);
-					self.main_func.push(ret);
-					String::new()
+			self.undefined.insert(name.id(), name.name.clone());
+			self.collect_function(name, value, position, &mut free);
+			position += 1;
+			next = rest;
+		}
+		self.collect_functions(next, position, &mut free);
+
+		loop {
+			let mut changed = false;
+
+			for (id, vars) in &free {
+				let position = self.functions[id].position;
+				let captures = vars
+					.iter()
+					.flat_map(|var| match self.functions.get(var) {
+						Some(function) => {
+							let mut captures = function.captures.clone();
+							captures.extend((function.position > position).then_some(*var));
+							captures
+						}
+						None => vec![*var],
+					})
+					.filter(|var| {
+						self.functions
+							.get(var)
+							.is_none_or(|function| function.position > position)
+					})
+					.collect::<BTreeSet<_>>()
+					.into_iter()
+					.collect::<Vec<_>>();
+
+				let function = self.functions.get_mut(id).unwrap();
+				if function.captures != captures {
+					function.captures = captures;
+					changed = true;
 				}
-				_ => unreachable!(),
-			},
-			Expr::Int(i) => format!(
-				"(STD.int {})",
-				if i < 0 {
-					2147483647 + (-i) as u32
-				} else {
-					i as u32
+			}
+
+			if !changed {
+				break;
+			}
+		}
+	}
+
+	/// Registers every `let`-bound function in `expr`, along with its free variables.
+	fn collect_functions(
+		&mut self,
+		expr: &Expr,
+		position: usize,
+		free: &mut Vec<(BindingId, BTreeSet<BindingId>)>,
+	) {
+		match &expr.kind {
+			ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Str(_) | ExprKind::Variable(_) => {}
+			ExprKind::Binary { lhs, rhs, .. } => {
+				self.collect_functions(lhs, position, free);
+				self.collect_functions(rhs, position, free);
+			}
+			ExprKind::Let { name, value, next } => {
+				self.collect_function(name, value, position, free);
+				self.collect_functions(next, position, free);
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				self.collect_functions(condition, position, free);
+				self.collect_functions(then, position, free);
+				self.collect_functions(otherwise, position, free);
+			}
+			ExprKind::Tuple(first, second) => {
+				self.collect_functions(first, position, free);
+				self.collect_functions(second, position, free);
+			}
+			ExprKind::Application { callee, args } => {
+				self.collect_functions(callee, position, free);
+				for arg in args {
+					self.collect_functions(arg, position, free);
 				}
+			}
+			ExprKind::Abstraction { body, .. } => self.collect_functions(body, position, free),
+		}
+	}
+
+	/// Registers `name` if its `value` is a function, then the functions in `value`.
+	fn collect_function(
+		&mut self,
+		name: &Ident,
+		value: &Expr,
+		position: usize,
+		free: &mut Vec<(BindingId, BTreeSet<BindingId>)>,
+	) {
+		if let ExprKind::Abstraction { args, body } = &value.kind {
+			let mut vars = BTreeSet::new();
+			free_variables(value, &mut vec![name.id()], &mut vars);
+			free.push((name.id(), vars));
+
+			let strict = tail_calls(body, name.id(), args.len());
+			// tail-recursive functions are loops, caching every iteration won't help
+			let memo = !strict
+				&& !args.is_empty()
+				&& self.memoize
+				&& self.effects.as_ref().is_some_and(|effects| {
+					effects.is_pure(name.id()) && effects.is_recursive(name.id())
+				});
+
+			let function = Function {
+				rule: self.bind_rule(name),
+				captures: vec![],
+				arity: args.len(),
+				position,
+				strict,
+				memo,
+			};
+			self.functions.insert(name.id(), function);
+		}
+
+		self.collect_functions(value, position, free);
+	}
+
+	/// Emits the rule of a lifted function.
+	///
+	/// A strict one first gives back any error it is passed, as its arguments are evaluated
+	/// anyway, so the calls to it only sequence the arguments that may print.
+	fn lift(&mut self, name: &Ident, args: &[Ident], body: Expr) {
+		let function = &self.functions[&name.id()];
+		if function.memo {
+			return self.lift_memoized(name, args, body);
+		}
+
+		let rule = function.rule.clone();
+		let strict = if function.strict { "!" } else { "" };
+		if function.strict {
+			let captures = " _".repeat(function.captures.len());
+			for failed in 0..args.len() {
+				let args = (0..args.len())
+					.map(|i| match i == failed {
+						true => " (STD.error m l)",
+						false => " _",
+					})
+					.collect::<String>();
+				self.rules
+					.push(format!("({rule}{captures}{args}) = (STD.error m l)"));
+			}
+		}
+		let mut params = function
+			.captures
+			.iter()
+			.map(|&capture| format!(" {}", self.capture_name(capture)))
+			.collect::<String>();
+		for arg in args {
+			params.push_str(&format!(" {strict}{}", self.bind(arg)));
+		}
+
+		let outer = self.rule.replace(name.id());
+		let body = self.transpile_expr(body);
+		self.rule = outer;
+		self.rules.push(format!("({rule}{params}) = {body}"));
+	}
+
+	/// Emits the rules of a memoized function.
+	///
+	/// `Rule.memo` takes a cache along with the arguments and evaluates to
+	/// `(Pair value cache)`. It returns the cached value if there is one, otherwise it runs
+	/// the body, passing the cache through its own recursive calls, and caches the result.
+	/// The plain rule starts each call from outside with an empty cache.
+	fn lift_memoized(&mut self, name: &Ident, args: &[Ident], body: Expr) {
+		let function = &self.functions[&name.id()];
+		let rule = function.rule.clone();
+		let captures = function
+			.captures
+			.iter()
+			.map(|&capture| format!(" {}", self.capture_name(capture)))
+			.collect::<String>();
+		let args = args.iter().map(|arg| self.bind(arg)).collect::<Vec<_>>();
+		let params = args.iter().map(|arg| format!(" {arg}")).collect::<String>();
+		let key = args[..args.len() - 1]
+			.iter()
+			.rfold(args[args.len() - 1].clone(), |key, arg| {
+				format!("(Pair {arg} {key})")
+			});
+
+		let outer = self.rule.replace(name.id());
+		let body = self.transpile_memo(body, "memo.cache".to_owned(), name.id());
+		self.rule = outer;
+
+		let memo = format!("{rule}.memo");
+		let lookup = format!("{rule}.memo.lookup");
+		self.rules.extend([
+			format!(
+				"({rule}{captures}{params}) = (STD.memo.value ({memo} STD.memo.empty{captures}{params}))"
+			),
+			format!(
+				"({memo} memo.cache{captures}{params}) = \
+				 ({lookup} (STD.memo.get memo.cache {key}) memo.cache{captures}{params})"
+			),
+			format!(
+				"({lookup} (STD.memo.found memo.value) memo.cache{captures}{params}) = \
+				 (Pair memo.value memo.cache)"
+			),
+			format!(
+				"({lookup} _ memo.cache{captures}{params}) = let memo.result = {body};{SEP}\
+				 let memo.value = (STD.memo.value memo.result);{SEP}\
+				 (Pair memo.value (STD.memo.put (STD.memo.cache memo.result) {key} memo.value))"
 			),
-			Expr::Str(s) => format!("{s:?}"),
-			Expr::Bool(true) => format!("(STD.bool 1)"),
-			Expr::Bool(false) => format!("(STD.bool 0)"),
-			Expr::Variable(v) => self.variables.get(v.val()).unwrap().clone(),
-			Expr::Binary { lhs, op, rhs } => {
-				let lhs = self.transpile_expr(*lhs, depth + 1);
-				let rhs = self.transpile_expr(*rhs, depth + 1);
-				format!("({op} {lhs} {rhs})")
-			}
-			Expr::If {
+		]);
+	}
+
+	/// Like [`Self::transpile_expr`], but evaluates to `(Pair value cache)`, passing `cache`
+	/// through the calls `expr` makes to the memoized function `id`.
+	fn transpile_memo(&mut self, expr: Expr, cache: String, id: BindingId) -> String {
+		let arity = self.functions[&id].arity;
+		if !calls(&expr, id, arity) {
+			return format!("(Pair {} {cache})", self.transpile_expr(expr));
+		}
+
+		let loc = location(expr.span);
+		let mut lets = String::new();
+
+		let result = match expr.kind {
+			// the right side must only run, and update the cache, when it's needed
+			ExprKind::Binary {
+				lhs,
+				op: op @ (BinOp::And | BinOp::Or),
+				rhs,
+			} => {
+				let (lhs, cache) = self.memo_bind(*lhs, cache, id, &mut lets);
+				let rhs = format!(
+					"(STD.memo.check {loc} {})",
+					self.transpile_memo(*rhs, cache.clone(), id)
+				);
+				match op {
+					BinOp::And => format!("(STD.if {loc} {lhs} {rhs} (Pair (STD.bool 0) {cache}))"),
+					_ => format!("(STD.if {loc} {lhs} (Pair (STD.bool 1) {cache}) {rhs})"),
+				}
+			}
+			ExprKind::Binary { lhs, op, rhs } => {
+				let (lhs, cache) = self.memo_bind(*lhs, cache, id, &mut lets);
+				let (rhs, cache) = self.memo_bind(*rhs, cache, id, &mut lets);
+				format!("(Pair ({op} {loc} {lhs} {rhs}) {cache})")
+			}
+			ExprKind::If {
 				condition,
 				then,
 				otherwise,
 			} => {
+				let (condition, cache) = self.memo_bind(*condition, cache, id, &mut lets);
 				format!(
-					"(STD.if ({}) ({}) ({}))",
-					self.transpile_expr(*condition, depth + 1),
-					self.transpile_expr(*then, depth + 1),
-					self.transpile_expr(*otherwise, depth + 1)
+					"(STD.if {loc} {condition} {} {})",
+					self.transpile_memo(*then, cache.clone(), id),
+					self.transpile_memo(*otherwise, cache, id)
 				)
 			}
-			Expr::Let { name, value, next } => {
-				let Ident(name_) = name;
+			ExprKind::Let { name, value, next } => match value.kind {
+				ExprKind::Abstraction { args, body } => {
+					self.lift(&name, &args, *body);
+					self.transpile_memo(*next, cache, id)
+				}
+				kind => {
+					let value = Expr::new(kind, value.span);
+					let forced = self.forced(&value);
+					let (value, cache) = self.memo_bind(value, cache, id, &mut lets);
+					let name = self.bind(&name);
+					lets.push_str(&format!("let {name} = {value};{SEP}"));
+					let next = self.transpile_memo(*next, cache, id);
+					sequence(forced, &name, next)
+				}
+			},
+			ExprKind::Tuple(first, second) => {
+				let (forced_first, forced_second) = (self.forced(&first), self.forced(&second));
+				let (first, cache) = self.memo_bind(*first, cache, id, &mut lets);
+				let (second, cache) = self.memo_bind(*second, cache, id, &mut lets);
+				let fields = vec![(forced_first, first), (forced_second, second)];
+				self.sequenced(fields, |_, fields| {
+					format!("(Pair (Pair {} {}) {cache})", fields[0], fields[1])
+				})
+			}
+			ExprKind::Application { callee, args } => {
+				let mut cache = cache;
+				let mut values = vec![];
+				for arg in args {
+					let forced = self.forced(&arg);
+					let (value, next) = self.memo_bind(arg, cache, id, &mut lets);
+					values.push((forced, value));
+					cache = next;
+				}
+
+				self.sequenced(values, |this, values| match callee.kind {
+					ExprKind::Variable(var) if var.binding == Binding::Local(id) => {
+						let function = &this.functions[&id];
+						let captures = function
+							.captures
+							.iter()
+							.map(|&capture| format!(" {}", this.capture_name(capture)))
+							.collect::<String>();
+						let args = values
+							.iter()
+							.fold(String::new(), |acc, arg| acc + " " + arg);
+						format!("({}.memo {cache}{captures}{args})", function.rule)
+					}
+					kind => {
+						let call = this.application(Expr::new(kind, callee.span), values, loc);
+						format!("(Pair {call} {cache})")
+					}
+				})
+			}
+			_ => unreachable!("`calls` only finds calls outside of closures"),
+		};
+
+		lets + &result
+	}
+
+	/// Evaluates `expr` before the rest of a memoized body, returning its value and the
+	/// cache after it.
+	fn memo_bind(
+		&mut self,
+		expr: Expr,
+		cache: String,
+		id: BindingId,
+		lets: &mut String,
+	) -> (String, String) {
+		if !calls(&expr, id, self.functions[&id].arity) {
+			return (self.transpile_expr(expr), cache);
+		}
 
-				self.variables.insert(name_.clone(), name_.clone());
-				let val = self.transpile_expr(*value, depth + 1);
-				let next = self.transpile_expr(*next, depth);
+		let result = format!("memo.{}", self.memo_vars);
+		self.memo_vars += 1;
+		let value = self.transpile_memo(expr, cache, id);
+		lets.push_str(&format!("let {result} = {value};{SEP}"));
 
-				#[cfg(not(debug_assertions))]
-				return format!("let {name_} = {};{}", val, next);
-				#[cfg(debug_assertions)]
-				return format!("let {name_} = {};\n\t{}", val, next);
+		(
+			format!("(STD.memo.value {result})"),
+			format!("(STD.memo.cache {result})"),
+		)
+	}
+
+	/// Top-level functions become rules, everything else ends up in `Main`.
+	fn toplevel(&mut self, expr: Expr) -> String {
+		let ExprKind::Let { name, value, next } = expr.kind else {
+			return self.transpile_expr(expr);
+		};
+		let name_id = name.id();
+
+		match value.kind {
+			ExprKind::Abstraction { args, body } => {
+				self.lift(&name, &args, *body);
+				self.undefined.remove(&name_id);
+				self.toplevel(*next)
 			}
-			Expr::Application { callee, args } => {
-				let args = args
-					.into_iter()
-					.map(|v| self.transpile_expr(v, depth))
-					.collect::<Box<[String]>>()
-					.join(" ");
-
-				let callee = 'id: {
-					match *callee {
-						Expr::Variable(var) => match self.builtins.get(var.val()) {
-							Some(fn_name) => return format!("({fn_name} {args})"),
-							None => break 'id self.variables.get(var.val()).unwrap().to_string(),
-						},
-						expr => break 'id self.transpile_expr(expr, depth + 1),
-					}
-				};
+			kind => {
+				let value = Expr::new(kind, value.span);
+				let forced = self.forced(&value);
+				let value = self.transpile_expr(value);
+				let name = self.bind(&name);
+				self.undefined.remove(&name_id);
+				let next = sequence(forced, &name, self.toplevel(*next));
 
-				format!("(STD.call ({} {}))", callee, args)
+				format!("let {name} = {value};{SEP}{next}")
 			}
-			Expr::Abstraction { args, body } => {
-				for arg in args.iter() {
-					self.variables.insert(arg.0.clone(), arg.0.clone());
-				}
+		}
+	}
+
+	/// Whether the `let` of `value` has to evaluate it before its body, see [`sequence`].
+	fn forced(&self, value: &Expr) -> bool {
+		self.effect(value) != Effect::None
+	}
+
+	fn effect(&self, expr: &Expr) -> Effect {
+		let effects = self.effects.as_ref().expect("effects are analyzed first");
+		effects.of(expr)
+	}
+
+	/// Calls `callee` with already generated arguments.
+	fn application(&mut self, callee: Expr, args: Vec<String>, loc: u64) -> String {
+		let arity = args.len();
+
+		match callee.kind {
+			ExprKind::Variable(Ident {
+				binding: Binding::Builtin(builtin),
+				..
+			}) => format!("({builtin} {loc} {})", args.join(" ")),
+			ExprKind::Variable(Ident {
+				binding: Binding::Local(id),
+				..
+			}) if self.functions.get(&id).is_some_and(|f| f.arity == arity) => {
+				let ExprKind::Variable(var) = callee.kind else {
+					unreachable!()
+				};
+				self.call_function(&var, args)
+			}
+			_ => {
+				self.call_arities.insert(arity);
+				let callee = self.transpile_expr(callee);
+				let args = args.iter().fold(String::new(), |acc, arg| acc + " " + arg);
+				format!("(STD.call.{arity} {loc} {callee}{args})")
+			}
+		}
+	}
+
+	fn transpile_expr(&mut self, expr: Expr) -> String {
+		let loc = location(expr.span);
 
+		match expr.kind {
+			// see `STD.i32` for how ints are represented
+			ExprKind::Int(i) => format!("(STD.int {})", i as u32),
+			ExprKind::Str(s) => format!("(STD.str {s:?})"),
+			ExprKind::Bool(true) => "(STD.bool 1)".to_owned(),
+			ExprKind::Bool(false) => "(STD.bool 0)".to_owned(),
+			ExprKind::Variable(v) => self.variable(&v),
+			ExprKind::Binary { lhs, op, rhs } => {
+				let lhs = self.transpile_expr(*lhs);
+				let rhs = self.transpile_expr(*rhs);
+				format!("({op} {loc} {lhs} {rhs})")
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
 				format!(
-					"(STD.closure {}({}))",
-					args.iter().fold(String::new(), |mut acc, ident| {
-						acc.push('@');
-						acc.push_str(ident.val());
-						acc
-					}),
-					self.transpile_expr(*body, depth + 1)
+					"(STD.if {loc} {} {} {})",
+					self.transpile_expr(*condition),
+					self.transpile_expr(*then),
+					self.transpile_expr(*otherwise)
 				)
 			}
-			Expr::Tuple(e1, e2) => {
-				let depth = depth + 1;
+			ExprKind::Let { name, value, next } => match value.kind {
+				ExprKind::Abstraction { args, body } => {
+					self.lift(&name, &args, *body);
+					self.transpile_expr(*next)
+				}
+				kind => {
+					let value = Expr::new(kind, value.span);
+					let forced = self.forced(&value);
+					let value = self.transpile_expr(value);
+					let name = self.bind(&name);
+					let next = sequence(forced, &name, self.transpile_expr(*next));
+
+					format!("let {name} = {value};{SEP}{next}")
+				}
+			},
+			ExprKind::Application { callee, args } => {
+				// builtins take their argument strictly already, and so do the rules of loops,
+				// which give back the errors they are passed, see `Codegen::lift`
+				let forced = match callee.kind {
+					ExprKind::Variable(Ident {
+						binding: Binding::Builtin(_),
+						..
+					}) => None,
+					ExprKind::Variable(Ident {
+						binding: Binding::Local(id),
+						..
+					}) if self
+						.functions
+						.get(&id)
+						.is_some_and(|f| f.strict && f.arity == args.len()) =>
+					{
+						Some(Effect::Print)
+					}
+					_ => Some(Effect::Fail),
+				};
+				let args = args
+					.into_iter()
+					.map(|arg| {
+						let force = forced.is_some_and(|forced| self.effect(&arg) >= forced);
+						(force, self.transpile_expr(arg))
+					})
+					.collect();
+				self.sequenced(args, |this, args| this.application(*callee, args, loc))
+			}
+			ExprKind::Abstraction { args, body } => {
+				let params = args
+					.iter()
+					.map(|arg| format!("@{} ", self.bind(arg)))
+					.collect::<String>();
+
 				format!(
-					"(Pair {} {})",
-					self.transpile_expr(*e1, depth),
-					self.transpile_expr(*e2, depth)
+					"(STD.closure {} {params}{})",
+					args.len(),
+					self.transpile_expr(*body)
 				)
 			}
+			ExprKind::Tuple(first, second) => {
+				let fields = [*first, *second]
+					.map(|field| (self.forced(&field), self.transpile_expr(field)));
+				self.sequenced(fields.into(), |_, fields| {
+					format!("(Pair {} {})", fields[0], fields[1])
+				})
+			}
+		}
+	}
+
+	/// What `emit` makes of `values`, evaluating the `forced` ones before, in order.
+	///
+	/// Those are the arguments of calls and the fields of tuples that may print or fail,
+	/// which HVM would skip if nothing needed them, see [`sequence`].
+	fn sequenced(
+		&mut self,
+		values: Vec<(bool, String)>,
+		emit: impl FnOnce(&mut Self, Vec<String>) -> String,
+	) -> String {
+		let mut lets = String::new();
+		let mut forced = vec![];
+		let values = values
+			.into_iter()
+			.map(|(force, value)| {
+				if !force {
+					return value;
+				}
+				let name = format!("seq.{}", self.seq_vars);
+				self.seq_vars += 1;
+				lets.push_str(&format!("let {name} = {value};{SEP}"));
+				forced.push(name.clone());
+				name
+			})
+			.collect();
+
+		let body = emit(self, values);
+		lets + &forced
+			.iter()
+			.rev()
+			.fold(body, |next, name| sequence(true, name, next))
+	}
+}
+
+/// The body `next` of the `let` of `name`, evaluating the value first when it's `forced`.
+///
+/// HVM only reduces what the result needs, so a value that is never used would never
+/// print or fail, and used ones would print in whatever order the result needs them.
+/// `STD.seq` only reduces the value to its head: the parts of it that may print or fail
+/// are sequenced themselves, see [`Codegen::sequenced`].
+fn sequence(forced: bool, name: &str, next: String) -> String {
+	match forced {
+		true => format!("(STD.seq {name} {next})"),
+		false => next,
+	}
+}
+
+/// Whether `expr` calls the function `id` directly in tail position.
+///
+/// The rule of such a function takes its arguments strictly: each iteration then gets
+/// fully computed values instead of thunks pointing at the previous one, so a loop written
+/// as tail recursion doesn't keep its whole history alive. Together with [`apply_rule`]
+/// freeing what each rewrite leaves behind, such loops run in the same few pages of the
+/// heap however many times they iterate.
+///
+/// That is not all the memory they use: HVM queues every call whose strict arguments it
+/// reduces first in a ring of 2^26 words, allocated with the heap, whose cursor only moves
+/// forward. A long loop goes all the way around it, so the process grows by up to 512MB
+/// before it levels off, at about 850MB in all with the default heap.
+pub fn tail_calls(expr: &Expr, id: BindingId, arity: usize) -> bool {
+	match &expr.kind {
+		ExprKind::If {
+			then, otherwise, ..
+		} => tail_calls(then, id, arity) || tail_calls(otherwise, id, arity),
+		ExprKind::Let { next, .. } => tail_calls(next, id, arity),
+		ExprKind::Application { callee, args } => is_call(callee, args, id, arity),
+		_ => false,
+	}
+}
+
+/// Whether `callee(args)` calls the function `id` with all of its `arity` arguments.
+fn is_call(callee: &Expr, args: &[Expr], id: BindingId, arity: usize) -> bool {
+	args.len() == arity
+		&& matches!(&callee.kind, ExprKind::Variable(var) if var.binding == Binding::Local(id))
+}
+
+/// Whether `expr` calls the function `id` directly, outside of the closures it creates.
+fn calls(expr: &Expr, id: BindingId, arity: usize) -> bool {
+	match &expr.kind {
+		ExprKind::Abstraction { .. } => false,
+		ExprKind::Application { callee, args } if is_call(callee, args, id, arity) => true,
+		_ => expr.children().any(|child| calls(child, id, arity)),
+	}
+}
+
+/// A function that calls itself in tail position, which the backends without tail calls
+/// emit as a loop instead, see [`tail_calls`]. `J` is what a backend needs to jump back to
+/// the start of the loop.
+pub struct Loop<J> {
+	id: BindingId,
+	arity: usize,
+	pub jump: J,
+}
+
+impl<J> Loop<J> {
+	/// The loop of the function `name` of `params`, if its `body` calls it in tail position.
+	pub fn of(
+		name: Option<&Ident>,
+		params: &[Ident],
+		body: &Expr,
+		jump: impl FnOnce() -> J,
+	) -> Option<Self> {
+		let name = name.filter(|name| tail_calls(body, name.id(), params.len()))?;
+		Some(Self {
+			id: name.id(),
+			arity: params.len(),
+			jump: jump(),
+		})
+	}
+
+	/// Whether `callee(args)`, in tail position, goes around the loop again.
+	pub fn continues(&self, callee: &Expr, args: &[Expr]) -> bool {
+		is_call(callee, args, self.id, self.arity)
+	}
+}
+
+/// Collects the local variables `expr` uses without binding them itself.
+fn free_variables(expr: &Expr, bound: &mut Vec<BindingId>, free: &mut BTreeSet<BindingId>) {
+	match &expr.kind {
+		ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Str(_) => {}
+		ExprKind::Variable(var) => {
+			if let Binding::Local(id) = var.binding {
+				if !bound.contains(&id) {
+					free.insert(id);
+				}
+			}
+		}
+		ExprKind::Binary { lhs, rhs, .. } => {
+			free_variables(lhs, bound, free);
+			free_variables(rhs, bound, free);
+		}
+		ExprKind::Let { name, value, next } => {
+			bound.push(name.id());
+			free_variables(value, bound, free);
+			free_variables(next, bound, free);
+			bound.pop();
+		}
+		ExprKind::If {
+			condition,
+			then,
+			otherwise,
+		} => {
+			free_variables(condition, bound, free);
+			free_variables(then, bound, free);
+			free_variables(otherwise, bound, free);
+		}
+		ExprKind::Tuple(first, second) => {
+			free_variables(first, bound, free);
+			free_variables(second, bound, free);
+		}
+		ExprKind::Application { callee, args } => {
+			free_variables(callee, bound, free);
+			for arg in args {
+				free_variables(arg, bound, free);
+			}
+		}
+		ExprKind::Abstraction { args, body } => {
+			let depth = bound.len();
+			bound.extend(args.iter().map(Ident::id));
+			free_variables(body, bound, free);
+			bound.truncate(depth);
+		}
+	}
+}
+
+/// Packs a span into the location argument of the STD rules that can fail.
+///
+/// `start` goes in the high 30 bits and `end` in the low ones, so it fits in a U60.
+pub fn location(span: Span) -> u64 {
+	(span.start as u64) << 30 | span.end as u64
+}
+
+/// What programs printed while being normalized, by the address of their heap.
+///
+/// HVM's own `Apps.HVM.print` writes to the standard output from whichever thread reduced
+/// it, so [`normalize`] swaps it for [`print`], which writes here instead.
+static PRINTED: Mutex<Vec<(usize, Vec<u8>)>> = Mutex::new(vec![]);
+
+/// Reduces `(Apps.HVM.print text cont)` like HVM does, but appends the text to [`PRINTED`].
+fn print(ctx: hvm::runtime::ReduceCtx) -> bool {
+	use hvm::runtime::{collect, free, get_loc, link, load_arg, load_ptr};
+
+	let arg = get_loc(ctx.term, 0);
+	if let Some(text) = hvm::language::readback::as_string(ctx.heap, ctx.prog, &[ctx.tid], arg) {
+		let heap = ctx.heap as *const _ as usize;
+		let mut printed = PRINTED.lock().unwrap();
+		if let Some((_, out)) = printed.iter_mut().find(|(key, _)| *key == heap) {
+			out.extend_from_slice(text.as_bytes());
+			out.push(b'\n');
+		}
+	}
+
+	link(ctx.heap, *ctx.host, load_arg(ctx.heap, ctx.term, 1));
+	collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_ptr(ctx.heap, arg));
+	free(ctx.heap, ctx.tid, arg, 2);
+	true
+}
+
+/// Looks up the rule a call to `fid` rewrites with, which [`normalize`] moves past the last
+/// id of the program to call it through [`visit_rule`] and [`apply_rule`].
+fn rule(prog: &hvm::runtime::Program, fid: u64) -> (&VisitObj, &ApplyObj) {
+	match prog.funs.get(&(fid + prog.aris.data.len() as u64)) {
+		Some(hvm::runtime::Function::Interpreted { visit, apply, .. }) => (visit, apply),
+		_ => unreachable!("rule {fid} wasn't moved"),
+	}
+}
+
+fn visit_rule(ctx: hvm::runtime::ReduceCtx) -> bool {
+	let (visit, _) = rule(ctx.prog, hvm::runtime::get_ext(ctx.term));
+	hvm::runtime::fun::visit(ctx, &visit.strict_idx)
+}
+
+/// Rewrites a call like HVM does, then frees the node of the call.
+///
+/// hvm 1.0.9 frees as many words as function id 0 takes arguments instead of the ones of
+/// the rule (`rule/fun.rs`), which is none, so every rewrite would leak its node and a loop
+/// of tail calls would fill the heap, then hang looking for free cells.
+fn apply_rule(ctx: hvm::runtime::ReduceCtx) -> bool {
+	use hvm::runtime::{arity_of, free, get_ext, get_loc, get_tag, load_arg, SUP};
+
+	let fid = get_ext(ctx.term);
+	let (visit, apply) = rule(ctx.prog, fid);
+	// superposing a strict argument reuses the node for one of the two calls
+	let superposes = visit
+		.strict_idx
+		.iter()
+		.any(|&arg| get_tag(load_arg(ctx.heap, ctx.term, arg)) == SUP);
+	let (heap, tid) = (ctx.heap, ctx.tid);
+	let (node, arity) = (get_loc(ctx.term, 0), arity_of(&ctx.prog.aris, ctx.term));
+
+	let applied = hvm::runtime::fun::apply(ctx, fid, visit, apply);
+	if applied && !superposes {
+		free(heap, tid, node, arity);
+	}
+	if applied {
+		reuse(heap, tid);
+	}
+	applied
+}
+
+/// Moves the allocation cursor of thread `tid` back once it went a chunk past where the
+/// thread started allocating, so it reuses the cells freed since.
+///
+/// HVM allocates at a cursor that only moves forward, reusing freed cells once it wraps
+/// around, so even a loop that frees everything it allocates would touch its whole part
+/// of the heap. If the cells after the mark turn out to be still in use, the cursor skips
+/// them on the next allocation, and the mark moves to where it landed instead.
+fn reuse(heap: &hvm::runtime::Heap, tid: usize) {
+	use std::{cell::Cell, sync::atomic::Ordering::Relaxed};
+
+	const CHUNK: u64 = 1 << 12;
+
+	thread_local! {
+		/// Where the cursor goes back to, and whether it just did.
+		static MARK: Cell<(u64, bool)> = const { Cell::new((u64::MAX, false)) };
+	}
+
+	let cursor = &heap.lvar[tid].next;
+	let next = cursor.load(Relaxed);
+	let (mut mark, rewound) = MARK.get();
+	if next < mark || rewound && next - mark > CHUNK / 2 {
+		mark = next;
+	}
+
+	let rewind = next - mark > CHUNK;
+	if rewind {
+		cursor.store(mark, Relaxed);
+	}
+	MARK.set((mark, rewind));
+}
+
+/// Reduces generated code to normal form and reads the result back as HVM text, writing
+/// what it printed to `out`.
+pub fn normalize(code: &str, heap_size: usize, out: &mut Output) -> Result<String, RunError> {
+	let file = hvm::language::syntax::read_file(code).map_err(RunError::Invalid)?;
+	let book = hvm::language::rulebook::gen_rulebook(&file);
+	let mut prog = hvm::runtime::Program::new();
+	prog.add_book(&book);
+	// rules go through `apply_rule`, which finds them `ids` further
+	let ids = prog.aris.data.len() as u64;
+	for fid in 0..ids {
+		if let Some(Some(hvm::runtime::Function::Interpreted { smap, .. })) =
+			prog.funs.data.get(fid as usize)
+		{
+			let smap = smap.clone();
+			let interpreted = prog.funs.data[fid as usize].take().unwrap();
+			prog.funs.insert(fid + ids, interpreted);
+			let compiled = hvm::runtime::Function::Compiled {
+				smap,
+				visit: visit_rule,
+				apply: apply_rule,
+			};
+			prog.funs.insert(fid, compiled);
+		}
+	}
+	prog.funs.insert(
+		hvm::runtime::HVM_PRINT,
+		hvm::runtime::Function::Compiled {
+			smap: vec![false; 2].into_boxed_slice(),
+			visit: |_| false,
+			apply: print,
+		},
+	);
+
+	let tids = hvm::runtime::default_heap_tids();
+	let heap = hvm::runtime::new_heap(heap_size, tids);
+	let tids = hvm::runtime::new_tids(tids);
+
+	let host = 0;
+	let main = *book
+		.name_to_id
+		.get("Main")
+		.ok_or_else(|| RunError::Invalid("missing `Main` rule".to_owned()))?;
+	hvm::runtime::link(&heap, host, hvm::runtime::Fun(main, 0));
+
+	let key = &heap as *const _ as usize;
+	PRINTED.lock().unwrap().push((key, vec![]));
+	hvm::runtime::normalize(&heap, &prog, &tids, host, false);
+	let printed = {
+		let mut printed = PRINTED.lock().unwrap();
+		let index = printed.iter().position(|(heap, _)| *heap == key).unwrap();
+		printed.swap_remove(index).1
+	};
+	out.write_all(&printed)
+		.map_err(|err| RunError::Invalid(err.to_string()))?;
+
+	let term = hvm::language::readback::as_term(&heap, &prog, host);
+
+	hvm::runtime::collect(
+		&heap,
+		&prog.aris,
+		tids[0],
+		hvm::runtime::load_ptr(&heap, host),
+	);
+	hvm::runtime::free(&heap, 0, 0, 1);
+
+	match find_error(&term) {
+		Some(err) => Err(err),
+		None => Ok(term.to_string()),
+	}
+}
+
+/// First `STD.error` in a normal form.
+///
+/// Closure bodies are skipped: HVM normalizes under lambdas, but a closure that fails
+/// when called is fine as long as nobody calls it.
+fn find_error(term: &Term) -> Option<RunError> {
+	match term {
+		Term::Ctr { name, args } if name == "STD.error" => {
+			let message = read_string(&args[0]).unwrap_or_else(|| "unknown error".to_owned());
+			let span = match *args[1] {
+				Term::U6O { numb } => {
+					Span::new((numb >> 30) as usize, (numb & 0x3FFF_FFFF) as usize)
+				}
+				_ => Span::default(),
+			};
+			Some(RunError::Runtime { message, span })
+		}
+		Term::Ctr { name, .. } if name == "STD.closure" => None,
+		Term::Ctr { args, .. } => args.iter().find_map(|arg| find_error(arg)),
+		_ => None,
+	}
+}
+
+/// Decodes a normalized `Data.String`.
+fn read_string(mut term: &Term) -> Option<String> {
+	let mut string = String::new();
+
+	loop {
+		let Term::Ctr { name, args } = term else {
+			return None;
+		};
+
+		match (name.as_str(), args.as_slice()) {
+			("Data.String.nil", []) => break Some(string),
+			("Data.String.cons", [head, tail]) => {
+				let Term::U6O { numb } = **head else {
+					return None;
+				};
+				string.push(char::from_u32(numb as u32)?);
+				term = tail;
+			}
+			_ => return None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{normalize, Codegen, RunError};
+	use crate::{
+		diagnostics::Diagnostics,
+		interp::Interpreter,
+		testing::{hvm, parse},
+	};
+
+	fn compile(src: &str) -> String {
+		let mut diagnostics = Diagnostics::new();
+		let code = Codegen::new(&mut diagnostics).transpile(parse(src));
+		assert!(!diagnostics.has_errors(), "{diagnostics:?}");
+		code
+	}
+
+	fn try_run(src: &str) -> Result<String, RunError> {
+		normalize(&compile(src), 1 << 24, &mut std::io::sink())
+	}
+
+	/// Compiles and runs a program, returning its normal form.
+	fn run(src: &str) -> String {
+		try_run(src).unwrap_or_else(|err| panic!("{err:?}"))
+	}
+
+	#[test]
+	fn codegen_shadowing() {
+		let src = "
+			let f = fn (x) => { let x = x * 10; let y = x + 1; y };
+			let g = fn (y) => { let x = y; f(x) + x };
+			let x = 1;
+			let x = g(x + 1);
+			x
+		";
+		assert_eq!(run(src), "(STD.int 23)");
+	}
+
+	#[test]
+	fn codegen_toplevel_captures() {
+		let src = "
+			let f = fn (n) => n * 2;
+			let x = f(3);
+			let g = fn (y) => x + y;
+			let h = fn (z) => g(z) + x;
+			let apply = fn (k, v) => k(v);
+			(h(1), apply(g, 10))
+		";
+		assert_eq!(run(src), "(Pair (STD.int 13) (STD.int 16))");
+
+		// each use of a function as a value is a closure of its own
+		let src = "
+			let f = fn (x) => x * 2;
+			let apply = fn (k, v) => k(v);
+			let pair = (f, f);
+			(apply(first(pair), 1), apply(second(pair), 2))
+		";
+		assert_eq!(run(src), "(Pair (STD.int 2) (STD.int 4))");
+	}
+
+	#[test]
+	fn codegen_mutual_recursion() {
+		let src = "
+			let is_even = fn (n) => if (n == 0) { true } else { is_odd(n - 1) };
+			let zero = 0;
+			let is_odd = fn (n) => if (n == zero) { false } else { is_even(n - 1) };
+			(is_even(10), is_odd(7))
+		";
+		assert_eq!(run(src), "(Pair (STD.bool 1) (STD.bool 1))");
+	}
+
+	#[test]
+	fn codegen_local_recursion() {
+		let src = "
+			let sum_to = fn (n) => {
+				let step = 1;
+				let loop = fn (i, acc) => if (i > n) { acc } else { loop(i + step, acc + i) };
+				loop(0, 0)
+			};
+			let twice = fn (f) => fn (x) => f(f(x));
+			let add_sum = fn (n) => {
+				let go = fn (k) => if (k == 0) { 0 } else { n + go(k - 1) };
+				go
+			};
+			(sum_to(10), twice(add_sum(3))(2))
+		";
+		assert_eq!(run(src), "(Pair (STD.int 55) (STD.int 18))");
+	}
+
+	#[test]
+	fn codegen_i32_semantics() {
+		let int = |i: i32| format!("(STD.int {})", i as u32);
+
+		let src = "(-7 / 2, (-7 % 2, (7 % -2, -2147483648 / -1)))";
+		let expected = format!(
+			"(Pair {} (Pair {} (Pair {} {})))",
+			int(-3),
+			int(-1),
+			int(1),
+			int(i32::MIN)
+		);
+		assert_eq!(run(src), expected);
+
+		let src = "(2147483647 + 1, (3 - 5, (-3 * 4, \"n = \" + (0 - 42))))";
+		let expected = format!(
+			"(Pair {} (Pair {} (Pair {} (STD.str \"n = -42\"))))",
+			int(i32::MIN),
+			int(-2),
+			int(-12)
+		);
+		assert_eq!(run(src), expected);
+
+		let src = "(-3 < 2, (-1 >= 0, (2 <= -2147483648, -5 > -6)))";
+		let expected = "(Pair (STD.bool 1) (Pair (STD.bool 0) (Pair (STD.bool 0) (STD.bool 1))))";
+		assert_eq!(run(src), expected);
+	}
+
+	#[test]
+	fn codegen_runtime_errors() {
+		let error = |src: &str| match try_run(src) {
+			Err(RunError::Runtime { message, span }) => {
+				(message, src[span.start..span.end].to_owned())
+			}
+			result => panic!("{result:?}"),
+		};
+
+		assert_eq!(
+			error("let f = fn (x) => 10 / x; f(5) + f(0)"),
+			("division by zero".to_owned(), "10 / x".to_owned())
+		);
+		assert_eq!(
+			error("let x = 1 + true; (2, x)"),
+			(
+				"cannot apply `+` to int and bool".to_owned(),
+				"1 + true".to_owned()
+			)
+		);
+		assert_eq!(
+			error("if (\"yes\") { 1 } else { 2 }"),
+			(
+				"expected a bool, found string".to_owned(),
+				"if (\"yes\") { 1 } else { 2 }".to_owned()
+			)
+		);
+		assert_eq!(
+			error("let f = fn (a, b) => a; let g = f; g(1)"),
+			(
+				"closure takes 2 arguments, but 1 were given".to_owned(),
+				"g(1)".to_owned()
+			)
+		);
+
+		// a closure that would fail is fine until it is called
+		assert_eq!(run("let f = fn () => 1 % 0; 2"), "(STD.int 2)");
+	}
+
+	#[test]
+	fn codegen_short_circuit() {
+		let src = "
+			let f = fn (x) => x != 0 && 10 / x > 1;
+			let g = fn (x) => x == 0 || 10 / x > 1;
+			((f(0), f(5)), (g(0), g(20)))
+		";
+		assert_eq!(
+			run(src),
+			"(Pair (Pair (STD.bool 0) (STD.bool 1)) (Pair (STD.bool 1) (STD.bool 0)))"
+		);
+
+		let error = try_run("true && 1").unwrap_err();
+		assert!(
+			matches!(error, RunError::Runtime { message, .. } if message == "expected a bool, found int")
+		);
+	}
+
+	#[test]
+	fn codegen_tail_calls() {
+		let src = "
+			let count = fn (n, acc) => if (n == 0) { acc } else { let m = n - 1; count(m, acc + 1) };
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			(count(100000, 0), fib(10))
+		";
+		let code = compile(src);
+		assert!(code.contains("(Count.0 !n.2 !acc.3) = "), "{code}");
+		assert!(code.contains("(Fib.1 n.5) = "), "{code}");
+
+		assert_eq!(run(src), "(Pair (STD.int 100000) (STD.int 55))");
+
+		// the iterations only fit in a small heap if each one frees what it allocated, so ten
+		// times as many need no more of it. Were cells kept, HVM would fill the heap and hang
+		let count = |n: u32| {
+			let src = format!(
+				"let count = fn (n, acc) => if (n == 0) {{ acc }} else {{ count(n - 1, acc + 1) }}; count({n}, 0)"
+			);
+			let (send, recv) = std::sync::mpsc::channel();
+			std::thread::spawn(move || {
+				send.send(normalize(&compile(&src), 1 << 14, &mut std::io::sink()).unwrap())
+			});
+			recv.recv_timeout(std::time::Duration::from_secs(300))
+				.unwrap_or_else(|_| panic!("counting to {n} filled the heap"))
+		};
+		for n in [100000, 1000000] {
+			assert_eq!(count(n), format!("(STD.int {n})"));
+		}
+	}
+
+	#[test]
+	fn codegen_memoization() {
+		let src = "
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			let combination = fn (n, k) => {
+				let a = k == 0;
+				let b = k == n;
+				if (a || b) { 1 } else { combination(n - 1, k - 1) + combination(n - 1, k) }
+			};
+			let loud = fn (n) => if (n < 2) { print(n) } else { loud(n - 1) + loud(n - 2) };
+			(fib(60), combination(30, 15))
+		";
+		let code = Codegen::new(&mut Diagnostics::new())
+			.memoize(true)
+			.transpile(parse(src));
+		assert!(code.contains("(Fib.0.memo "), "{code}");
+		assert!(code.contains("(Loud.2 n.8) = "), "{code}");
+		assert!(!code.contains("(Loud.2.memo "), "{code}");
+
+		let fib = (0..60)
+			.fold((0i32, 1i32), |(a, b), _| (b, a.wrapping_add(b)))
+			.0;
+		assert_eq!(
+			normalize(&code, 1 << 24, &mut std::io::sink()).unwrap(),
+			format!("(Pair (STD.int {}) (STD.int 155117520))", fib as u32)
+		);
+	}
+
+	#[test]
+	fn codegen_comparisons() {
+		let src = "
+			let nil = \"<nil>\";
+			let xs = (1, (\"b\", nil));
+			((xs == (1, (\"b\", nil)), xs == nil), ((-1 < 1, \"ab\" < \"b\"), ((1, \"b\") >= (1, \"ab\"), true > false)))
+		";
+		assert_eq!(
+			run(src),
+			"(Pair (Pair (STD.bool 1) (STD.bool 0)) (Pair (Pair (STD.bool 1) (STD.bool 1)) (Pair (STD.bool 1) (STD.bool 1))))"
+		);
+
+		let message = |src| match try_run(src) {
+			Err(RunError::Runtime { message, .. }) => message,
+			result => panic!("{result:?}"),
+		};
+		assert_eq!(
+			message("(1, 2) < (1, \"a\")"),
+			"cannot apply `<` to int and string"
+		);
+		assert_eq!(
+			message("(1, fn () => 1) == (1, 2)"),
+			"cannot compare closures"
+		);
+	}
+
+	#[test]
+	fn codegen_print() {
+		assert_eq!(
+			hvm("((1, -2), (true, (\"a\", (fn (x) => x, (0, -2147483648)))))"),
+			"((1, -2), (true, (a, (<#closure>, (0, -2147483648)))))"
+		);
+		assert_eq!(run("let x = print((1, 2)); first(x) + 1"), "(STD.int 2)");
+		assert!(matches!(
+			try_run("print((1, 1 / 0))"),
+			Err(RunError::Runtime { .. })
+		));
+	}
+
+	#[test]
+	fn codegen_effectful_lets() {
+		let printed = |src: &str| {
+			let mut out = vec![];
+			let result = normalize(&compile(src), 1 << 24, &mut out);
+			(result.ok(), String::from_utf8(out).unwrap())
+		};
+
+		assert_eq!(
+			printed("let _ = print(1); let _ = print((2, \"a\")); let x = print(3); 4"),
+			(Some("(STD.int 4)".to_owned()), "1\n(2, a)\n3\n".to_owned())
+		);
+		assert_eq!(
+			printed("let f = fn (x) => { let _ = print(x); x + 1 }; let y = f(1); f(y) + f(0)"),
+			(Some("(STD.int 4)".to_owned()), "1\n2\n0\n".to_owned())
+		);
+		assert_eq!(
+			printed("let _ = print(1); let _ = 1 / 0; print(5)"),
+			(None, "1\n".to_owned())
+		);
+	}
+
+	/// What HVM and the interpreter print and evaluate `src` to, or the error they fail with.
+	fn outcomes(src: &str) -> [(String, Result<String, String>); 2] {
+		let code = Codegen::new(&mut Diagnostics::new())
+			.show(true)
+			.transpile(parse(src));
+		let mut out = vec![];
+		let hvm = match normalize(&code, 1 << 24, &mut out) {
+			Ok(shown) => Ok(shown.trim_matches('"').to_owned()),
+			Err(RunError::Runtime { message, .. }) => Err(message),
+			Err(err) => panic!("{err:?}"),
+		};
+		let hvm = (String::from_utf8(out).unwrap(), hvm);
+
+		let expr = parse(src);
+		let mut out = vec![];
+		let interp = Interpreter::new(&mut out)
+			.run(&expr)
+			.map(|value| value.to_string())
+			.map_err(|err| err.message);
+		[hvm, (String::from_utf8(out).unwrap(), interp)]
+	}
+
+	#[test]
+	fn codegen_effects_agree_with_interp() {
+		for src in [
+			"let f = fn (a, b) => a; print(f(1, print(7)))",
+			"let f = fn (a) => 3; let _ = f(print(8)); 0",
+			"let f = fn (a, b) => a; let g = fn (x) => f(1, 10 / x); print(g(0))",
+			"let z = fn (x) => x; print(first((1, 10 / z(0))))",
+			"let f = fn (n, x) => if (n == 0) { 0 } else { f(n - 1, 1 / 0) }; print(f(3, 1))",
+		] {
+			let [hvm, interp] = outcomes(src);
+			assert_eq!(hvm, interp, "{src}");
+		}
+	}
+
+	#[test]
+	fn codegen_forward_calls_agree_with_interp() {
+		for src in [
+			"let f = fn () => g(); let x = f(); let g = fn () => 1; print(x)",
+			"let f = fn () => g(); let x = f(); let z = 5; let g = fn () => z; print(x)",
+			"let f = fn () => g(); let x = f(); let z = print(5); let g = fn () => z; print(x)",
+			"let f = fn (n) => if (n == 0) { 0 } else { g() }; let x = f(0); let g = fn () => 1; (x, f(1))",
+			"let f = fn () => { let h = fn () => g(); h() }; let x = f(); let g = fn () => 1; x",
+		] {
+			let [hvm, interp] = outcomes(src);
+			assert_eq!(hvm, interp, "{src}");
 		}
 	}
 }
@@ -0,0 +1,570 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+	codegen::{location, Loop},
+	expr::{BinOp, Binding, BindingId, Builtin, Expr, ExprKind, Ident},
+};
+
+/// A C function being emitted.
+#[derive(Default)]
+struct Scope {
+	code: String,
+	indent: usize,
+	locals: HashSet<BindingId>,
+	/// Variables of enclosing functions, in the order the closure keeps them in `env`.
+	captures: Vec<BindingId>,
+	/// The `let` the function is bound to, which it reaches through `self`.
+	current: Option<BindingId>,
+	/// Set when the function is a `for (;;)`, with the variables holding the arguments, which
+	/// its tail calls reassign before `continue`.
+	looping: Option<Loop<Vec<String>>>,
+}
+
+/// Emits a standalone C program, built with the system `cc`.
+///
+/// Ints are kept in the pointer itself and other values are boxed in `struct Value`, in an
+/// arena that is never freed, see `std.c`. Every function is lifted to a C function taking
+/// its closure and an array of arguments. Closures keep the variables of enclosing functions
+/// they use, gathered while their body is emitted, like [`crate::bytecode`] does. The
+/// top-level `let` chain becomes C globals, so top-level functions can call each other in
+/// any order.
+///
+/// Expressions are flattened into statements as in [`crate::codegen_js`], and functions
+/// that call themselves in tail position become loops.
+pub struct CCodegen {
+	scopes: Vec<Scope>,
+	/// C names of the variables.
+	names: HashMap<BindingId, String>,
+	/// Names of the top-level `let`s, for the error of using one before its definition.
+	globals: HashMap<BindingId, String>,
+	/// C function and arity of the functions bound by `let`, which are called directly.
+	functions: HashMap<BindingId, (String, usize)>,
+	prototypes: String,
+	statics: String,
+	definitions: String,
+	temps: usize,
+	lambdas: usize,
+}
+
+const STD: &str = include_str!("../std.c");
+
+pub fn transpile(expr: &Expr) -> String {
+	let mut codegen = CCodegen {
+		scopes: vec![Scope {
+			indent: 1,
+			..Scope::default()
+		}],
+		names: HashMap::new(),
+		globals: HashMap::new(),
+		functions: HashMap::new(),
+		prototypes: String::new(),
+		statics: String::new(),
+		definitions: String::new(),
+		temps: 0,
+		lambdas: 0,
+	};
+
+	let mut next = expr;
+	while let ExprKind::Let {
+		name, next: rest, ..
+	} = &next.kind
+	{
+		let global = format!("g_{}", self::name(name));
+		codegen.statics += &format!("static V {global};\n");
+		codegen.names.insert(name.id(), global);
+		codegen.globals.insert(name.id(), name.name.clone());
+		next = rest;
+	}
+	codegen.collect_functions(expr);
+	codegen.tail(expr);
+
+	let main = codegen.scopes.pop().unwrap().code;
+	format!(
+		"{STD}\n{}\n{}\n{}static V rinha_main(void) {{\n{main}}}\n\n\
+		int main(int argc, char **argv) {{\n\treturn rt_run(rinha_main, argc, argv);\n}}\n",
+		codegen.prototypes, codegen.statics, codegen.definitions
+	)
+}
+
+/// Quotes `str` as a C string literal, escaping every byte that isn't printable ASCII.
+fn string(str: &str) -> String {
+	let mut quoted = String::with_capacity(str.len() + 2);
+	quoted.push('"');
+	for byte in str.bytes() {
+		match byte {
+			// `?` could start a trigraph
+			b'"' | b'\\' | b'?' => {
+				quoted.push('\\');
+				quoted.push(byte as char);
+			}
+			b' '..=b'~' => quoted.push(byte as char),
+			byte => quoted.push_str(&format!("\\{byte:03o}")),
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+fn name(ident: &Ident) -> String {
+	format!("{}_{}", ident.name, ident.id().0)
+}
+
+fn helper(op: &BinOp) -> &'static str {
+	match op {
+		BinOp::Add => "rt_add",
+		BinOp::Sub => "rt_sub",
+		BinOp::Mul => "rt_mul",
+		BinOp::Div => "rt_div",
+		BinOp::Rem => "rt_rem",
+		BinOp::Eq => "rt_eq",
+		BinOp::Neq => "rt_neq",
+		BinOp::Lt => "rt_lt",
+		BinOp::Gt => "rt_gt",
+		BinOp::Lte => "rt_lte",
+		BinOp::Gte => "rt_gte",
+		BinOp::And | BinOp::Or => unreachable!("`{}` short-circuits", op.symbol()),
+	}
+}
+
+/// Arguments of a call, as a compound literal.
+fn arguments(args: &[String]) -> String {
+	match args {
+		[] => "NULL".to_owned(),
+		args => format!("(V[]){{{}}}", args.join(", ")),
+	}
+}
+
+impl CCodegen {
+	fn collect_functions(&mut self, expr: &Expr) {
+		if let ExprKind::Let { name, value, .. } = &expr.kind {
+			if let ExprKind::Abstraction { args, .. } = &value.kind {
+				let code = format!("code_{}", self::name(name));
+				self.functions.insert(name.id(), (code, args.len()));
+			}
+		}
+		expr.children()
+			.for_each(|child| self.collect_functions(child));
+	}
+
+	fn scope(&mut self) -> &mut Scope {
+		self.scopes.last_mut().unwrap()
+	}
+
+	fn line(&mut self, line: &str) {
+		let scope = self.scope();
+		scope.code.push_str(&"\t".repeat(scope.indent));
+		scope.code.push_str(line);
+		scope.code.push('\n');
+	}
+
+	fn temp(&mut self) -> String {
+		self.temps += 1;
+		format!("t{}", self.temps)
+	}
+
+	/// Binds `value` to a fresh variable, so it is computed right away.
+	fn bind(&mut self, value: String) -> String {
+		let temp = self.temp();
+		self.line(&format!("V {temp} = {value};"));
+		temp
+	}
+
+	/// Emits `body` of a block, indented.
+	fn block(&mut self, head: &str, body: impl FnOnce(&mut Self)) {
+		self.line(&format!("{head} {{"));
+		self.scope().indent += 1;
+		body(self);
+		self.scope().indent -= 1;
+		self.line("}");
+	}
+
+	fn local(&mut self, ident: &Ident) -> String {
+		let name = name(ident);
+		self.names.insert(ident.id(), name.clone());
+		self.scope().locals.insert(ident.id());
+		name
+	}
+
+	/// The variable `id`. Functions can run before the top-level `let`s they use are done,
+	/// so they check the globals they read.
+	fn variable(&mut self, id: BindingId, loc: u64) -> String {
+		let nested = self.scopes.len() > 1;
+		let scope = self.scopes.last_mut().unwrap();
+		if scope.locals.contains(&id) {
+			return self.names[&id].clone();
+		}
+		if let Some(name) = self.globals.get(&id) {
+			let global = &self.names[&id];
+			return match nested {
+				true => format!("rt_global({global}, \"{name}\", {loc})"),
+				false => global.clone(),
+			};
+		}
+		if scope.current == Some(id) {
+			return "self".to_owned();
+		}
+
+		let index = match scope.captures.iter().position(|c| *c == id) {
+			Some(index) => index,
+			None => {
+				scope.captures.push(id);
+				scope.captures.len() - 1
+			}
+		};
+		format!("self->env[{index}]")
+	}
+
+	/// Lifts a function of `params` to a C function, as a loop if it calls itself in tail
+	/// position, and creates its closure. With `name`, it is the function of that `let`.
+	fn function(
+		&mut self,
+		name: Option<&Ident>,
+		params: &[Ident],
+		body: &Expr,
+		loc: u64,
+	) -> String {
+		let code = match name {
+			Some(name) => self.functions[&name.id()].0.clone(),
+			None => {
+				self.lambdas += 1;
+				format!("lambda_{}", self.lambdas)
+			}
+		};
+		self.prototypes += &format!("static V {code}(V self, V *args);\n");
+		let looping = Loop::of(name, params, body, || {
+			(0..params.len()).map(|_| self.temp()).collect::<Vec<_>>()
+		});
+
+		self.scopes.push(Scope {
+			indent: 1,
+			current: name.map(Ident::id),
+			..Scope::default()
+		});
+		let names = params
+			.iter()
+			.map(|param| self.local(param))
+			.collect::<Vec<_>>();
+		let args = (0..params.len()).map(|i| format!("args[{i}]"));
+
+		match looping {
+			Some(looping) => {
+				let loop_params = looping.jump.clone();
+				if !params.is_empty() {
+					let bindings = loop_params
+						.iter()
+						.zip(args)
+						.map(|(param, arg)| format!("{param} = {arg}"))
+						.collect::<Vec<_>>();
+					self.line(&format!("V {};", bindings.join(", ")));
+				}
+				self.scope().looping = Some(looping);
+				self.block("for (;;)", |this| {
+					if !names.is_empty() {
+						let bindings = names
+							.iter()
+							.zip(&loop_params)
+							.map(|(name, param)| format!("{name} = {param}"))
+							.collect::<Vec<_>>();
+						this.line(&format!("V {};", bindings.join(", ")));
+					}
+					this.tail(body);
+				});
+			}
+			None => {
+				for (name, arg) in names.iter().zip(args) {
+					self.line(&format!("V {name} = {arg};"));
+				}
+				self.tail(body);
+			}
+		}
+
+		let scope = self.scopes.pop().unwrap();
+		self.definitions += &format!("static V {code}(V self, V *args) {{\n{}}}\n\n", scope.code);
+
+		let closure = self.bind(format!(
+			"rt_closure({code}, {}, {})",
+			params.len(),
+			scope.captures.len()
+		));
+		for (index, capture) in scope.captures.iter().enumerate() {
+			let value = self.variable(*capture, loc);
+			self.line(&format!("{closure}->env[{index}] = {value};"));
+		}
+		closure
+	}
+
+	/// Emits the statements returning the value of `expr`.
+	fn tail(&mut self, expr: &Expr) {
+		let loc = location(expr.span);
+
+		match &expr.kind {
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				let condition = self.atom(condition);
+				self.block(&format!("if (rt_bool({condition}, {loc}))"), |this| {
+					this.tail(then)
+				});
+				self.block("else", |this| this.tail(otherwise));
+			}
+			ExprKind::Let { name, value, next } => {
+				self.let_(name, value);
+				self.tail(next);
+			}
+			ExprKind::Application { callee, args }
+				if self
+					.scopes
+					.last()
+					.unwrap()
+					.looping
+					.as_ref()
+					.is_some_and(|looping| looping.continues(callee, args)) =>
+			{
+				let args = args.iter().map(|arg| self.atom(arg)).collect::<Vec<_>>();
+				let params = self.scope().looping.as_ref().unwrap().jump.clone();
+				for (param, arg) in params.iter().zip(args) {
+					self.line(&format!("{param} = {arg};"));
+				}
+				self.line("continue;");
+			}
+			_ => {
+				let value = self.atom(expr);
+				self.line(&format!("return {value};"));
+			}
+		}
+	}
+
+	fn let_(&mut self, name: &Ident, value: &Expr) {
+		let value = match &value.kind {
+			ExprKind::Abstraction { args, body } => {
+				self.function(Some(name), args, body, location(value.span))
+			}
+			_ => self.atom(value),
+		};
+		match self.globals.contains_key(&name.id()) {
+			true => {
+				let global = &self.names[&name.id()];
+				self.line(&format!("{global} = {value};"));
+			}
+			false => {
+				let local = self.local(name);
+				self.line(&format!("V {local} = {value};"));
+			}
+		}
+	}
+
+	/// Emits the statements computing `expr`, returning an expression of its value which
+	/// has no effects left.
+	fn atom(&mut self, expr: &Expr) -> String {
+		let loc = location(expr.span);
+
+		match &expr.kind {
+			ExprKind::Int(i32::MIN) => "rt_int(INT32_MIN)".to_owned(),
+			ExprKind::Int(i) => format!("rt_int({i})"),
+			ExprKind::Bool(true) => "&rt_true".to_owned(),
+			ExprKind::Bool(false) => "&rt_false".to_owned(),
+			ExprKind::Str(s) => {
+				let temp = self.temp();
+				self.statics += &format!(
+					"static struct Value s_{temp} = {{STR, .s = {{{}, {}}}}};\n",
+					s.len(),
+					string(s)
+				);
+				format!("&s_{temp}")
+			}
+			ExprKind::Variable(var) => match var.binding {
+				Binding::Local(id) => self.variable(id, loc),
+				binding => unreachable!("`{}` is not a local binding: {binding:?}", var.name),
+			},
+			ExprKind::Binary {
+				lhs,
+				op: op @ (BinOp::And | BinOp::Or),
+				rhs,
+			} => {
+				let lhs = self.atom(lhs);
+				let temp = self.bind(lhs);
+				let test = match op {
+					BinOp::And => format!("rt_bool({temp}, {loc})"),
+					_ => format!("!rt_bool({temp}, {loc})"),
+				};
+				self.block(&format!("if ({test})"), |this| {
+					let rhs = this.atom(rhs);
+					this.line(&format!("{temp} = {rhs};"));
+					this.line(&format!("rt_bool({temp}, {loc});"));
+				});
+				temp
+			}
+			ExprKind::Binary { lhs, op, rhs } => {
+				let lhs = self.atom(lhs);
+				let rhs = self.atom(rhs);
+				self.bind(format!("{}({lhs}, {rhs}, {loc})", helper(op)))
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				let condition = self.atom(condition);
+				let temp = self.temp();
+				self.line(&format!("V {temp};"));
+				self.block(&format!("if (rt_bool({condition}, {loc}))"), |this| {
+					let then = this.atom(then);
+					this.line(&format!("{temp} = {then};"));
+				});
+				self.block("else", |this| {
+					let otherwise = this.atom(otherwise);
+					this.line(&format!("{temp} = {otherwise};"));
+				});
+				temp
+			}
+			ExprKind::Let { name, value, next } => {
+				self.let_(name, value);
+				self.atom(next)
+			}
+			ExprKind::Tuple(first, second) => {
+				let first = self.atom(first);
+				let second = self.atom(second);
+				self.bind(format!("rt_tuple({first}, {second})"))
+			}
+			ExprKind::Abstraction { args, body } => self.function(None, args, body, loc),
+			ExprKind::Application { callee, args } => {
+				if let ExprKind::Variable(Ident {
+					binding: Binding::Builtin(builtin),
+					..
+				}) = callee.kind
+				{
+					let args = args.iter().map(|arg| self.atom(arg)).collect::<Vec<_>>();
+					let call = match builtin {
+						Builtin::Print => format!("rt_print({})", args.join(", ")),
+						Builtin::First => format!("rt_first({}, {loc})", args.join(", ")),
+						Builtin::Second => format!("rt_second({}, {loc})", args.join(", ")),
+					};
+					return self.bind(call);
+				}
+
+				let direct = match &callee.kind {
+					ExprKind::Variable(Ident {
+						binding: Binding::Local(id),
+						..
+					}) => self
+						.functions
+						.get(id)
+						.filter(|(_, arity)| *arity == args.len())
+						.map(|(code, _)| (*id, code.clone())),
+					_ => None,
+				};
+				let call = match direct {
+					Some((id, code)) => {
+						let closure = self.variable(id, loc);
+						let args = args.iter().map(|arg| self.atom(arg)).collect::<Vec<_>>();
+						format!("{code}({closure}, {})", arguments(&args))
+					}
+					None => {
+						let callee = self.atom(callee);
+						let args = args.iter().map(|arg| self.atom(arg)).collect::<Vec<_>>();
+						format!(
+							"rt_call({callee}, {}, {}, {loc})",
+							args.len(),
+							arguments(&args)
+						)
+					}
+				};
+				self.bind(call)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		backend::{Backend, Program, RunError, C},
+		diagnostics::Diagnostics,
+		testing::{hvm, parse},
+	};
+
+	/// Builds and runs `src`, or returns `None` if there is no C compiler.
+	fn native(src: &str) -> Option<(String, Result<String, RunError>)> {
+		let mut c = C::default();
+		c.compile(&Program { expr: parse(src) }, &mut Diagnostics::new());
+		let mut out = vec![];
+		match c.run(&mut out) {
+			Err(RunError::Invalid(err)) if err.starts_with("couldn't run") => None,
+			result => Some((String::from_utf8(out).unwrap(), result)),
+		}
+	}
+
+	#[test]
+	fn c_agrees_with_hvm() {
+		let src = "
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			let count = fn (n, acc) => if (n == 0) { acc } else { count(n - 1, acc + 1) };
+			let adder = fn (x) => fn (y) => x + y;
+			let range = fn (lo, hi) => {
+				let go = fn (i, acc) => if (i < lo) { acc } else { go(i - 1, (i, acc)) };
+				go(hi, \"<nil>\")
+			};
+			let p = (1, (\"b\", true));
+			let checks = (p == (1, (\"b\", true)), (p < (1, (\"c\", false)), (true && 1 < 2, false || 3 >= 4)));
+			let math = (2147483647 * 3, (-7 / 2, (7 % -3, adder(\"a\")(-1))));
+			(fib(20), (count(100000, 0), (range(1, 3), (checks, (math, adder)))))
+		";
+		let Some((_, result)) = native(src) else {
+			eprintln!("no C compiler, skipping");
+			return;
+		};
+		assert_eq!(result.unwrap(), hvm(src));
+	}
+
+	/// Loops don't allocate, so a long one runs in the address space a short one needs.
+	#[test]
+	fn c_loops_in_constant_space() {
+		use std::process::Command;
+
+		let src = "let count = fn (n, acc) => if (n == 0) { acc } else { count(n - 1, acc + 1) }; count(30000000, 0)";
+		let mut c = C::default();
+		c.compile(&Program { expr: parse(src) }, &mut Diagnostics::new());
+		let dir = std::env::temp_dir().join(format!("rinha-loop-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("main.c"), c.emit().unwrap()).unwrap();
+		let Ok(build) = Command::new("cc")
+			.args(["-O2", "-pthread", "-o", "main", "main.c"])
+			.current_dir(&dir)
+			.status()
+		else {
+			eprintln!("no C compiler, skipping");
+			return;
+		};
+		assert!(build.success());
+
+		let run = Command::new("sh")
+			.args(["-c", "ulimit -v 262144 && exec ./main --result"])
+			.current_dir(&dir)
+			.output()
+			.unwrap();
+		let _ = std::fs::remove_dir_all(&dir);
+		assert_eq!(String::from_utf8_lossy(&run.stderr), "result: 30000000");
+	}
+
+	#[test]
+	fn c_prints_and_fails() {
+		let src = "let _ = print((1, \"a\\nb\")); let f = fn (x) => 10 / x; f(5) + f(0)";
+		let Some((out, result)) = native(src) else {
+			return;
+		};
+		assert_eq!(out, "(1, a\nb)\n");
+		let Err(RunError::Runtime { message, span }) = result else {
+			panic!("{result:?}")
+		};
+		assert_eq!(message, "division by zero");
+		assert_eq!(&src[span.start..span.end], "10 / x");
+
+		let src = "let f = fn () => g(); let x = f; let y = x(); let g = fn () => 1; y";
+		let Some((_, Err(RunError::Runtime { message, .. }))) = native(src) else {
+			panic!("{src} didn't fail")
+		};
+		assert_eq!(message, "`g` is used before its definition");
+	}
+}
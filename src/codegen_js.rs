@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+
+use crate::{
+	codegen::{location, Loop},
+	expr::{BinOp, Binding, BindingId, Builtin, Expr, ExprKind, Ident},
+};
+
+/// Emits a standalone JavaScript program, run by `node`.
+///
+/// Expressions are flattened into statements that bind every intermediate result to a
+/// `const`, so effects happen in the same order as in Rinha. Ints stay i32s by wrapping
+/// every operation with `| 0`, and the operators go through the checks of `std.js` so
+/// programs fail with the same messages as with HVM. Functions that call themselves in
+/// tail position become loops, as JavaScript has no tail calls.
+///
+/// The variables of `let`s are declared when their function starts, so a function that
+/// runs before a `let` it reads is done gets `undefined` instead of a `ReferenceError`,
+/// and reports it like the interpreter does.
+pub struct JsCodegen {
+	code: String,
+	indent: usize,
+	temps: usize,
+	/// Arities of the functions bound by `let`, which are called directly.
+	functions: HashMap<BindingId, usize>,
+	/// How many functions deep each `let` is, to check the ones read by inner functions.
+	lets: HashMap<BindingId, usize>,
+	depth: usize,
+	/// The function being emitted as a `while (true)`, with the parameters of the JavaScript
+	/// function its tail calls reassign before `continue`.
+	current: Option<Loop<Vec<String>>>,
+}
+
+const STD: &str = include_str!("../std.js");
+
+pub fn transpile(expr: &Expr) -> String {
+	let mut codegen = JsCodegen {
+		code: String::new(),
+		indent: 1,
+		temps: 0,
+		functions: HashMap::new(),
+		lets: HashMap::new(),
+		depth: 0,
+		current: None,
+	};
+	codegen.collect_functions(expr);
+	codegen.declare(expr);
+	codegen.tail(expr);
+
+	// `$run` starts the whole program again on a thread with a bigger stack
+	format!(
+		"function $program() {{\n{STD}\nfunction main() {{\n{}}}\n\n$run(main);\n}}\n\n$program();\n",
+		codegen.code
+	)
+}
+
+/// Quotes `str` as a JavaScript string literal.
+fn string(str: &str) -> String {
+	let mut quoted = String::with_capacity(str.len() + 2);
+	quoted.push('"');
+	for char in str.chars() {
+		match char {
+			'"' => quoted.push_str("\\\""),
+			'\\' => quoted.push_str("\\\\"),
+			'\n' => quoted.push_str("\\n"),
+			'\r' => quoted.push_str("\\r"),
+			'\t' => quoted.push_str("\\t"),
+			char if char.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", char as u32)),
+			char => quoted.push(char),
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+fn name(ident: &Ident) -> String {
+	format!("{}_{}", ident.name, ident.id().0)
+}
+
+fn helper(op: &BinOp) -> &'static str {
+	match op {
+		BinOp::Add => "$add",
+		BinOp::Sub => "$sub",
+		BinOp::Mul => "$mul",
+		BinOp::Div => "$div",
+		BinOp::Rem => "$rem",
+		BinOp::Eq => "$eq",
+		BinOp::Neq => "$neq",
+		BinOp::Lt => "$lt",
+		BinOp::Gt => "$gt",
+		BinOp::Lte => "$lte",
+		BinOp::Gte => "$gte",
+		BinOp::And | BinOp::Or => unreachable!("`{}` short-circuits", op.symbol()),
+	}
+}
+
+impl JsCodegen {
+	fn collect_functions(&mut self, expr: &Expr) {
+		if let ExprKind::Let { name, value, .. } = &expr.kind {
+			if let ExprKind::Abstraction { args, .. } = &value.kind {
+				self.functions.insert(name.id(), args.len());
+			}
+		}
+		expr.children()
+			.for_each(|child| self.collect_functions(child));
+	}
+
+	/// Declares the variables of the `let`s of `body`, outside of the functions it creates.
+	fn declare(&mut self, body: &Expr) {
+		fn collect(expr: &Expr, lets: &mut Vec<Ident>) {
+			match &expr.kind {
+				ExprKind::Abstraction { .. } => return,
+				ExprKind::Let { name, .. } => lets.push(name.clone()),
+				_ => {}
+			}
+			expr.children().for_each(|child| collect(child, lets));
+		}
+
+		let mut lets = vec![];
+		collect(body, &mut lets);
+		if lets.is_empty() {
+			return;
+		}
+
+		for ident in &lets {
+			self.lets.insert(ident.id(), self.depth);
+		}
+		let names = lets.iter().map(name).collect::<Vec<_>>();
+		self.line(&format!("let {};", names.join(", ")));
+	}
+
+	fn line(&mut self, line: &str) {
+		self.code.push_str(&"\t".repeat(self.indent));
+		self.code.push_str(line);
+		self.code.push('\n');
+	}
+
+	fn temp(&mut self) -> String {
+		self.temps += 1;
+		format!("${}", self.temps)
+	}
+
+	/// Binds `value` to a fresh `const`, so it is computed right away.
+	fn bind(&mut self, value: String) -> String {
+		let temp = self.temp();
+		self.line(&format!("const {temp} = {value};"));
+		temp
+	}
+
+	/// Emits `body` of a block, indented.
+	fn block(&mut self, head: &str, body: impl FnOnce(&mut Self)) {
+		self.block_then(head, "}", body);
+	}
+
+	fn block_then(&mut self, head: &str, end: &str, body: impl FnOnce(&mut Self)) {
+		self.line(&format!("{head} {{"));
+		self.indent += 1;
+		body(self);
+		self.indent -= 1;
+		self.line(end);
+	}
+
+	/// Emits a function of `params`, as a loop if it calls itself in tail position. With
+	/// `name`, it is a function expression assigned to the variable of that `let`,
+	/// otherwise an arrow function bound to a temp.
+	fn function(&mut self, name: Option<&Ident>, params: &[Ident], body: &Expr) -> String {
+		let looping = Loop::of(name, params, body, || {
+			(0..params.len()).map(|_| self.temp()).collect::<Vec<_>>()
+		});
+		let names = params.iter().map(self::name).collect::<Vec<_>>();
+		let (value, head) = match name {
+			Some(name) => {
+				let name = self::name(name);
+				(name.clone(), format!("{name} = function {name}"))
+			}
+			None => {
+				let temp = self.temp();
+				(temp.clone(), format!("const {temp} = "))
+			}
+		};
+		let head = |params: &[String]| match name {
+			Some(_) => format!("{head}({})", params.join(", ")),
+			None => format!("{head}({}) =>", params.join(", ")),
+		};
+
+		self.depth += 1;
+		let Some(looping) = looping else {
+			let previous = self.current.take();
+			self.block_then(&head(&names), "};", |this| {
+				this.declare(body);
+				this.tail(body);
+			});
+			self.current = previous;
+			self.depth -= 1;
+			return value;
+		};
+
+		let loop_params = looping.jump.clone();
+		let previous = self.current.replace(looping);
+		self.block_then(&head(&loop_params), "};", |this| {
+			this.block("while (true)", |this| {
+				if !names.is_empty() {
+					let bindings = names
+						.iter()
+						.zip(&loop_params)
+						.map(|(name, param)| format!("{name} = {param}"))
+						.collect::<Vec<_>>();
+					this.line(&format!("const {};", bindings.join(", ")));
+				}
+				this.declare(body);
+				this.tail(body);
+			});
+		});
+		self.current = previous;
+		self.depth -= 1;
+		value
+	}
+
+	/// Emits the statements returning the value of `expr`.
+	fn tail(&mut self, expr: &Expr) {
+		let loc = location(expr.span);
+
+		match &expr.kind {
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				let condition = self.atom(condition);
+				self.block(&format!("if ($bool({condition}, {loc}))"), |this| {
+					this.tail(then)
+				});
+				self.block("else", |this| this.tail(otherwise));
+			}
+			ExprKind::Let { name, value, next } => {
+				self.let_(name, value);
+				self.tail(next);
+			}
+			ExprKind::Application { callee, args }
+				if self
+					.current
+					.as_ref()
+					.is_some_and(|current| current.continues(callee, args)) =>
+			{
+				let args = args.iter().map(|arg| self.atom(arg)).collect::<Vec<_>>();
+				let params = self.current.as_ref().unwrap().jump.clone();
+				for (param, arg) in params.iter().zip(args) {
+					self.line(&format!("{param} = {arg};"));
+				}
+				self.line("continue;");
+			}
+			_ => {
+				let value = self.atom(expr);
+				self.line(&format!("return {value};"));
+			}
+		}
+	}
+
+	fn let_(&mut self, name: &Ident, value: &Expr) {
+		match &value.kind {
+			ExprKind::Abstraction { args, body } => {
+				self.function(Some(name), args, body);
+			}
+			_ => {
+				let value = self.atom(value);
+				self.line(&format!("{} = {value};", self::name(name)));
+			}
+		}
+	}
+
+	/// Emits the statements computing `expr`, returning an expression of its value which
+	/// has no effects left.
+	fn atom(&mut self, expr: &Expr) -> String {
+		let loc = location(expr.span);
+
+		match &expr.kind {
+			ExprKind::Int(i) => i.to_string(),
+			ExprKind::Bool(b) => b.to_string(),
+			ExprKind::Str(s) => string(s),
+			ExprKind::Variable(var) => match self.lets.get(&var.id()) {
+				Some(depth) if *depth < self.depth => {
+					format!("$defined({}, {}, {loc})", name(var), string(&var.name))
+				}
+				_ => name(var),
+			},
+			ExprKind::Binary {
+				lhs,
+				op: op @ (BinOp::And | BinOp::Or),
+				rhs,
+			} => {
+				let lhs = self.atom(lhs);
+				let temp = self.temp();
+				self.line(&format!("let {temp} = $bool({lhs}, {loc});"));
+				let test = match op {
+					BinOp::And => temp.clone(),
+					_ => format!("!{temp}"),
+				};
+				self.block(&format!("if ({test})"), |this| {
+					let rhs = this.atom(rhs);
+					this.line(&format!("{temp} = $bool({rhs}, {loc});"));
+				});
+				temp
+			}
+			ExprKind::Binary { lhs, op, rhs } => {
+				let lhs = self.atom(lhs);
+				let rhs = self.atom(rhs);
+				self.bind(format!("{}({lhs}, {rhs}, {loc})", helper(op)))
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				let condition = self.atom(condition);
+				let temp = self.temp();
+				self.line(&format!("let {temp};"));
+				self.block(&format!("if ($bool({condition}, {loc}))"), |this| {
+					let then = this.atom(then);
+					this.line(&format!("{temp} = {then};"));
+				});
+				self.block("else", |this| {
+					let otherwise = this.atom(otherwise);
+					this.line(&format!("{temp} = {otherwise};"));
+				});
+				temp
+			}
+			ExprKind::Let { name, value, next } => {
+				self.let_(name, value);
+				self.atom(next)
+			}
+			ExprKind::Tuple(first, second) => {
+				let first = self.atom(first);
+				let second = self.atom(second);
+				format!("[{first}, {second}]")
+			}
+			ExprKind::Abstraction { args, body } => self.function(None, args, body),
+			ExprKind::Application { callee, args } => {
+				let builtin = match &callee.kind {
+					ExprKind::Variable(Ident {
+						binding: Binding::Builtin(builtin),
+						..
+					}) => Some(*builtin),
+					_ => None,
+				};
+				let callee_atom = match builtin {
+					Some(_) => String::new(),
+					None => self.atom(callee),
+				};
+				let args = args.iter().map(|arg| self.atom(arg)).collect::<Vec<_>>();
+
+				let call = match (builtin, &callee.kind) {
+					(Some(Builtin::Print), _) => format!("$print({})", args.join(", ")),
+					(Some(Builtin::First), _) => format!("$first({}, {loc})", args.join(", ")),
+					(Some(Builtin::Second), _) => format!("$second({}, {loc})", args.join(", ")),
+					(None, ExprKind::Variable(var)) if matches!(var.binding, Binding::Local(id) if self.functions.get(&id) == Some(&args.len())) =>
+					{
+						format!("{callee_atom}({})", args.join(", "))
+					}
+					(None, _) => format!("$call({callee_atom}, [{}], {loc})", args.join(", ")),
+				};
+				self.bind(call)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		backend::{Backend, Js, Program, RunError},
+		diagnostics::Diagnostics,
+		testing::{hvm, parse},
+	};
+
+	/// Runs `src` with `node`, or returns `None` if it isn't installed.
+	fn node(src: &str) -> Option<(String, Result<String, RunError>)> {
+		let mut js = Js::default();
+		js.compile(&Program { expr: parse(src) }, &mut Diagnostics::new());
+		let mut out = vec![];
+		match js.run(&mut out) {
+			Err(RunError::Invalid(err)) if err.starts_with("couldn't run `node`") => None,
+			result => Some((String::from_utf8(out).unwrap(), result)),
+		}
+	}
+
+	#[test]
+	fn js_agrees_with_hvm() {
+		let programs = [
+			"let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) }; fib(20)",
+			"let count = fn (n, acc) => if (n == 0) { acc } else { count(n - 1, acc + 1) }; count(100000, 0)",
+			"let sum = fn (n) => if (n == 0) { 0 } else { n + sum(n - 1) }; (sum(1000), 2147483647 * 3)",
+			"let adder = fn (x) => fn (y) => x + y; (adder(\"a\")(1), (adder(1)(-2), -7 / 2))",
+			"let p = (1, (\"b\", true)); (p == (1, (\"b\", true)), (p < (1, (\"c\", false)), first(second(p))))",
+			"let f = fn (x) => x; (f, (true && (1 < 2), (false || (3 >= 4), 7 % -3)))",
+		];
+		for src in programs {
+			let Some((_, result)) = node(src) else {
+				eprintln!("`node` isn't installed, skipping");
+				return;
+			};
+			assert_eq!(result.unwrap(), hvm(src), "{src}");
+		}
+	}
+
+	#[test]
+	fn js_prints_and_fails() {
+		let src = "let _ = print((1, \"a\\nb\")); let f = fn (x) => 10 / x; f(5) + f(0)";
+		let Some((out, result)) = node(src) else {
+			return;
+		};
+		assert_eq!(out, "(1, a\nb)\n");
+		let Err(RunError::Runtime { message, span }) = result else {
+			panic!("{result:?}")
+		};
+		assert_eq!(message, "division by zero");
+		assert_eq!(&src[span.start..span.end], "10 / x");
+
+		let src = "let f = fn () => g(); let x = f(); let g = fn () => 1; print(x)";
+		let Some((out, Err(RunError::Runtime { message, span }))) = node(src) else {
+			panic!("{src} didn't fail")
+		};
+		assert_eq!(out, "");
+		assert_eq!(message, "`g` is used before its definition");
+		assert_eq!(&src[span.start..span.end], "g");
+
+		// deeper than node's own stack allows
+		let src = "let sum = fn (n) => if (n == 0) { 0 } else { n + sum(n - 1) }; sum(100000)";
+		let Some((_, result)) = node(src) else {
+			return;
+		};
+		assert_eq!(result.unwrap(), "705082704");
+	}
+}
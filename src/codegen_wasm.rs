@@ -0,0 +1,1451 @@
+use std::{collections::HashMap, fmt::Write as _, io::Write};
+
+use crate::{
+	backend::RunError,
+	codegen::{location, Loop},
+	expr::{BinOp, Binding, BindingId, Builtin, Expr, ExprKind, Ident, Span},
+	wasm::{self, op, Instance, Module, Trap, EMPTY, FUNCREF, I32, I64, PAGE_SIZE},
+};
+
+/// Tags of values, in their upper 32 bits. Ints keep the i32 in the lower ones, other
+/// values a bool or a pointer to linear memory.
+mod tag {
+	pub const INT: i64 = 0;
+	pub const BOOL: i64 = 1;
+	/// The length, then the bytes.
+	pub const STR: i64 = 2;
+	/// Both values.
+	pub const TUPLE: i64 = 3;
+	/// The function, its arity, then the captured values.
+	pub const CLOSURE: i64 = 4;
+	/// A top-level `let` that isn't defined yet.
+	pub const UNDEFINED: i64 = 5;
+}
+
+/// Why the program failed, the first argument of the imported `fail`.
+mod fail {
+	pub const EXPECTED_BOOL: i32 = 0;
+	pub const EXPECTED_TUPLE: i32 = 1;
+	pub const EXPECTED_CLOSURE: i32 = 2;
+	pub const ARITY: i32 = 3;
+	pub const DIVISION_BY_ZERO: i32 = 4;
+	pub const COMPARE_CLOSURES: i32 = 5;
+	pub const UNDEFINED: i32 = 6;
+	/// Plus the index of the operator in [`super::OPERATORS`].
+	pub const MISMATCH: i32 = 16;
+}
+
+const OPERATORS: [BinOp; 11] = [
+	BinOp::Add,
+	BinOp::Sub,
+	BinOp::Mul,
+	BinOp::Div,
+	BinOp::Rem,
+	BinOp::Eq,
+	BinOp::Neq,
+	BinOp::Lt,
+	BinOp::Gt,
+	BinOp::Lte,
+	BinOp::Gte,
+];
+
+/// Indices of the imported functions, then of the runtime functions every module defines.
+mod rt {
+	pub const PRINT: u32 = 0;
+	pub const FAIL: u32 = 1;
+	pub const IMPORTS: u32 = 2;
+
+	pub const ALLOC: u32 = 2;
+	pub const BOOL: u32 = 3;
+	pub const DEFINED: u32 = 4;
+	pub const ADD: u32 = 5;
+	pub const SUB: u32 = 6;
+	pub const MUL: u32 = 7;
+	pub const DIV: u32 = 8;
+	pub const REM: u32 = 9;
+	pub const COMPARE: u32 = 10;
+	pub const EQ: u32 = 11;
+	pub const ORDER: u32 = 12;
+	pub const FIRST: u32 = 13;
+	pub const SECOND: u32 = 14;
+	pub const TUPLE: u32 = 15;
+	pub const CALLEE: u32 = 16;
+	pub const TEXT_LEN: u32 = 17;
+	pub const WRITE: u32 = 18;
+	pub const CONCAT: u32 = 19;
+}
+
+/// Globals every module defines, before the top-level `let`s.
+mod global {
+	/// Where the next allocation goes, an i32.
+	pub const HEAP: u32 = 0;
+	/// The values of different types [`super::rt::COMPARE`] found.
+	pub const LHS: u32 = 1;
+	pub const RHS: u32 = 2;
+	pub const COUNT: u32 = 3;
+}
+
+/// Where the string literals start, so no value points to 0.
+const DATA: usize = 8;
+
+fn tagged(tag: i64, payload: u32) -> i64 {
+	tag << 32 | payload as i64
+}
+
+fn leb(out: &mut Vec<u8>, mut value: u64) {
+	loop {
+		let byte = (value & 0x7f) as u8;
+		value >>= 7;
+		if value == 0 {
+			out.push(byte);
+			return;
+		}
+		out.push(byte | 0x80);
+	}
+}
+
+fn sleb(out: &mut Vec<u8>, mut value: i64) {
+	loop {
+		let byte = (value & 0x7f) as u8;
+		value >>= 7;
+		if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+			out.push(byte);
+			return;
+		}
+		out.push(byte | 0x80);
+	}
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+	leb(out, name.len() as u64);
+	out.extend_from_slice(name.as_bytes());
+}
+
+/// Instructions of a function body.
+#[derive(Default)]
+struct Asm {
+	code: Vec<u8>,
+}
+
+impl Asm {
+	fn op(&mut self, op: u8) -> &mut Self {
+		self.code.push(op);
+		self
+	}
+
+	/// An instruction with an index, of a local, a global, a function or a label.
+	fn imm(&mut self, op: u8, index: u32) -> &mut Self {
+		self.code.push(op);
+		leb(&mut self.code, index as u64);
+		self
+	}
+
+	fn get(&mut self, local: u32) -> &mut Self {
+		self.imm(op::LOCAL_GET, local)
+	}
+
+	fn set(&mut self, local: u32) -> &mut Self {
+		self.imm(op::LOCAL_SET, local)
+	}
+
+	fn tee(&mut self, local: u32) -> &mut Self {
+		self.imm(op::LOCAL_TEE, local)
+	}
+
+	fn call(&mut self, function: u32) -> &mut Self {
+		self.imm(op::CALL, function)
+	}
+
+	fn i32(&mut self, value: i32) -> &mut Self {
+		self.code.push(op::I32_CONST);
+		sleb(&mut self.code, value as i64);
+		self
+	}
+
+	fn i64(&mut self, value: i64) -> &mut Self {
+		self.code.push(op::I64_CONST);
+		sleb(&mut self.code, value);
+		self
+	}
+
+	fn mem(&mut self, op: u8, offset: u32) -> &mut Self {
+		self.code.extend([op, 0]);
+		leb(&mut self.code, offset as u64);
+		self
+	}
+
+	fn block(&mut self, op: u8, ty: u8) -> &mut Self {
+		self.code.extend([op, ty]);
+		self
+	}
+
+	/// Replaces the value on top of the stack by its tag.
+	fn tag(&mut self) -> &mut Self {
+		self.i64(32).op(op::I64_SHR_U).op(op::I32_WRAP_I64)
+	}
+
+	/// Replaces the i32 on top of the stack by a value tagged with `tag`.
+	fn tagged(&mut self, tag: i64) -> &mut Self {
+		self.op(op::I64_EXTEND_I32_U);
+		if tag != 0 {
+			self.i64(tag << 32).op(op::I64_OR);
+		}
+		self
+	}
+
+	/// Fails with `code` if the i32 on top of the stack isn't 0, `x` and `y` being locals.
+	fn fail_if(&mut self, code: i32, x: Option<u32>, y: Option<u32>, loc: u32) -> &mut Self {
+		self.block(op::IF, EMPTY).i32(code);
+		for value in [x, y] {
+			match value {
+				Some(local) => self.get(local),
+				None => self.i64(0),
+			};
+		}
+		self.get(loc).call(rt::FAIL).op(op::UNREACHABLE).op(op::END)
+	}
+
+	/// Pushes whether both values in locals `x` and `y` are ints.
+	fn ints(&mut self, x: u32, y: u32) -> &mut Self {
+		self.get(x)
+			.get(y)
+			.op(op::I64_OR)
+			.i64(32)
+			.op(op::I64_SHR_U)
+			.op(op::I64_EQZ)
+	}
+
+	/// Pushes -1, 0 or 1 comparing the i32s on the stack, computed by `x` and `y`.
+	fn order(&mut self, x: impl Fn(&mut Self), y: impl Fn(&mut Self), signed: bool) -> &mut Self {
+		let (gt, lt) = match signed {
+			true => (op::I32_GT_S, op::I32_LT_S),
+			false => (op::I32_GT_U, op::I32_LT_U),
+		};
+		x(self);
+		y(self);
+		self.op(gt);
+		x(self);
+		y(self);
+		self.op(lt).op(op::I32_SUB)
+	}
+}
+
+struct Function {
+	ty: u32,
+	locals: Vec<u8>,
+	code: Vec<u8>,
+}
+
+/// The parts of a module, encoded by [`Builder::encode`].
+#[derive(Default)]
+struct Builder {
+	types: Vec<(Vec<u8>, Vec<u8>)>,
+	/// Defined functions, by index after the imports, empty while reserved.
+	functions: Vec<Option<Function>>,
+	/// Initial values of the top-level `let`s.
+	globals: usize,
+	data: Vec<u8>,
+	main: u32,
+}
+
+impl Builder {
+	fn ty(&mut self, params: &[u8], results: &[u8]) -> u32 {
+		let ty = (params.to_vec(), results.to_vec());
+		match self.types.iter().position(|t| *t == ty) {
+			Some(index) => index as u32,
+			None => {
+				self.types.push(ty);
+				self.types.len() as u32 - 1
+			}
+		}
+	}
+
+	/// Type of functions of `arity`, which take their closure first.
+	fn closure_type(&mut self, arity: usize) -> u32 {
+		self.ty(&vec![I64; arity + 1], &[I64])
+	}
+
+	fn reserve(&mut self) -> u32 {
+		self.functions.push(None);
+		rt::IMPORTS + self.functions.len() as u32 - 1
+	}
+
+	fn define(&mut self, index: u32, function: Function) {
+		self.functions[(index - rt::IMPORTS) as usize] = Some(function);
+	}
+
+	fn add(&mut self, params: &[u8], results: &[u8], locals: &[u8], asm: &mut Asm) -> u32 {
+		let ty = self.ty(params, results);
+		let index = self.reserve();
+		asm.op(op::END);
+		let code = std::mem::take(&mut asm.code);
+		self.define(
+			index,
+			Function {
+				ty,
+				locals: locals.to_vec(),
+				code,
+			},
+		);
+		index
+	}
+
+	fn heap(&self) -> usize {
+		(DATA + self.data.len() + 7) & !7
+	}
+
+	fn encode(mut self) -> Vec<u8> {
+		let print = self.ty(&[I64], &[]);
+		let fail = self.ty(&[I32, I64, I64, I64], &[]);
+		let functions = rt::IMPORTS as usize + self.functions.len();
+
+		let mut module = b"\0asm\x01\0\0\0".to_vec();
+		let mut section = |id: u8, count: usize, content: Vec<u8>| {
+			let mut body = vec![];
+			leb(&mut body, count as u64);
+			body.extend(content);
+			module.push(id);
+			leb(&mut module, body.len() as u64);
+			module.extend(body);
+		};
+
+		let mut types = vec![];
+		for (params, results) in &self.types {
+			types.push(0x60);
+			name(&mut types, std::str::from_utf8(params).unwrap());
+			name(&mut types, std::str::from_utf8(results).unwrap());
+		}
+		section(1, self.types.len(), types);
+
+		let mut imports = vec![];
+		for (import, ty) in [("print", print), ("fail", fail)] {
+			name(&mut imports, "rinha");
+			name(&mut imports, import);
+			imports.push(0);
+			leb(&mut imports, ty as u64);
+		}
+		section(2, 2, imports);
+
+		let mut types = vec![];
+		for function in &self.functions {
+			leb(&mut types, function.as_ref().unwrap().ty as u64);
+		}
+		section(3, self.functions.len(), types);
+
+		let mut table = vec![FUNCREF, 0];
+		leb(&mut table, functions as u64);
+		section(4, 1, table);
+
+		let mut memory = vec![0];
+		leb(&mut memory, (self.heap() / PAGE_SIZE + 1) as u64);
+		section(5, 1, memory);
+
+		let mut globals = vec![I32, 1, op::I32_CONST];
+		sleb(&mut globals, self.heap() as i64);
+		globals.push(op::END);
+		for _ in 1..global::COUNT as usize + self.globals {
+			globals.extend([I64, 1, op::I64_CONST]);
+			sleb(&mut globals, tagged(tag::UNDEFINED, 0));
+			globals.push(op::END);
+		}
+		section(6, global::COUNT as usize + self.globals, globals);
+
+		let mut exports = vec![];
+		name(&mut exports, "main");
+		exports.push(0);
+		leb(&mut exports, self.main as u64);
+		name(&mut exports, "memory");
+		exports.extend([2, 0]);
+		section(7, 2, exports);
+
+		let mut elements = vec![0, op::I32_CONST, 0, op::END];
+		leb(&mut elements, functions as u64);
+		for function in 0..functions {
+			leb(&mut elements, function as u64);
+		}
+		section(9, 1, elements);
+
+		let mut code = vec![];
+		for function in self.functions {
+			let function = function.unwrap();
+			let mut body = vec![];
+			leb(&mut body, function.locals.len() as u64);
+			for local in function.locals {
+				body.extend([1, local]);
+			}
+			body.extend(function.code);
+			leb(&mut code, body.len() as u64);
+			code.extend(body);
+		}
+		section(10, functions - rt::IMPORTS as usize, code);
+
+		let mut data = vec![0, op::I32_CONST];
+		sleb(&mut data, DATA as i64);
+		data.push(op::END);
+		leb(&mut data, self.data.len() as u64);
+		data.extend(self.data);
+		section(11, 1, data);
+
+		module
+	}
+}
+
+/// Defines the runtime functions, at the indices of [`rt`].
+fn runtime(module: &mut Builder) {
+	let asm = &mut Asm::default();
+
+	// alloc(size) -> pointer, growing the memory when needed
+	asm.get(0)
+		.imm(op::GLOBAL_GET, global::HEAP)
+		.tee(1)
+		.op(op::I32_ADD)
+		.i32(7)
+		.op(op::I32_ADD)
+		.i32(-8)
+		.op(op::I32_AND)
+		.imm(op::GLOBAL_SET, global::HEAP)
+		.block(op::BLOCK, EMPTY)
+		.imm(op::GLOBAL_GET, global::HEAP)
+		.imm(op::MEMORY_SIZE, 0)
+		.i32(16)
+		.op(op::I32_SHL)
+		.op(op::I32_LE_U)
+		.imm(op::BR_IF, 0)
+		.imm(op::GLOBAL_GET, global::HEAP)
+		.imm(op::MEMORY_SIZE, 0)
+		.i32(16)
+		.op(op::I32_SHL)
+		.op(op::I32_SUB)
+		.i32(PAGE_SIZE as i32 - 1)
+		.op(op::I32_ADD)
+		.i32(16)
+		.op(op::I32_SHR_U)
+		.imm(op::MEMORY_GROW, 0)
+		.i32(-1)
+		.op(op::I32_NE)
+		.imm(op::BR_IF, 0)
+		.op(op::UNREACHABLE)
+		.op(op::END)
+		.get(1);
+	module.add(&[I32], &[I32], &[I32], asm);
+
+	// bool(x, loc) -> i32
+	asm.get(0)
+		.tag()
+		.i32(tag::BOOL as i32)
+		.op(op::I32_NE)
+		.fail_if(fail::EXPECTED_BOOL, Some(0), None, 1)
+		.get(0)
+		.op(op::I32_WRAP_I64);
+	module.add(&[I64, I64], &[I32], &[], asm);
+
+	// defined(x, name, loc) -> x
+	asm.get(0)
+		.tag()
+		.i32(tag::UNDEFINED as i32)
+		.op(op::I32_EQ)
+		.fail_if(fail::UNDEFINED, Some(1), None, 2)
+		.get(0);
+	module.add(&[I64, I64, I64], &[I64], &[], asm);
+
+	// add(x, y, loc), of ints or concatenating strings and ints
+	asm.ints(0, 1)
+		.block(op::IF, EMPTY)
+		.get(0)
+		.op(op::I32_WRAP_I64)
+		.get(1)
+		.op(op::I32_WRAP_I64)
+		.op(op::I32_ADD)
+		.tagged(tag::INT)
+		.op(op::RETURN)
+		.op(op::END);
+	for x in [0, 1] {
+		// ints and strings are the only tags `| 2` keeps 2
+		asm.get(x).tag().i32(2).op(op::I32_OR).i32(2).op(op::I32_NE);
+	}
+	asm.op(op::I32_OR)
+		.fail_if(fail::MISMATCH, Some(0), Some(1), 2)
+		.get(0)
+		.get(1)
+		.call(rt::CONCAT);
+	module.add(&[I64, I64, I64], &[I64], &[], asm);
+
+	// sub, mul, div and rem (x, y, loc) of ints
+	for (index, op) in [
+		(1, op::I32_SUB),
+		(2, op::I32_MUL),
+		(3, op::I32_DIV_S),
+		(4, op::I32_REM_S),
+	] {
+		asm.ints(0, 1)
+			.op(op::I32_EQZ)
+			.fail_if(fail::MISMATCH + index, Some(0), Some(1), 2);
+		if op == op::I32_DIV_S || op == op::I32_REM_S {
+			asm.get(1)
+				.op(op::I64_EQZ)
+				.fail_if(fail::DIVISION_BY_ZERO, None, None, 2);
+			// wasm traps on i32::MIN / -1, where Rinha wraps
+			asm.get(1)
+				.op(op::I32_WRAP_I64)
+				.i32(-1)
+				.op(op::I32_EQ)
+				.block(op::IF, EMPTY)
+				.i32(0);
+			if op == op::I32_DIV_S {
+				asm.get(0).op(op::I32_WRAP_I64).op(op::I32_SUB);
+			}
+			asm.tagged(tag::INT).op(op::RETURN).op(op::END);
+		}
+		asm.get(0)
+			.op(op::I32_WRAP_I64)
+			.get(1)
+			.op(op::I32_WRAP_I64)
+			.op(op)
+			.tagged(tag::INT);
+		module.add(&[I64, I64, I64], &[I64], &[], asm);
+	}
+
+	// compare(x, y, loc) -> -1, 0 or 1, or 2 with the values of different types in globals
+	let (tx, ty, px, py, lx, ly, i, cx, cy) = (3, 4, 5, 6, 7, 8, 9, 10, 11);
+	asm.get(0)
+		.tag()
+		.tee(tx)
+		.i32(tag::CLOSURE as i32)
+		.op(op::I32_EQ)
+		.get(1)
+		.tag()
+		.tee(ty)
+		.i32(tag::CLOSURE as i32)
+		.op(op::I32_EQ)
+		.op(op::I32_OR)
+		.fail_if(fail::COMPARE_CLOSURES, None, None, 2)
+		.get(tx)
+		.get(ty)
+		.op(op::I32_NE)
+		.block(op::IF, EMPTY)
+		.get(0)
+		.imm(op::GLOBAL_SET, global::LHS)
+		.get(1)
+		.imm(op::GLOBAL_SET, global::RHS)
+		.i32(2)
+		.op(op::RETURN)
+		.op(op::END)
+		.get(tx)
+		.i32(tag::STR as i32)
+		.op(op::I32_LT_U)
+		.block(op::IF, EMPTY)
+		.order(
+			|asm| {
+				asm.get(0).op(op::I32_WRAP_I64);
+			},
+			|asm| {
+				asm.get(1).op(op::I32_WRAP_I64);
+			},
+			true,
+		)
+		.op(op::RETURN)
+		.op(op::END)
+		.get(0)
+		.op(op::I32_WRAP_I64)
+		.set(px)
+		.get(1)
+		.op(op::I32_WRAP_I64)
+		.set(py)
+		.get(tx)
+		.i32(tag::TUPLE as i32)
+		.op(op::I32_EQ)
+		.block(op::IF, EMPTY);
+	for offset in [0, 8] {
+		asm.get(px)
+			.mem(op::I64_LOAD, offset)
+			.get(py)
+			.mem(op::I64_LOAD, offset)
+			.get(2)
+			.call(rt::COMPARE)
+			.tee(cx)
+			.block(op::IF, EMPTY)
+			.get(cx)
+			.op(op::RETURN)
+			.op(op::END);
+	}
+	asm.i32(0)
+		.op(op::RETURN)
+		.op(op::END)
+		// strings, byte by byte up to the shortest length, then by length
+		.get(px)
+		.mem(op::I32_LOAD, 0)
+		.set(lx)
+		.get(py)
+		.mem(op::I32_LOAD, 0)
+		.set(ly)
+		.block(op::BLOCK, EMPTY)
+		.block(op::LOOP, EMPTY)
+		.get(i)
+		.get(lx)
+		.op(op::I32_GE_U)
+		.get(i)
+		.get(ly)
+		.op(op::I32_GE_U)
+		.op(op::I32_OR)
+		.imm(op::BR_IF, 1)
+		.get(px)
+		.get(i)
+		.op(op::I32_ADD)
+		.mem(op::I32_LOAD8_U, 4)
+		.set(cx)
+		.get(py)
+		.get(i)
+		.op(op::I32_ADD)
+		.mem(op::I32_LOAD8_U, 4)
+		.set(cy)
+		.get(cx)
+		.get(cy)
+		.op(op::I32_NE)
+		.block(op::IF, EMPTY)
+		.order(
+			|asm| {
+				asm.get(cx);
+			},
+			|asm| {
+				asm.get(cy);
+			},
+			false,
+		)
+		.op(op::RETURN)
+		.op(op::END)
+		.get(i)
+		.i32(1)
+		.op(op::I32_ADD)
+		.set(i)
+		.imm(op::BR, 0)
+		.op(op::END)
+		.op(op::END)
+		.order(
+			|asm| {
+				asm.get(lx);
+			},
+			|asm| {
+				asm.get(ly);
+			},
+			false,
+		);
+	module.add(&[I64, I64, I64], &[I32], &[I32; 9], asm);
+
+	// eq(x, y, loc) -> bool
+	asm.ints(0, 1)
+		.block(op::IF, I32)
+		.get(0)
+		.get(1)
+		.op(op::I64_EQ)
+		.op(op::ELSE)
+		.get(0)
+		.get(1)
+		.get(2)
+		.call(rt::COMPARE)
+		.op(op::I32_EQZ)
+		.op(op::END)
+		.tagged(tag::BOOL);
+	module.add(&[I64, I64, I64], &[I64], &[], asm);
+
+	// order(operator, x, y, loc) -> -1, 0 or 1
+	asm.ints(1, 2)
+		.block(op::IF, EMPTY)
+		.order(
+			|asm| {
+				asm.get(1).op(op::I32_WRAP_I64);
+			},
+			|asm| {
+				asm.get(2).op(op::I32_WRAP_I64);
+			},
+			true,
+		)
+		.op(op::RETURN)
+		.op(op::END)
+		.get(1)
+		.get(2)
+		.get(3)
+		.call(rt::COMPARE)
+		.tee(4)
+		.i32(2)
+		.op(op::I32_EQ)
+		.block(op::IF, EMPTY)
+		.i32(fail::MISMATCH)
+		.get(0)
+		.op(op::I32_ADD)
+		.imm(op::GLOBAL_GET, global::LHS)
+		.imm(op::GLOBAL_GET, global::RHS)
+		.get(3)
+		.call(rt::FAIL)
+		.op(op::UNREACHABLE)
+		.op(op::END)
+		.get(4);
+	module.add(&[I32, I64, I64, I64], &[I32], &[I32], asm);
+
+	// first and second (x, loc)
+	for offset in [0, 8] {
+		asm.get(0)
+			.tag()
+			.i32(tag::TUPLE as i32)
+			.op(op::I32_NE)
+			.fail_if(fail::EXPECTED_TUPLE, Some(0), None, 1)
+			.get(0)
+			.op(op::I32_WRAP_I64)
+			.mem(op::I64_LOAD, offset);
+		module.add(&[I64, I64], &[I64], &[], asm);
+	}
+
+	// tuple(first, second)
+	asm.i32(16)
+		.call(rt::ALLOC)
+		.tee(2)
+		.get(0)
+		.mem(op::I64_STORE, 0)
+		.get(2)
+		.get(1)
+		.mem(op::I64_STORE, 8)
+		.get(2)
+		.tagged(tag::TUPLE);
+	module.add(&[I64, I64], &[I64], &[I32], asm);
+
+	// callee(f, argc, loc) -> the function to call, if `f` is a closure taking `argc` arguments
+	asm.get(0)
+		.tag()
+		.i32(tag::CLOSURE as i32)
+		.op(op::I32_NE)
+		.fail_if(fail::EXPECTED_CLOSURE, Some(0), None, 2)
+		.get(0)
+		.op(op::I32_WRAP_I64)
+		.tee(3)
+		.mem(op::I32_LOAD, 4)
+		.get(1)
+		.op(op::I32_NE)
+		.block(op::IF, EMPTY)
+		.i32(fail::ARITY)
+		.get(3)
+		.mem(op::I32_LOAD, 4)
+		.op(op::I64_EXTEND_I32_U)
+		.get(1)
+		.op(op::I64_EXTEND_I32_U)
+		.get(2)
+		.call(rt::FAIL)
+		.op(op::UNREACHABLE)
+		.op(op::END)
+		.get(3)
+		.mem(op::I32_LOAD, 0);
+	module.add(&[I64, I32, I64], &[I32], &[I32], asm);
+
+	// text_len(x) -> length of the int or string `x` as text
+	let (n, len) = (1, 2);
+	asm.get(0)
+		.tag()
+		.op(op::I32_EQZ)
+		.block(op::IF, I32)
+		.get(0)
+		.op(op::I32_WRAP_I64)
+		.op(op::I64_EXTEND_I32_S)
+		.tee(n)
+		.i64(0)
+		.op(op::I64_LT_S)
+		.tee(len)
+		.block(op::IF, EMPTY)
+		.i64(0)
+		.get(n)
+		.op(op::I64_SUB)
+		.set(n)
+		.op(op::END)
+		.block(op::LOOP, EMPTY)
+		.get(len)
+		.i32(1)
+		.op(op::I32_ADD)
+		.set(len)
+		.get(n)
+		.i64(10)
+		.op(op::I64_DIV_U)
+		.tee(n)
+		.op(op::I64_EQZ)
+		.op(op::I32_EQZ)
+		.imm(op::BR_IF, 0)
+		.op(op::END)
+		.get(len)
+		.op(op::ELSE)
+		.get(0)
+		.op(op::I32_WRAP_I64)
+		.mem(op::I32_LOAD, 0)
+		.op(op::END);
+	module.add(&[I64], &[I32], &[I64, I32], asm);
+
+	// write(dst, x) -> the end of the text of the int or string `x`, written at `dst`
+	let (n, len, src, i) = (2, 3, 4, 5);
+	asm.get(1)
+		.tag()
+		.op(op::I32_EQZ)
+		.block(op::IF, I32)
+		.get(1)
+		.call(rt::TEXT_LEN)
+		.set(len)
+		.get(1)
+		.op(op::I32_WRAP_I64)
+		.op(op::I64_EXTEND_I32_S)
+		.tee(n)
+		.i64(0)
+		.op(op::I64_LT_S)
+		.block(op::IF, EMPTY)
+		.get(0)
+		.i32(b'-' as i32)
+		.mem(op::I32_STORE8, 0)
+		.i64(0)
+		.get(n)
+		.op(op::I64_SUB)
+		.set(n)
+		.op(op::END)
+		// digits are written from the end
+		.get(0)
+		.get(len)
+		.op(op::I32_ADD)
+		.tee(i)
+		.block(op::LOOP, EMPTY)
+		.get(i)
+		.i32(1)
+		.op(op::I32_SUB)
+		.tee(i)
+		.get(n)
+		.i64(10)
+		.op(op::I64_REM_U)
+		.op(op::I32_WRAP_I64)
+		.i32(b'0' as i32)
+		.op(op::I32_ADD)
+		.mem(op::I32_STORE8, 0)
+		.get(n)
+		.i64(10)
+		.op(op::I64_DIV_U)
+		.tee(n)
+		.op(op::I64_EQZ)
+		.op(op::I32_EQZ)
+		.imm(op::BR_IF, 0)
+		.op(op::END)
+		.op(op::ELSE)
+		.get(1)
+		.op(op::I32_WRAP_I64)
+		.tee(src)
+		.mem(op::I32_LOAD, 0)
+		.set(len)
+		.block(op::BLOCK, EMPTY)
+		.block(op::LOOP, EMPTY)
+		.get(i)
+		.get(len)
+		.op(op::I32_GE_U)
+		.imm(op::BR_IF, 1)
+		.get(0)
+		.get(i)
+		.op(op::I32_ADD)
+		.get(src)
+		.get(i)
+		.op(op::I32_ADD)
+		.mem(op::I32_LOAD8_U, 4)
+		.mem(op::I32_STORE8, 0)
+		.get(i)
+		.i32(1)
+		.op(op::I32_ADD)
+		.set(i)
+		.imm(op::BR, 0)
+		.op(op::END)
+		.op(op::END)
+		.get(0)
+		.get(len)
+		.op(op::I32_ADD)
+		.op(op::END);
+	module.add(&[I32, I64], &[I32], &[I64, I32, I32, I32], asm);
+
+	// concat(x, y) -> string
+	let (len, ptr) = (2, 3);
+	asm.get(0)
+		.call(rt::TEXT_LEN)
+		.get(1)
+		.call(rt::TEXT_LEN)
+		.op(op::I32_ADD)
+		.tee(len)
+		.i32(4)
+		.op(op::I32_ADD)
+		.call(rt::ALLOC)
+		.tee(ptr)
+		.get(len)
+		.mem(op::I32_STORE, 0)
+		.get(ptr)
+		.i32(4)
+		.op(op::I32_ADD)
+		.get(0)
+		.call(rt::WRITE)
+		.get(1)
+		.call(rt::WRITE)
+		.op(op::DROP)
+		.get(ptr)
+		.tagged(tag::STR);
+	module.add(&[I64, I64], &[I64], &[I32, I32], asm);
+}
+
+/// A function being emitted.
+#[derive(Default)]
+struct Scope {
+	asm: Asm,
+	/// Parameters of the function, its closure included.
+	params: u32,
+	locals: Vec<u8>,
+	slots: HashMap<BindingId, u32>,
+	/// Variables of enclosing functions, in the order the closure keeps them.
+	captures: Vec<BindingId>,
+	/// The `let` the function is bound to, which it reaches through its closure.
+	current: Option<BindingId>,
+	/// Blocks open, for the depth of branches.
+	depth: u32,
+	/// Set when the body is wrapped in a `loop` block, with the depth of that block, which a
+	/// tail call branches back to once it stored the arguments in the parameters.
+	looping: Option<Loop<u32>>,
+}
+
+/// Emits a WebAssembly module of a program, which exports its `main` and memory, and
+/// imports `print` and `fail` from the host, see [`run`].
+///
+/// Values are i64s tagged in their upper half, see [`tag`], so i32 arithmetic runs
+/// natively on their lower half. Tuples, closures and strings are allocated in linear
+/// memory, which is never freed. Closures keep the index of their function in the table
+/// and the variables of enclosing functions they use, gathered while their body is emitted
+/// like [`crate::bytecode`] does. The top-level `let` chain becomes globals, and functions
+/// that call themselves in tail position become loops.
+pub struct WasmCodegen {
+	module: Builder,
+	scopes: Vec<Scope>,
+	/// Global and name of the top-level `let`s.
+	globals: HashMap<BindingId, (u32, i64)>,
+	/// Function and arity of the functions bound by `let`, which are called directly.
+	functions: HashMap<BindingId, (u32, usize)>,
+	strings: HashMap<String, i64>,
+}
+
+pub fn transpile(expr: &Expr) -> Vec<u8> {
+	let mut module = Builder::default();
+	runtime(&mut module);
+
+	let mut codegen = WasmCodegen {
+		module,
+		scopes: vec![Scope::default()],
+		globals: HashMap::new(),
+		functions: HashMap::new(),
+		strings: HashMap::new(),
+	};
+	codegen.module.main = codegen.module.reserve();
+
+	let mut next = expr;
+	while let ExprKind::Let {
+		name, next: rest, ..
+	} = &next.kind
+	{
+		let global = global::COUNT + codegen.globals.len() as u32;
+		let name_value = codegen.string(&name.name);
+		codegen.globals.insert(name.id(), (global, name_value));
+		next = rest;
+	}
+	codegen.module.globals = codegen.globals.len();
+	codegen.collect_functions(expr);
+
+	codegen.expr(expr, true);
+	let mut scope = codegen.scopes.pop().unwrap();
+	scope.asm.op(op::END);
+	let main = Function {
+		ty: codegen.module.ty(&[], &[I64]),
+		locals: scope.locals,
+		code: scope.asm.code,
+	};
+	codegen.module.define(codegen.module.main, main);
+
+	codegen.module.encode()
+}
+
+impl WasmCodegen {
+	fn collect_functions(&mut self, expr: &Expr) {
+		if let ExprKind::Let { name, value, .. } = &expr.kind {
+			if let ExprKind::Abstraction { args, .. } = &value.kind {
+				let function = self.module.reserve();
+				self.functions.insert(name.id(), (function, args.len()));
+			}
+		}
+		expr.children()
+			.for_each(|child| self.collect_functions(child));
+	}
+
+	fn scope(&mut self) -> &mut Scope {
+		self.scopes.last_mut().unwrap()
+	}
+
+	fn asm(&mut self) -> &mut Asm {
+		&mut self.scope().asm
+	}
+
+	fn local(&mut self, ty: u8) -> u32 {
+		let scope = self.scope();
+		scope.locals.push(ty);
+		scope.params + scope.locals.len() as u32 - 1
+	}
+
+	/// A string literal, laid out in the data segment.
+	fn string(&mut self, str: &str) -> i64 {
+		if let Some(value) = self.strings.get(str) {
+			return *value;
+		}
+		let data = &mut self.module.data;
+		data.resize((data.len() + 3) & !3, 0);
+		let ptr = DATA + data.len();
+		data.extend((str.len() as u32).to_le_bytes());
+		data.extend(str.as_bytes());
+
+		let value = tagged(tag::STR, ptr as u32);
+		self.strings.insert(str.to_owned(), value);
+		value
+	}
+
+	/// Pushes the variable `id`. Functions can run before the top-level `let`s they use are
+	/// done, so they check the globals they read.
+	fn variable(&mut self, id: BindingId, loc: i64) {
+		let nested = self.scopes.len() > 1;
+		let global = self.globals.get(&id).copied();
+		let scope = self.scope();
+
+		if let Some(slot) = scope.slots.get(&id) {
+			let slot = *slot;
+			scope.asm.get(slot);
+		} else if let Some((global, name)) = global {
+			scope.asm.imm(op::GLOBAL_GET, global);
+			if nested {
+				scope.asm.i64(name).i64(loc).call(rt::DEFINED);
+			}
+		} else if scope.current == Some(id) {
+			scope.asm.get(0);
+		} else {
+			let index = match scope.captures.iter().position(|c| *c == id) {
+				Some(index) => index,
+				None => {
+					scope.captures.push(id);
+					scope.captures.len() - 1
+				}
+			};
+			scope
+				.asm
+				.get(0)
+				.op(op::I32_WRAP_I64)
+				.mem(op::I64_LOAD, 8 + 8 * index as u32);
+		}
+	}
+
+	/// Emits a function of `params`, as a loop if it calls itself in tail position, and
+	/// pushes its closure. With `name`, it is the function of that `let`.
+	fn function(&mut self, name: Option<&Ident>, params: &[Ident], body: &Expr, loc: i64) {
+		let function = match name {
+			Some(name) => self.functions[&name.id()].0,
+			None => self.module.reserve(),
+		};
+		let looping = Loop::of(name, params, body, || 1);
+		let loops = looping.is_some();
+
+		let mut scope = Scope {
+			params: params.len() as u32 + 1,
+			current: name.map(Ident::id),
+			..Scope::default()
+		};
+		for (slot, param) in params.iter().enumerate() {
+			scope.slots.insert(param.id(), slot as u32 + 1);
+		}
+		if let Some(looping) = looping {
+			scope.asm.block(op::LOOP, I64);
+			scope.depth = looping.jump;
+			scope.looping = Some(looping);
+		}
+		self.scopes.push(scope);
+		self.expr(body, true);
+		if loops {
+			self.asm().op(op::END);
+		}
+		self.asm().op(op::END);
+
+		let scope = self.scopes.pop().unwrap();
+		let ty = self.module.closure_type(params.len());
+		self.module.define(
+			function,
+			Function {
+				ty,
+				locals: scope.locals,
+				code: scope.asm.code,
+			},
+		);
+
+		let ptr = self.local(I32);
+		self.asm()
+			.i32(8 + 8 * scope.captures.len() as i32)
+			.call(rt::ALLOC)
+			.tee(ptr)
+			.i32(function as i32)
+			.mem(op::I32_STORE, 0)
+			.get(ptr)
+			.i32(params.len() as i32)
+			.mem(op::I32_STORE, 4);
+		for (index, capture) in scope.captures.iter().enumerate() {
+			self.asm().get(ptr);
+			self.variable(*capture, loc);
+			self.asm().mem(op::I64_STORE, 8 + 8 * index as u32);
+		}
+		self.asm().get(ptr).tagged(tag::CLOSURE);
+	}
+
+	/// Pushes a bool from the i32 `bool` checks the value on the stack to be.
+	fn boolean(&mut self, loc: i64) {
+		self.asm().i64(loc).call(rt::BOOL).tagged(tag::BOOL);
+	}
+
+	/// Emits both branches of an `if`, whose condition is on the stack.
+	fn branches(&mut self, then: impl FnOnce(&mut Self), otherwise: impl FnOnce(&mut Self)) {
+		self.asm().block(op::IF, I64);
+		self.scope().depth += 1;
+		then(self);
+		self.asm().op(op::ELSE);
+		otherwise(self);
+		self.asm().op(op::END);
+		self.scope().depth -= 1;
+	}
+
+	/// Pushes the value of `expr`. In `tail` position, a function looping calls itself by
+	/// branching back.
+	fn expr(&mut self, expr: &Expr, tail: bool) {
+		let loc = location(expr.span) as i64;
+
+		match &expr.kind {
+			ExprKind::Int(i) => {
+				self.asm().i64(tagged(tag::INT, *i as u32));
+			}
+			ExprKind::Bool(b) => {
+				self.asm().i64(tagged(tag::BOOL, *b as u32));
+			}
+			ExprKind::Str(s) => {
+				let value = self.string(s);
+				self.asm().i64(value);
+			}
+			ExprKind::Variable(var) => match var.binding {
+				Binding::Local(id) => self.variable(id, loc),
+				binding => unreachable!("`{}` is not a local binding: {binding:?}", var.name),
+			},
+			ExprKind::Binary {
+				lhs,
+				op: op @ (BinOp::And | BinOp::Or),
+				rhs,
+			} => {
+				self.expr(lhs, false);
+				self.asm().i64(loc).call(rt::BOOL);
+				let rhs = |this: &mut Self| {
+					this.expr(rhs, false);
+					this.boolean(loc);
+				};
+				match op {
+					BinOp::And => self.branches(rhs, |this| {
+						this.asm().i64(tagged(tag::BOOL, 0));
+					}),
+					_ => self.branches(
+						|this| {
+							this.asm().i64(tagged(tag::BOOL, 1));
+						},
+						rhs,
+					),
+				}
+			}
+			ExprKind::Binary { lhs, op, rhs } => {
+				let index = OPERATORS.iter().position(|o| o == op).unwrap() as i32;
+				let ordering = match op {
+					BinOp::Lt => Some(op::I32_LT_S),
+					BinOp::Gt => Some(op::I32_GT_S),
+					BinOp::Lte => Some(op::I32_LE_S),
+					BinOp::Gte => Some(op::I32_GE_S),
+					_ => None,
+				};
+				if ordering.is_some() {
+					self.asm().i32(index);
+				}
+				self.expr(lhs, false);
+				self.expr(rhs, false);
+				self.asm().i64(loc);
+				match (op, ordering) {
+					(_, Some(ordering)) => {
+						self.asm()
+							.call(rt::ORDER)
+							.i32(0)
+							.op(ordering)
+							.tagged(tag::BOOL);
+					}
+					(BinOp::Eq, _) => {
+						self.asm().call(rt::EQ);
+					}
+					(BinOp::Neq, _) => {
+						self.asm().call(rt::EQ).i64(1).op(op::I64_XOR);
+					}
+					(BinOp::Add, _) => {
+						self.asm().call(rt::ADD);
+					}
+					(BinOp::Sub, _) => {
+						self.asm().call(rt::SUB);
+					}
+					(BinOp::Mul, _) => {
+						self.asm().call(rt::MUL);
+					}
+					(BinOp::Div, _) => {
+						self.asm().call(rt::DIV);
+					}
+					(BinOp::Rem, _) => {
+						self.asm().call(rt::REM);
+					}
+					(op, _) => unreachable!("`{}` is not arithmetic", op.symbol()),
+				}
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				self.expr(condition, false);
+				self.asm().i64(loc).call(rt::BOOL);
+				self.branches(
+					|this| this.expr(then, tail),
+					|this| this.expr(otherwise, tail),
+				);
+			}
+			ExprKind::Let { name, value, next } => {
+				match &value.kind {
+					ExprKind::Abstraction { args, body } => {
+						self.function(Some(name), args, body, location(value.span) as i64)
+					}
+					_ => self.expr(value, false),
+				}
+				match self.globals.get(&name.id()) {
+					Some((global, _)) => {
+						let global = *global;
+						self.asm().imm(op::GLOBAL_SET, global);
+					}
+					None => {
+						let slot = self.local(I64);
+						self.scope().slots.insert(name.id(), slot);
+						self.asm().set(slot);
+					}
+				}
+				self.expr(next, tail);
+			}
+			ExprKind::Tuple(first, second) => {
+				self.expr(first, false);
+				self.expr(second, false);
+				self.asm().call(rt::TUPLE);
+			}
+			ExprKind::Abstraction { args, body } => self.function(None, args, body, loc),
+			ExprKind::Application { callee, args } => self.application(callee, args, tail, loc),
+		}
+	}
+
+	fn application(&mut self, callee: &Expr, args: &[Expr], tail: bool, loc: i64) {
+		let ExprKind::Variable(var) = &callee.kind else {
+			return self.call(callee, args, loc);
+		};
+		let id = match var.binding {
+			Binding::Builtin(builtin) => {
+				self.expr(&args[0], false);
+				match builtin {
+					Builtin::Print => {
+						let value = self.local(I64);
+						self.asm().tee(value).call(rt::PRINT).get(value);
+					}
+					Builtin::First => {
+						self.asm().i64(loc).call(rt::FIRST);
+					}
+					Builtin::Second => {
+						self.asm().i64(loc).call(rt::SECOND);
+					}
+				}
+				return;
+			}
+			Binding::Local(id) => id,
+			binding => unreachable!("`{}` is not a local binding: {binding:?}", var.name),
+		};
+
+		let scope = self.scopes.last().unwrap();
+		match &scope.looping {
+			Some(looping) if tail && looping.continues(callee, args) => {
+				let depth = scope.depth - looping.jump;
+				args.iter().for_each(|arg| self.expr(arg, false));
+				for slot in (1..=args.len() as u32).rev() {
+					self.asm().set(slot);
+				}
+				self.asm().imm(op::BR, depth);
+			}
+			_ => match self.functions.get(&id).copied() {
+				Some((function, arity)) if arity == args.len() => {
+					self.variable(id, loc);
+					args.iter().for_each(|arg| self.expr(arg, false));
+					self.asm().call(function);
+				}
+				_ => self.call(callee, args, loc),
+			},
+		}
+	}
+
+	/// Calls a closure, checking it takes as many arguments as given.
+	fn call(&mut self, callee: &Expr, args: &[Expr], loc: i64) {
+		self.expr(callee, false);
+		let closure = self.local(I64);
+		self.asm().tee(closure);
+		args.iter().for_each(|arg| self.expr(arg, false));
+		let ty = self.module.closure_type(args.len());
+		self.asm()
+			.get(closure)
+			.i32(args.len() as i32)
+			.i64(loc)
+			.call(rt::CALLEE)
+			.imm(op::CALL_INDIRECT, ty)
+			.op(0);
+	}
+}
+
+/// Appends `value` as `print` shows it.
+fn show(memory: &[u8], value: u64, text: &mut String) {
+	let payload = value as u32;
+	let at = payload as usize;
+	let load = |at: usize| u64::from_le_bytes(memory[at..at + 8].try_into().unwrap());
+
+	match (value >> 32) as i64 {
+		tag::INT => write!(text, "{}", payload as i32).unwrap(),
+		tag::BOOL => write!(text, "{}", payload != 0).unwrap(),
+		tag::STR => {
+			let len = u32::from_le_bytes(memory[at..at + 4].try_into().unwrap()) as usize;
+			text.push_str(&String::from_utf8_lossy(&memory[at + 4..at + 4 + len]));
+		}
+		tag::TUPLE => {
+			text.push('(');
+			show(memory, load(at), text);
+			text.push_str(", ");
+			show(memory, load(at + 8), text);
+			text.push(')');
+		}
+		_ => text.push_str("<#closure>"),
+	}
+}
+
+/// The error of a call to `fail`.
+fn failure(memory: &[u8], args: &[u64]) -> RunError {
+	let &[code, x, y, loc] = args else {
+		unreachable!("`fail` takes 4 arguments")
+	};
+	let type_name = |value: u64| match (value >> 32) as i64 {
+		tag::INT => "int",
+		tag::BOOL => "bool",
+		tag::STR => "string",
+		tag::TUPLE => "tuple",
+		_ => "closure",
+	};
+
+	let message = match code as i32 {
+		fail::EXPECTED_BOOL => format!("expected a bool, found {}", type_name(x)),
+		fail::EXPECTED_TUPLE => format!("expected a tuple, found {}", type_name(x)),
+		fail::EXPECTED_CLOSURE => format!("expected a closure, found {}", type_name(x)),
+		fail::ARITY => format!("closure takes {x} arguments, but {y} were given"),
+		fail::DIVISION_BY_ZERO => "division by zero".to_owned(),
+		fail::COMPARE_CLOSURES => "cannot compare closures".to_owned(),
+		fail::UNDEFINED => {
+			let mut name = String::new();
+			show(memory, x, &mut name);
+			format!("`{name}` is used before its definition")
+		}
+		code => format!(
+			"cannot apply `{}` to {} and {}",
+			OPERATORS[(code - fail::MISMATCH) as usize].symbol(),
+			type_name(x),
+			type_name(y)
+		),
+	};
+	let span = Span::new((loc >> 30) as usize, (loc & ((1 << 30) - 1)) as usize);
+	RunError::Runtime { message, span }
+}
+
+/// Runs a module [`transpile`] emitted with the interpreter of [`crate::wasm`], providing
+/// its imports: `print` writes to `out`, and `fail` stops the program with an error.
+pub fn run(bytes: &[u8], out: &mut dyn Write) -> Result<String, RunError> {
+	let invalid = |Trap(err)| RunError::Invalid(format!("wasm: {err}"));
+	let module = Module::decode(bytes).map_err(invalid)?;
+	let mut instance = Instance::new(&module).map_err(invalid)?;
+
+	let mut error = None;
+	let mut host = |import: &wasm::Import, memory: &mut [u8], args: &[u64]| match (
+		import.module.as_str(),
+		import.name.as_str(),
+	) {
+		("rinha", "print") => {
+			let mut text = String::new();
+			show(memory, args[0], &mut text);
+			writeln!(out, "{text}").map_err(|err| Trap(err.to_string()))?;
+			Ok(None)
+		}
+		("rinha", "fail") => {
+			error = Some(failure(memory, args));
+			Err(Trap("the program failed".to_owned()))
+		}
+		(module, name) => Err(Trap(format!("unknown import `{module}.{name}`"))),
+	};
+	let result = instance.invoke("main", &[], &mut host);
+	if let Some(err) = error {
+		return Err(err);
+	}
+
+	let value = result
+		.map_err(invalid)?
+		.ok_or_else(|| RunError::Invalid("wasm: `main` returned nothing".to_owned()))?;
+	let mut text = String::new();
+	show(&instance.memory, value, &mut text);
+	Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{run, transpile};
+	use crate::{
+		backend::RunError,
+		testing::{hvm, parse},
+	};
+
+	fn wasm(src: &str) -> (String, Result<String, RunError>) {
+		let mut out = vec![];
+		let result = run(&transpile(&parse(src)), &mut out);
+		(String::from_utf8(out).unwrap(), result)
+	}
+
+	#[test]
+	fn wasm_agrees_with_hvm() {
+		let programs = [
+			"let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) }; fib(20)",
+			"let count = fn (n, acc) => if (n == 0) { acc } else { count(n - 1, acc + 1) }; count(100000, 0)",
+			"let sum = fn (n) => if (n == 0) { 0 } else { n + sum(n - 1) }; (sum(1000), 2147483647 * 3)",
+			"let adder = fn (x) => fn (y) => x + y; (adder(\"a\")(-10), (adder(1)(-2), -7 / 2))",
+			"let p = (1, (\"b\", true)); (p == (1, (\"b\", true)), (p < (1, (\"c\", false)), first(second(p))))",
+			"let f = fn (x) => x; (f, (true && (1 < 2), (false || (3 >= 4), 7 % -3)))",
+		];
+		for src in programs {
+			assert_eq!(wasm(src).1.unwrap(), hvm(src), "{src}");
+		}
+	}
+
+	#[test]
+	fn wasm_prints_and_fails() {
+		let src = "let _ = print((1, \"a\\nb\")); let f = fn (x) => 10 / x; f(5) + f(0)";
+		let (out, result) = wasm(src);
+		assert_eq!(out, "(1, a\nb)\n");
+		let Err(RunError::Runtime { message, span }) = result else {
+			panic!("{result:?}")
+		};
+		assert_eq!(message, "division by zero");
+		assert_eq!(&src[span.start..span.end], "10 / x");
+
+		let src = "let f = fn () => (1, \"a\") < (1, 2); f()";
+		let Err(RunError::Runtime { message, .. }) = wasm(src).1 else {
+			panic!()
+		};
+		assert_eq!(message, "cannot apply `<` to string and int");
+	}
+}
@@ -0,0 +1,261 @@
+use std::{
+	fmt::{Display, Write as _},
+	io::Write,
+	path::Path,
+};
+
+use crate::expr::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Warning,
+	Error,
+}
+
+impl Display for Severity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Warning => write!(f, "warning"),
+			Self::Error => write!(f, "error"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	pub message: String,
+	pub span: Option<Span>,
+}
+
+impl Diagnostic {
+	pub fn error(message: impl Into<String>, span: impl Into<Option<Span>>) -> Self {
+		Self {
+			severity: Severity::Error,
+			message: message.into(),
+			span: span.into(),
+		}
+	}
+
+	pub fn warning(message: impl Into<String>, span: impl Into<Option<Span>>) -> Self {
+		Self {
+			severity: Severity::Warning,
+			message: message.into(),
+			span: span.into(),
+		}
+	}
+}
+
+/// Diagnostics collected over a run, in the order they were reported.
+#[derive(Debug, Default, Clone)]
+pub struct Diagnostics {
+	list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	#[inline]
+	pub fn push(&mut self, diagnostic: Diagnostic) {
+		self.list.push(diagnostic);
+	}
+
+	#[inline]
+	pub fn error(&mut self, message: impl Into<String>, span: impl Into<Option<Span>>) {
+		self.push(Diagnostic::error(message, span));
+	}
+
+	#[inline]
+	pub fn warning(&mut self, message: impl Into<String>, span: impl Into<Option<Span>>) {
+		self.push(Diagnostic::warning(message, span));
+	}
+
+	#[inline]
+	pub fn has_errors(&self) -> bool {
+		self.list.iter().any(|d| d.severity == Severity::Error)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+		self.list.iter()
+	}
+
+	/// Writes every diagnostic, rendered against `source`.
+	pub fn emit(&self, source: &Source, out: &mut impl Write) -> std::io::Result<()> {
+		for diagnostic in self.iter() {
+			write!(out, "{}", source.render(diagnostic))?;
+		}
+		out.flush()
+	}
+}
+
+impl From<Diagnostic> for Diagnostics {
+	fn from(diagnostic: Diagnostic) -> Self {
+		Self {
+			list: vec![diagnostic],
+		}
+	}
+}
+
+/// The program text spans point into, used to turn byte offsets into `file:line:col`.
+///
+/// The text is optional: a JSON AST may come without the `.rinha` program it was generated from.
+pub struct Source {
+	name: String,
+	text: Option<String>,
+	line_starts: Vec<usize>,
+}
+
+impl Source {
+	pub fn new(name: impl Into<String>, text: Option<String>) -> Self {
+		let line_starts = text.as_deref().map_or_else(Vec::new, |text| {
+			std::iter::once(0)
+				.chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+				.collect()
+		});
+
+		Self {
+			name: name.into(),
+			text,
+			line_starts,
+		}
+	}
+
+	/// Spans in a JSON AST point into the `.rinha` program it was generated from,
+	/// so look for that program next to the JSON file: first with the same stem,
+	/// then by the file name recorded in the AST.
+	pub fn for_ast(json_path: &Path, name: &str) -> Self {
+		let dir = json_path.parent().unwrap_or(Path::new(""));
+		let candidates = [
+			Some(json_path.with_extension("rinha")),
+			Path::new(name).file_name().map(|file| dir.join(file)),
+		];
+
+		for path in candidates.into_iter().flatten() {
+			if let Ok(text) = std::fs::read_to_string(&path) {
+				return Self::new(path.display().to_string(), Some(text));
+			}
+		}
+
+		Self::new(name, None)
+	}
+
+	#[cfg(test)]
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// 1-based line and column of a byte offset, if the source text is known.
+	pub fn location(&self, offset: usize) -> Option<(usize, usize)> {
+		let text = self.text.as_deref()?;
+		let offset = offset.min(text.len());
+		let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+		let prefix = text.get(self.line_starts[line]..offset)?;
+
+		Some((line + 1, prefix.chars().count() + 1))
+	}
+
+	/// Text of a 1-based line, without its line break.
+	pub fn line(&self, line: usize) -> Option<&str> {
+		let text = self.text.as_deref()?;
+		let start = *self.line_starts.get(line - 1)?;
+		let end = self
+			.line_starts
+			.get(line)
+			.map_or(text.len(), |next| next - 1);
+
+		Some(text[start..end].trim_end_matches('\r'))
+	}
+
+	/// Renders a diagnostic with its position and, when possible, the offending line.
+	///
+	/// ```text
+	/// error: unbound variable `x`
+	///  --> test_files/example.rinha:3:9
+	///   |
+	/// 3 | let y = x + 1;
+	///   |         ^
+	/// ```
+	pub fn render(&self, diagnostic: &Diagnostic) -> String {
+		let mut out = format!("{}: {}\n", diagnostic.severity, diagnostic.message);
+
+		let Some(span) = diagnostic.span else {
+			return out;
+		};
+
+		let Some((line, col)) = self.location(span.start) else {
+			let _ = writeln!(out, " --> {}@{}..{}", self.name, span.start, span.end);
+			return out;
+		};
+
+		let gutter = line.to_string().len();
+		let _ = writeln!(out, "{:gutter$}--> {}:{line}:{col}", "", self.name);
+
+		if let Some(text) = self.line(line) {
+			let before = text.chars().take(col - 1).count();
+			let width = match self.location(span.end) {
+				Some((end_line, end_col)) if end_line == line => end_col.saturating_sub(col),
+				_ => text.chars().count().saturating_sub(before),
+			};
+
+			let _ = writeln!(out, "{:gutter$} |", "");
+			let _ = writeln!(out, "{line} | {text}");
+			let _ = writeln!(
+				out,
+				"{:gutter$} | {:before$}{}",
+				"",
+				"",
+				"^".repeat(width.max(1))
+			);
+		}
+
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Diagnostic, Source};
+	use crate::expr::Span;
+
+	#[test]
+	fn offsets_to_line_col() {
+		let source = Source::new("a.rinha", Some("let x = 1;\nlet y = x;\r\nprint(y)".into()));
+
+		assert_eq!(source.location(0), Some((1, 1)));
+		assert_eq!(source.location(8), Some((1, 9)));
+		assert_eq!(source.location(11), Some((2, 1)));
+		assert_eq!(source.location(23), Some((3, 1)));
+		assert_eq!(source.line(2), Some("let y = x;"));
+		assert_eq!(Source::new("a.rinha", None).location(3), None);
+	}
+
+	#[test]
+	fn render_snippet() {
+		let source = Source::new("a.rinha", Some("let x = 1;\nlet y = z + 1;\ny".into()));
+		let diagnostic = Diagnostic::error("unbound variable `z`", Span::new(19, 20));
+
+		assert_eq!(
+			source.render(&diagnostic),
+			"error: unbound variable `z`\n --> a.rinha:2:9\n  |\n2 | let y = z + 1;\n  |         ^\n"
+		);
+
+		let source = Source::new("a.rinha", None);
+		assert_eq!(
+			source.render(&Diagnostic::warning("careful", Span::new(19, 20))),
+			"warning: careful\n --> a.rinha@19..20\n"
+		);
+	}
+
+	#[test]
+	fn ast_source_lookup() {
+		let source = Source::for_ast("test_files/tco.json".as_ref(), "test_files/tco.rinha");
+		assert_eq!(source.name(), "test_files/tco.rinha");
+		assert_eq!(source.location(34), Some((2, 7)));
+
+		let source = Source::for_ast("test_files/fib.json".as_ref(), "files/fib.rinha");
+		assert_eq!(source.name(), "files/fib.rinha");
+		assert_eq!(source.location(34), None);
+	}
+}
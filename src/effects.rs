@@ -0,0 +1,269 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::expr::{Binding, BindingId, Builtin, Expr, ExprKind};
+
+/// What evaluating an expression may do, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Effect {
+	/// Always evaluates to a value.
+	None,
+	/// May fail at runtime, or never finish, but doesn't print.
+	Fail,
+	/// May print.
+	Print,
+}
+
+/// Direct calls between `let`-bound functions.
+///
+/// Only calls to a known function with the right number of arguments, outside of the
+/// closures a body creates, are edges. Anything else called is unknown.
+pub struct CallGraph {
+	functions: HashMap<BindingId, Calls>,
+	arities: HashMap<BindingId, usize>,
+}
+
+#[derive(Default)]
+struct Calls {
+	callees: BTreeSet<BindingId>,
+	/// Whether the body prints, or calls something that isn't a known function.
+	prints: bool,
+}
+
+impl CallGraph {
+	pub fn build(expr: &Expr) -> Self {
+		let mut arities = HashMap::new();
+		collect_arities(expr, &mut arities);
+
+		let mut functions = HashMap::new();
+		collect_functions(expr, &arities, &mut functions);
+		Self { functions, arities }
+	}
+
+	/// Functions `id` calls directly.
+	pub fn callees(&self, id: BindingId) -> impl Iterator<Item = BindingId> + '_ {
+		self.functions
+			.get(&id)
+			.into_iter()
+			.flat_map(|calls| calls.callees.iter().copied())
+	}
+}
+
+fn collect_functions(
+	expr: &Expr,
+	arities: &HashMap<BindingId, usize>,
+	functions: &mut HashMap<BindingId, Calls>,
+) {
+	if let ExprKind::Let { name, value, .. } = &expr.kind {
+		if let ExprKind::Abstraction { body, .. } = &value.kind {
+			let mut calls = Calls::default();
+			collect_calls(body, arities, &mut calls);
+			functions.insert(name.id(), calls);
+		}
+	}
+
+	expr.children()
+		.for_each(|child| collect_functions(child, arities, functions));
+}
+
+/// Arities of every `let`-bound function.
+fn collect_arities(expr: &Expr, arities: &mut HashMap<BindingId, usize>) {
+	if let ExprKind::Let { name, value, .. } = &expr.kind {
+		if let ExprKind::Abstraction { args, .. } = &value.kind {
+			arities.insert(name.id(), args.len());
+		}
+	}
+	expr.children()
+		.for_each(|child| collect_arities(child, arities));
+}
+
+/// Records the calls evaluating `expr` makes.
+fn collect_calls(expr: &Expr, arities: &HashMap<BindingId, usize>, calls: &mut Calls) {
+	match &expr.kind {
+		// the body of a closure only runs when the closure gets called
+		ExprKind::Abstraction { .. } => return,
+		ExprKind::Application { callee, args } => match known_callee(callee, args, arities) {
+			Callee::Builtin(Builtin::Print) | Callee::Unknown => calls.prints = true,
+			Callee::Builtin(_) => {}
+			Callee::Function(id) => {
+				calls.callees.insert(id);
+			}
+		},
+		_ => {}
+	}
+
+	expr.children()
+		.for_each(|child| collect_calls(child, arities, calls));
+}
+
+enum Callee {
+	Builtin(Builtin),
+	Function(BindingId),
+	Unknown,
+}
+
+fn known_callee(callee: &Expr, args: &[Expr], arities: &HashMap<BindingId, usize>) -> Callee {
+	match &callee.kind {
+		ExprKind::Variable(var) => match var.binding {
+			Binding::Builtin(builtin) => Callee::Builtin(builtin),
+			Binding::Local(id) if arities.get(&id) == Some(&args.len()) => Callee::Function(id),
+			_ => Callee::Unknown,
+		},
+		_ => Callee::Unknown,
+	}
+}
+
+/// What every `let`-bound function, and through them every expression, may do.
+///
+/// A function prints if its body does, or if it calls a function that prints, which is
+/// found as a fixpoint over the [`CallGraph`], so recursive functions can be pure. Calling
+/// something that isn't a known function is assumed to print, while creating a closure
+/// does nothing.
+pub struct Effects {
+	graph: CallGraph,
+	/// Functions that may print.
+	printing: BTreeSet<BindingId>,
+}
+
+impl Effects {
+	pub fn analyze(expr: &Expr) -> Self {
+		let graph = CallGraph::build(expr);
+
+		let mut printing = graph
+			.functions
+			.iter()
+			.filter(|(_, calls)| calls.prints)
+			.map(|(id, _)| *id)
+			.collect::<BTreeSet<_>>();
+		loop {
+			let callers = graph
+				.functions
+				.iter()
+				.filter(|(id, calls)| {
+					!printing.contains(id) && calls.callees.iter().any(|c| printing.contains(c))
+				})
+				.map(|(id, _)| *id)
+				.collect::<Vec<_>>();
+
+			if callers.is_empty() {
+				break;
+			}
+			printing.extend(callers);
+		}
+
+		Self { graph, printing }
+	}
+
+	/// Whether calling the function `id` never prints.
+	#[inline]
+	pub fn is_pure(&self, id: BindingId) -> bool {
+		self.graph.functions.contains_key(&id) && !self.printing.contains(&id)
+	}
+
+	/// Whether the function `id` calls itself directly.
+	pub fn is_recursive(&self, id: BindingId) -> bool {
+		self.graph.callees(id).any(|callee| callee == id)
+	}
+
+	/// What evaluating `expr` may do.
+	pub fn of(&self, expr: &Expr) -> Effect {
+		let own = match &expr.kind {
+			ExprKind::Int(_)
+			| ExprKind::Bool(_)
+			| ExprKind::Str(_)
+			| ExprKind::Variable(_)
+			| ExprKind::Tuple(..)
+			| ExprKind::Let { .. } => Effect::None,
+			ExprKind::Abstraction { .. } => return Effect::None,
+			// operators and conditions fail on values of the wrong type
+			ExprKind::Binary { .. } | ExprKind::If { .. } => Effect::Fail,
+			ExprKind::Application { callee, args } => {
+				match known_callee(callee, args, &self.graph.arities) {
+					Callee::Builtin(Builtin::Print) | Callee::Unknown => Effect::Print,
+					Callee::Function(id) if self.printing.contains(&id) => Effect::Print,
+					Callee::Builtin(_) | Callee::Function(_) => Effect::Fail,
+				}
+			}
+		};
+
+		expr.children()
+			.map(|child| self.of(child))
+			.fold(own, Effect::max)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Effect, Effects};
+	use crate::{
+		diagnostics::Diagnostics,
+		expr::{BindingId, Expr, ExprKind},
+		resolve, syntax,
+	};
+
+	fn analyze(src: &str) -> (Expr, Effects) {
+		let mut expr = syntax::parse(src).unwrap();
+		resolve::resolve(&mut expr, &mut Diagnostics::new());
+		let effects = Effects::analyze(&expr);
+		(expr, effects)
+	}
+
+	#[test]
+	fn effects_through_calls() {
+		let (expr, effects) = analyze(
+			"
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			let log = fn (x) => print(x);
+			let twice = fn (x) => log(x) + log(x);
+			let apply = fn (f, x) => f(x);
+			let make = fn (x) => fn () => print(x);
+			let is_even = fn (n) => if (n == 0) { true } else { is_odd(n - 1) };
+			let is_odd = fn (n) => if (n == 0) { false } else { is_even(n - 1) };
+			0
+			",
+		);
+
+		let mut names = vec![];
+		let mut next = &expr;
+		while let ExprKind::Let {
+			name, next: rest, ..
+		} = &next.kind
+		{
+			names.push((name.name.as_str(), name.id()));
+			next = rest;
+		}
+		let pure = names
+			.iter()
+			.filter(|(_, id)| effects.is_pure(*id))
+			.map(|(name, _)| *name)
+			.collect::<Vec<_>>();
+		assert_eq!(pure, ["fib", "make", "is_even", "is_odd"]);
+
+		assert!(effects.is_recursive(names[0].1));
+		assert!(!effects.is_recursive(names[5].1));
+		assert!(!effects.is_recursive(BindingId(100)));
+	}
+
+	#[test]
+	fn effects_of_expressions() {
+		let effect = |src: &str| {
+			let src = format!(
+				"let f = fn (x) => x + 1; let p = fn (x) => print(x); let g = fn (x) => x; {src}"
+			);
+			let (mut expr, effects) = analyze(&src);
+			for _ in 0..3 {
+				let ExprKind::Let { next, .. } = expr.kind else {
+					unreachable!()
+				};
+				expr = *next;
+			}
+			effects.of(&expr)
+		};
+
+		assert_eq!(effect("(1, (\"a\", fn () => print(1)))"), Effect::None);
+		assert_eq!(effect("let y = 2; (y, g)"), Effect::None);
+		assert_eq!(effect("f(1)"), Effect::Fail);
+		assert_eq!(effect("let y = first((1, 2)); y"), Effect::Fail);
+		assert_eq!(effect("if (true) { 1 } else { p(2) }"), Effect::Print);
+		assert_eq!(effect("g(1)(2)"), Effect::Print);
+	}
+}
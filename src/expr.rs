@@ -1,24 +1,192 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Ident(pub String);
-impl From<String> for Ident {
-	fn from(value: String) -> Self {
-		Self(value)
+/// Byte range of a node in the original source file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+	pub start: usize,
+	pub end: usize,
+}
+
+impl Span {
+	#[inline]
+	pub fn new(start: usize, end: usize) -> Self {
+		Self { start, end }
+	}
+
+	/// Smallest span covering both `self` and `other`.
+	#[inline]
+	pub fn to(self, other: Span) -> Self {
+		Self::new(self.start.min(other.start), self.end.max(other.end))
+	}
+}
+
+/// Unique id of a binder (a `let` name or a function parameter), assigned by the resolver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BindingId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+	Print,
+	First,
+	Second,
+}
+
+impl Builtin {
+	pub const ALL: [Builtin; 3] = [Self::Print, Self::First, Self::Second];
+
+	/// Name the builtin is called by in Rinha programs.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Print => "print",
+			Self::First => "first",
+			Self::Second => "second",
+		}
 	}
 }
 
+impl Display for Builtin {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let str = match self {
+			Self::Print => "STD.print",
+			Self::First => "STD.first",
+			Self::Second => "STD.second",
+		};
+
+		write!(f, "{str}")
+	}
+}
+
+/// What an identifier refers to. Binders carry their own id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+	Unresolved,
+	Local(BindingId),
+	Builtin(Builtin),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ident {
+	pub name: String,
+	pub span: Span,
+	pub binding: Binding,
+}
+
 impl Ident {
+	#[inline]
+	pub fn new(name: impl Into<String>, span: Span) -> Self {
+		Self {
+			name: name.into(),
+			span,
+			binding: Binding::Unresolved,
+		}
+	}
+
+	/// Id of the binder this identifier declares or refers to.
+	///
+	/// Only valid after resolution.
+	#[inline]
+	pub fn id(&self) -> BindingId {
+		match self.binding {
+			Binding::Local(id) => id,
+			binding => unreachable!("`{}` is not a local binding: {binding:?}", self.name),
+		}
+	}
+
 	#[inline]
 	pub fn val(&self) -> &str {
-		&self.0
+		&self.name
 	}
 }
 
 type BExpr = Box<Expr>;
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
+pub struct Expr {
+	pub kind: ExprKind,
+	pub span: Span,
+}
+
+impl Expr {
+	#[inline]
+	pub fn new(kind: ExprKind, span: Span) -> Self {
+		Self { kind, span }
+	}
+
+	/// Direct subexpressions, in evaluation order.
+	pub fn children(&self) -> impl Iterator<Item = &Expr> {
+		let children = match &self.kind {
+			ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Str(_) | ExprKind::Variable(_) => {
+				vec![]
+			}
+			ExprKind::Binary { lhs, rhs, .. } => vec![&**lhs, rhs],
+			ExprKind::Let { value, next, .. } => vec![&**value, next],
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => vec![&**condition, then, otherwise],
+			ExprKind::Tuple(first, second) => vec![&**first, second],
+			ExprKind::Application { callee, args } => {
+				std::iter::once(&**callee).chain(args).collect()
+			}
+			ExprKind::Abstraction { body, .. } => vec![&**body],
+		};
+
+		children.into_iter()
+	}
+
+	/// Resets every span in the tree, so trees can be compared by shape only.
+	#[cfg(test)]
+	pub fn without_spans(self) -> Self {
+		let strip = |e: BExpr| Box::new(e.without_spans());
+		let ident = |i: Ident| Ident::new(i.name, Span::default());
+
+		let kind = match self.kind {
+			ExprKind::Variable(v) => ExprKind::Variable(ident(v)),
+			ExprKind::Binary { lhs, op, rhs } => ExprKind::Binary {
+				lhs: strip(lhs),
+				op,
+				rhs: strip(rhs),
+			},
+			ExprKind::Let { name, value, next } => ExprKind::Let {
+				name: ident(name),
+				value: strip(value),
+				next: strip(next),
+			},
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => ExprKind::If {
+				condition: strip(condition),
+				then: strip(then),
+				otherwise: strip(otherwise),
+			},
+			ExprKind::Tuple(first, second) => ExprKind::Tuple(strip(first), strip(second)),
+			ExprKind::Application { callee, args } => ExprKind::Application {
+				callee: strip(callee),
+				args: args.into_iter().map(Expr::without_spans).collect(),
+			},
+			ExprKind::Abstraction { args, body } => ExprKind::Abstraction {
+				args: args.into_iter().map(ident).collect(),
+				body: strip(body),
+			},
+			kind => kind,
+		};
+
+		Self::new(kind, Span::default())
+	}
+}
+
+#[cfg(test)]
+impl From<ExprKind> for Box<Expr> {
+	fn from(kind: ExprKind) -> Self {
+		Box::new(Expr::new(kind, Span::default()))
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExprKind {
 	Int(i32),
 	Bool(bool),
 	Str(String),
@@ -66,9 +234,32 @@ pub enum BinOp {
 	Or,
 }
 
-impl From<&str> for BinOp {
-	fn from(value: &str) -> Self {
-		match value {
+impl BinOp {
+	/// Operator as written in Rinha programs.
+	pub fn symbol(&self) -> &'static str {
+		match self {
+			Self::Add => "+",
+			Self::Sub => "-",
+			Self::Mul => "*",
+			Self::Div => "/",
+			Self::Rem => "%",
+			Self::Eq => "==",
+			Self::Neq => "!=",
+			Self::Lt => "<",
+			Self::Gt => ">",
+			Self::Lte => "<=",
+			Self::Gte => ">=",
+			Self::And => "&&",
+			Self::Or => "||",
+		}
+	}
+}
+
+impl FromStr for BinOp {
+	type Err = ();
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		Ok(match value {
 			"Add" => Self::Add,
 			"Sub" => Self::Sub,
 			"Mul" => Self::Mul,
@@ -82,8 +273,8 @@ impl From<&str> for BinOp {
 			"Gte" => Self::Gte,
 			"And" => Self::And,
 			"Or" => Self::Or,
-			_ => panic!("Invalid operator"),
-		}
+			_ => return Err(()),
+		})
 	}
 }
 
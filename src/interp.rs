@@ -0,0 +1,308 @@
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use crate::{
+	expr::{BinOp, Binding, BindingId, Expr, ExprKind, Ident},
+	value::{self, Error},
+};
+
+/// A runtime value of the tree-walking interpreter, borrowing function bodies from the tree.
+pub type Value<'a> = value::Value<Closure<'a>>;
+
+#[derive(Debug)]
+pub struct Closure<'a> {
+	params: &'a [Ident],
+	body: &'a Expr,
+	env: Env<'a>,
+}
+
+/// Bindings in scope, innermost first.
+///
+/// A slot is empty while the function bound to it is being created, or until the `let`
+/// of a top-level function is reached.
+#[derive(Debug, Clone, Default)]
+pub struct Env<'a>(Option<Rc<Frame<'a>>>);
+
+#[derive(Debug)]
+struct Frame<'a> {
+	id: BindingId,
+	value: RefCell<Option<Value<'a>>>,
+	parent: Env<'a>,
+}
+
+impl<'a> Env<'a> {
+	fn bind(&self, id: BindingId, value: Option<Value<'a>>) -> Self {
+		Self(Some(Rc::new(Frame {
+			id,
+			value: RefCell::new(value),
+			parent: self.clone(),
+		})))
+	}
+
+	fn frame(&self, id: BindingId) -> Option<&Rc<Frame<'a>>> {
+		let mut env = self;
+		while let Some(frame) = &env.0 {
+			if frame.id == id {
+				return Some(frame);
+			}
+			env = &frame.parent;
+		}
+		None
+	}
+}
+
+/// Evaluates a resolved tree directly, as the reference for what programs mean.
+///
+/// Evaluation is strict and goes left to right. Calls in tail position reuse the loop of
+/// [`Interpreter::eval`], so loops written as tail recursion don't grow the Rust stack.
+/// Fails with the same messages the HVM runtime reports.
+pub struct Interpreter<'o> {
+	out: &'o mut dyn Write,
+}
+
+impl<'o> Interpreter<'o> {
+	pub fn new(out: &'o mut dyn Write) -> Self {
+		Self { out }
+	}
+
+	pub fn run<'a>(&mut self, expr: &'a Expr) -> Result<Value<'a>, Error> {
+		// top-level functions can be called before their `let`, so their slots come first
+		let mut env = Env::default();
+		let mut next = expr;
+		while let ExprKind::Let {
+			name,
+			value,
+			next: rest,
+		} = &next.kind
+		{
+			if let ExprKind::Abstraction { .. } = value.kind {
+				env = env.bind(name.id(), None);
+			}
+			next = rest;
+		}
+
+		self.eval(expr, env)
+	}
+
+	fn eval<'a>(&mut self, mut expr: &'a Expr, mut env: Env<'a>) -> Result<Value<'a>, Error> {
+		loop {
+			let span = expr.span;
+
+			return Ok(match &expr.kind {
+				ExprKind::Int(i) => Value::Int(*i),
+				ExprKind::Bool(b) => Value::Bool(*b),
+				ExprKind::Str(s) => Value::Str(s.as_str().into()),
+				ExprKind::Variable(var) => Self::variable(var, &env)?,
+				ExprKind::Binary {
+					lhs,
+					op: op @ (BinOp::And | BinOp::Or),
+					rhs,
+				} => match (op, self.eval(lhs, env.clone())?) {
+					(BinOp::And, Value::Bool(false)) => Value::Bool(false),
+					(BinOp::Or, Value::Bool(true)) => Value::Bool(true),
+					(_, Value::Bool(_)) => match self.eval(rhs, env)? {
+						value @ Value::Bool(_) => value,
+						value => return Err(Error::expected("a bool", &value, span)),
+					},
+					(_, value) => return Err(Error::expected("a bool", &value, span)),
+				},
+				ExprKind::Binary { lhs, op, rhs } => {
+					let lhs = self.eval(lhs, env.clone())?;
+					let rhs = self.eval(rhs, env)?;
+					value::binary(&lhs, op, &rhs, span)?
+				}
+				ExprKind::If {
+					condition,
+					then,
+					otherwise,
+				} => {
+					expr = match self.eval(condition, env.clone())? {
+						Value::Bool(true) => then,
+						Value::Bool(false) => otherwise,
+						value => return Err(Error::expected("a bool", &value, span)),
+					};
+					continue;
+				}
+				ExprKind::Let { name, value, next } => {
+					env = match &value.kind {
+						ExprKind::Abstraction { args, body } => {
+							// functions see themselves, so the slot exists before the closure
+							let env = match env.frame(name.id()) {
+								Some(_) => env,
+								None => env.bind(name.id(), None),
+							};
+							let closure = Self::closure(args, body, &env);
+							*env.frame(name.id()).unwrap().value.borrow_mut() = Some(closure);
+							env
+						}
+						_ => {
+							let value = self.eval(value, env.clone())?;
+							env.bind(name.id(), Some(value))
+						}
+					};
+					expr = next;
+					continue;
+				}
+				ExprKind::Tuple(first, second) => {
+					let first = self.eval(first, env.clone())?;
+					let second = self.eval(second, env)?;
+					Value::Tuple(Rc::new((first, second)))
+				}
+				ExprKind::Abstraction { args, body } => Self::closure(args, body, &env),
+				ExprKind::Application { callee, args } => {
+					// builtins are keywords, they can't be used as values
+					if let ExprKind::Variable(Ident {
+						binding: Binding::Builtin(builtin),
+						..
+					}) = callee.kind
+					{
+						let args = self.eval_all(args, &env)?;
+						return value::builtin(builtin, args, self.out, span);
+					}
+
+					let callee = self.eval(callee, env.clone())?;
+					let args = self.eval_all(args, &env)?;
+					let Value::Closure(closure) = callee else {
+						return Err(Error::expected("a closure", &callee, span));
+					};
+					if closure.params.len() != args.len() {
+						return Err(Error::arity(closure.params.len(), args.len(), span));
+					}
+
+					env = closure
+						.params
+						.iter()
+						.zip(args)
+						.fold(closure.env.clone(), |env, (param, arg)| {
+							env.bind(param.id(), Some(arg))
+						});
+					expr = closure.body;
+					continue;
+				}
+			});
+		}
+	}
+
+	fn eval_all<'a>(&mut self, exprs: &'a [Expr], env: &Env<'a>) -> Result<Vec<Value<'a>>, Error> {
+		exprs
+			.iter()
+			.map(|expr| self.eval(expr, env.clone()))
+			.collect()
+	}
+
+	fn variable<'a>(var: &Ident, env: &Env<'a>) -> Result<Value<'a>, Error> {
+		match var.binding {
+			Binding::Local(id) => env
+				.frame(id)
+				.and_then(|frame| frame.value.borrow().clone())
+				.ok_or_else(|| {
+					Error::new(
+						format!("`{}` is used before its definition", var.name),
+						var.span,
+					)
+				}),
+			Binding::Builtin(builtin) => unreachable!("`{}` used as a value", builtin.name()),
+			Binding::Unresolved => Err(Error::new(
+				format!("unbound variable `{}`", var.name),
+				var.span,
+			)),
+		}
+	}
+
+	fn closure<'a>(params: &'a [Ident], body: &'a Expr, env: &Env<'a>) -> Value<'a> {
+		Value::Closure(Rc::new(Closure {
+			params,
+			body,
+			env: env.clone(),
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Interpreter;
+	use crate::{testing::parse, value::Error};
+
+	/// Runs a program, returning what it printed and its value as `print` would show it.
+	fn run(src: &str) -> (String, Result<String, Error>) {
+		let expr = parse(src);
+		let mut out = vec![];
+		let result = Interpreter::new(&mut out)
+			.run(&expr)
+			.map(|value| value.to_string());
+		(String::from_utf8(out).unwrap(), result)
+	}
+
+	#[test]
+	fn interp_programs() {
+		let src = "
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			let count = fn (n, acc) => if (n == 0) { acc } else { count(n - 1, acc + 1) };
+			let is_even = fn (n) => if (n == 0) { true } else { is_odd(n - 1) };
+			let adder = fn (x) => fn (y) => x + y;
+			let is_odd = fn (n) => if (n == 0) { false } else { is_even(n - 1) };
+			let _ = print((1, (\"a\", adder)));
+			let x = print(\"fib: \" + fib(15));
+			(x, (count(1000000, 0), (is_even(7), (adder(2)(3), 2147483647 + 1))))
+		";
+		let (out, result) = run(src);
+		assert_eq!(out, "(1, (a, <#closure>))\nfib: 610\n");
+		assert_eq!(
+			result.unwrap(),
+			"(fib: 610, (1000000, (false, (5, -2147483648))))"
+		);
+
+		let src = "
+			let nil = \"<nil>\";
+			let xs = (1, (\"b\", nil));
+			((xs == (1, (\"b\", nil)), xs == nil), ((-1 < 1, \"ab\" < \"b\"), ((1, \"b\") >= (1, \"ab\"), true > false)))
+		";
+		assert_eq!(
+			run(src).1.unwrap(),
+			"((true, false), ((true, true), (true, true)))"
+		);
+	}
+
+	#[test]
+	fn interp_runtime_errors() {
+		let error = |src: &str| {
+			let Err(Error { message, span }) = run(src).1 else {
+				panic!("{src} didn't fail")
+			};
+			(message, src[span.start..span.end].to_owned())
+		};
+
+		assert_eq!(
+			error("let f = fn (x) => 10 / x; f(5) + f(0)"),
+			("division by zero".to_owned(), "10 / x".to_owned())
+		);
+		assert_eq!(
+			error("let x = 1 + true; (2, x)"),
+			(
+				"cannot apply `+` to int and bool".to_owned(),
+				"1 + true".to_owned()
+			)
+		);
+		assert_eq!(
+			error("let f = fn (a, b) => a; let g = f; g(1)"),
+			(
+				"closure takes 2 arguments, but 1 were given".to_owned(),
+				"g(1)".to_owned()
+			)
+		);
+		assert_eq!(
+			error("(1, 2) < (1, \"a\")").0,
+			"cannot apply `<` to int and string"
+		);
+		assert_eq!(
+			error("(1, fn () => 1) == (1, 2)").0,
+			"cannot compare closures"
+		);
+		assert_eq!(error("true && 1").0, "expected a bool, found int");
+		assert_eq!(error("first(1)").0, "expected a tuple, found int");
+
+		// nothing is printed after the first failure
+		let (out, result) = run("let _ = print(1); let _ = 1 % 0; print(2)");
+		assert_eq!(out, "1\n");
+		assert!(result.is_err());
+	}
+}
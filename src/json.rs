@@ -3,6 +3,8 @@
 /// https://github.com/winnow-rs/winnow/blob/main/examples/json/parser_dispatch.rs
 /// LICENSE: https://github.com/winnow-rs/winnow/blob/main/COPYRIGHT
 /// ==============================================================================
+use std::fmt::Display;
+
 use winnow::{
 	ascii::float,
 	combinator::{
@@ -14,7 +16,8 @@ use winnow::{
 	PResult, Parser,
 };
 
-type Obj = Vec<JsonValue>;
+/// Object members in document order.
+pub type Obj = Vec<(String, JsonValue)>;
 
 #[derive(Clone, Debug)]
 pub enum JsonValue {
@@ -26,51 +29,101 @@ pub enum JsonValue {
 	Object(Obj),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonKind {
+	Null,
+	Boolean,
+	Str,
+	Num,
+	Array,
+	Object,
+}
+
+impl Display for JsonKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let str = match self {
+			Self::Null => "null",
+			Self::Boolean => "boolean",
+			Self::Str => "string",
+			Self::Num => "number",
+			Self::Array => "array",
+			Self::Object => "object",
+		};
+
+		write!(f, "{str}")
+	}
+}
+
+/// Returned by the `extract_*` accessors when a value has the wrong shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KindMismatch {
+	pub expected: JsonKind,
+	pub found: JsonKind,
+}
+
 impl JsonValue {
-	#[inline]
-	pub fn extract_bool(&self) -> bool {
+	pub fn kind(&self) -> JsonKind {
 		match self {
-			JsonValue::Boolean(b) => *b,
-			_ => unsafe { std::hint::unreachable_unchecked() },
+			JsonValue::Null => JsonKind::Null,
+			JsonValue::Boolean(_) => JsonKind::Boolean,
+			JsonValue::Str(_) => JsonKind::Str,
+			JsonValue::Num(_) => JsonKind::Num,
+			JsonValue::Array(_) => JsonKind::Array,
+			JsonValue::Object(_) => JsonKind::Object,
+		}
+	}
+
+	#[inline]
+	fn mismatch(&self, expected: JsonKind) -> KindMismatch {
+		KindMismatch {
+			expected,
+			found: self.kind(),
 		}
 	}
+
 	#[inline]
-	pub fn extract_str(&self) -> &str {
+	pub fn extract_bool(&self) -> Result<bool, KindMismatch> {
 		match self {
-			JsonValue::Str(s) => s,
-			_ => unsafe { std::hint::unreachable_unchecked() },
+			JsonValue::Boolean(b) => Ok(*b),
+			_ => Err(self.mismatch(JsonKind::Boolean)),
 		}
 	}
 	#[inline]
-	pub fn extract_num(&self) -> i32 {
+	pub fn extract_str(&self) -> Result<&str, KindMismatch> {
 		match self {
-			JsonValue::Num(i) => *i,
-			_ => unsafe { std::hint::unreachable_unchecked() },
+			JsonValue::Str(s) => Ok(s),
+			_ => Err(self.mismatch(JsonKind::Str)),
 		}
 	}
 	#[inline]
-	pub fn extract_array(&self) -> &Vec<JsonValue> {
+	pub fn extract_num(&self) -> Result<i32, KindMismatch> {
 		match self {
-			JsonValue::Array(arr) => arr,
-			_ => unsafe { std::hint::unreachable_unchecked() },
+			JsonValue::Num(i) => Ok(*i),
+			_ => Err(self.mismatch(JsonKind::Num)),
 		}
 	}
 	#[inline]
-	pub fn extract_object(&self) -> &Obj {
+	pub fn extract_array(&self) -> Result<&Vec<JsonValue>, KindMismatch> {
 		match self {
-			JsonValue::Object(obj) => obj,
-			_ => unsafe { std::hint::unreachable_unchecked() },
+			JsonValue::Array(arr) => Ok(arr),
+			_ => Err(self.mismatch(JsonKind::Array)),
 		}
 	}
 	#[inline]
-	pub fn extract_object_key(&self, idx: usize) -> &JsonValue {
+	pub fn extract_object(&self) -> Result<&Obj, KindMismatch> {
 		match self {
-			JsonValue::Object(obj) => &obj[idx],
-			_ => unsafe { std::hint::unreachable_unchecked() },
+			JsonValue::Object(obj) => Ok(obj),
+			_ => Err(self.mismatch(JsonKind::Object)),
 		}
 	}
 }
 
+/// Looks up the first member named `key`.
+#[inline]
+pub fn get<'a>(obj: &'a Obj, key: &str) -> Option<&'a JsonValue> {
+	obj.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
 pub type Stream<'i> = &'i str;
 
 pub fn run<'i, E: ParserError<Stream<'i>> + AddContext<Stream<'i>, &'static str>>(
@@ -194,10 +247,8 @@ fn object<'i, E: ParserError<Stream<'i>> + AddContext<Stream<'i>, &'static str>>
 
 fn key_value<'i, E: ParserError<Stream<'i>> + AddContext<Stream<'i>, &'static str>>(
 	input: &mut Stream<'i>,
-) -> PResult<JsonValue, E> {
-	separated_pair(string, cut_err((ws, ':', ws)), json_value)
-		.map(|(_, v)| v)
-		.parse_next(input)
+) -> PResult<(String, JsonValue), E> {
+	separated_pair(string, cut_err((ws, ':', ws)), json_value).parse_next(input)
 }
 
 fn ws<'i, E: ParserError<Stream<'i>>>(input: &mut Stream<'i>) -> PResult<&'i str, E> {
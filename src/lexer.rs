@@ -0,0 +1,284 @@
+use std::fmt::Display;
+
+use crate::syntax::SyntaxError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+	/// Unsigned, `2147483648` being only valid negated.
+	Int(u32),
+	Str(String),
+	Ident(String),
+	True,
+	False,
+	Let,
+	Fn,
+	If,
+	Else,
+	Print,
+	First,
+	Second,
+	LParen,
+	RParen,
+	LBrace,
+	RBrace,
+	Comma,
+	Semicolon,
+	Arrow,
+	Assign,
+	Plus,
+	Minus,
+	Star,
+	Slash,
+	Percent,
+	EqEq,
+	NotEq,
+	Lt,
+	Gt,
+	Lte,
+	Gte,
+	AndAnd,
+	OrOr,
+	Eof,
+}
+
+impl Display for Token {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let str = match self {
+			Self::Int(i) => return write!(f, "`{i}`"),
+			Self::Str(s) => return write!(f, "{s:?}"),
+			Self::Ident(i) => return write!(f, "`{i}`"),
+			Self::True => "`true`",
+			Self::False => "`false`",
+			Self::Let => "`let`",
+			Self::Fn => "`fn`",
+			Self::If => "`if`",
+			Self::Else => "`else`",
+			Self::Print => "`print`",
+			Self::First => "`first`",
+			Self::Second => "`second`",
+			Self::LParen => "`(`",
+			Self::RParen => "`)`",
+			Self::LBrace => "`{`",
+			Self::RBrace => "`}`",
+			Self::Comma => "`,`",
+			Self::Semicolon => "`;`",
+			Self::Arrow => "`=>`",
+			Self::Assign => "`=`",
+			Self::Plus => "`+`",
+			Self::Minus => "`-`",
+			Self::Star => "`*`",
+			Self::Slash => "`/`",
+			Self::Percent => "`%`",
+			Self::EqEq => "`==`",
+			Self::NotEq => "`!=`",
+			Self::Lt => "`<`",
+			Self::Gt => "`>`",
+			Self::Lte => "`<=`",
+			Self::Gte => "`>=`",
+			Self::AndAnd => "`&&`",
+			Self::OrOr => "`||`",
+			Self::Eof => "end of file",
+		};
+
+		write!(f, "{str}")
+	}
+}
+
+/// A token together with the byte range it covers in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexeme {
+	pub token: Token,
+	pub start: usize,
+	pub end: usize,
+}
+
+pub struct Lexer<'i> {
+	src: &'i str,
+	pos: usize,
+}
+
+impl<'i> Lexer<'i> {
+	pub fn new(src: &'i str) -> Self {
+		Self { src, pos: 0 }
+	}
+
+	pub fn tokenize(mut self) -> Result<Vec<Lexeme>, SyntaxError> {
+		let mut tokens = vec![];
+
+		loop {
+			let lexeme = self.next_token()?;
+			let eof = lexeme.token == Token::Eof;
+			tokens.push(lexeme);
+
+			if eof {
+				break Ok(tokens);
+			}
+		}
+	}
+
+	#[inline]
+	fn peek(&self) -> Option<u8> {
+		self.src.as_bytes().get(self.pos).copied()
+	}
+
+	#[inline]
+	fn peek_nth(&self, n: usize) -> Option<u8> {
+		self.src.as_bytes().get(self.pos + n).copied()
+	}
+
+	fn error(&self, message: impl Into<String>, start: usize) -> SyntaxError {
+		SyntaxError {
+			message: message.into(),
+			start,
+			end: self.pos.max(start + 1).min(self.src.len()),
+		}
+	}
+
+	fn skip_trivia(&mut self) -> Result<(), SyntaxError> {
+		loop {
+			match (self.peek(), self.peek_nth(1)) {
+				(Some(b' ' | b'\t' | b'\r' | b'\n'), _) => self.pos += 1,
+				(Some(b'/'), Some(b'/')) => {
+					while !matches!(self.peek(), Some(b'\n') | None) {
+						self.pos += 1;
+					}
+				}
+				(Some(b'/'), Some(b'*')) => {
+					let start = self.pos;
+					self.pos += 2;
+					loop {
+						match (self.peek(), self.peek_nth(1)) {
+							(Some(b'*'), Some(b'/')) => {
+								self.pos += 2;
+								break;
+							}
+							(Some(_), _) => self.pos += 1,
+							(None, _) => return Err(self.error("unterminated comment", start)),
+						}
+					}
+				}
+				_ => break Ok(()),
+			}
+		}
+	}
+
+	fn next_token(&mut self) -> Result<Lexeme, SyntaxError> {
+		self.skip_trivia()?;
+
+		let start = self.pos;
+		let Some(c) = self.peek() else {
+			return Ok(Lexeme {
+				token: Token::Eof,
+				start,
+				end: start,
+			});
+		};
+
+		let token = match c {
+			b'0'..=b'9' => self.number(start)?,
+			b'"' => self.string(start)?,
+			b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.word(),
+			_ => {
+				let (token, len) = match (c, self.peek_nth(1)) {
+					(b'=', Some(b'>')) => (Token::Arrow, 2),
+					(b'=', Some(b'=')) => (Token::EqEq, 2),
+					(b'!', Some(b'=')) => (Token::NotEq, 2),
+					(b'<', Some(b'=')) => (Token::Lte, 2),
+					(b'>', Some(b'=')) => (Token::Gte, 2),
+					(b'&', Some(b'&')) => (Token::AndAnd, 2),
+					(b'|', Some(b'|')) => (Token::OrOr, 2),
+					(b'=', _) => (Token::Assign, 1),
+					(b'<', _) => (Token::Lt, 1),
+					(b'>', _) => (Token::Gt, 1),
+					(b'+', _) => (Token::Plus, 1),
+					(b'-', _) => (Token::Minus, 1),
+					(b'*', _) => (Token::Star, 1),
+					(b'/', _) => (Token::Slash, 1),
+					(b'%', _) => (Token::Percent, 1),
+					(b'(', _) => (Token::LParen, 1),
+					(b')', _) => (Token::RParen, 1),
+					(b'{', _) => (Token::LBrace, 1),
+					(b'}', _) => (Token::RBrace, 1),
+					(b',', _) => (Token::Comma, 1),
+					(b';', _) => (Token::Semicolon, 1),
+					_ => {
+						let c = self.src[start..].chars().next().unwrap_or_default();
+						self.pos += c.len_utf8();
+						return Err(self.error(format!("unexpected character `{c}`"), start));
+					}
+				};
+				self.pos += len;
+				token
+			}
+		};
+
+		Ok(Lexeme {
+			token,
+			start,
+			end: self.pos,
+		})
+	}
+
+	fn number(&mut self, start: usize) -> Result<Token, SyntaxError> {
+		while let Some(b'0'..=b'9') = self.peek() {
+			self.pos += 1;
+		}
+
+		// the literal is parsed as unsigned so `-2147483648` still fits once negated, the
+		// parser rejects it otherwise
+		match self.src[start..self.pos].parse::<u32>() {
+			Ok(n) if n <= i32::MIN.unsigned_abs() => Ok(Token::Int(n)),
+			_ => Err(self.error("integer literal out of range", start)),
+		}
+	}
+
+	fn string(&mut self, start: usize) -> Result<Token, SyntaxError> {
+		self.pos += 1;
+		let mut string = String::new();
+
+		loop {
+			let Some(c) = self.src[self.pos..].chars().next() else {
+				return Err(self.error("unterminated string", start));
+			};
+			self.pos += c.len_utf8();
+
+			match c {
+				'"' => break Ok(Token::Str(string)),
+				'\\' => {
+					let escaped = match self.peek() {
+						Some(b'"') => '"',
+						Some(b'\\') => '\\',
+						Some(b'n') => '\n',
+						Some(b'r') => '\r',
+						Some(b't') => '\t',
+						Some(b'0') => '\0',
+						_ => return Err(self.error("invalid escape sequence", self.pos - 1)),
+					};
+					self.pos += 1;
+					string.push(escaped);
+				}
+				c => string.push(c),
+			}
+		}
+	}
+
+	fn word(&mut self) -> Token {
+		let start = self.pos;
+		while let Some(b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_') = self.peek() {
+			self.pos += 1;
+		}
+
+		match &self.src[start..self.pos] {
+			"let" => Token::Let,
+			"fn" => Token::Fn,
+			"if" => Token::If,
+			"else" => Token::Else,
+			"true" => Token::True,
+			"false" => Token::False,
+			"print" => Token::Print,
+			"first" => Token::First,
+			"second" => Token::Second,
+			word => Token::Ident(word.to_owned()),
+		}
+	}
+}
@@ -1,84 +1,174 @@
 #![recursion_limit = "1024"]
 
+mod backend;
+mod bytecode;
 mod codegen;
+mod codegen_c;
+mod codegen_js;
+mod codegen_wasm;
+mod diagnostics;
+mod effects;
 mod expr;
+mod interp;
 mod json;
+mod lexer;
+mod optimize;
 mod parser;
+mod resolve;
+mod syntax;
+#[cfg(test)]
+mod testing;
+mod value;
+mod vm;
+mod wasm;
+
+use diagnostics::{Diagnostic, Diagnostics, Source};
+
+struct Args {
+	file_path: String,
+	format: Option<parser::Format>,
+	backend: String,
+	options: backend::Options,
+	emit: bool,
+}
+
+impl Args {
+	const USAGE: &'static str =
+		"usage: rinha [--json | --rinha] [--backend <name>] [--memoize] [--emit] <file>";
+
+	/// `rinha [--json | --rinha] [--backend <name>] [--memoize] [--emit] <file>`
+	///
+	/// The input format is guessed from the file extension unless one of the flags is given.
+	/// `--backend` picks what runs the program, one of [`backend::BACKENDS`]. `--memoize`
+	/// caches the results of pure recursive functions. `--emit` prints the generated code
+	/// or module instead of running it, for backends that generate one.
+	fn parse() -> Self {
+		let mut file_path = None;
+		let mut format = None;
+		let mut backend = backend::BACKENDS[0].to_owned();
+		let mut options = backend::Options::default();
+		let mut emit = false;
+
+		let mut args = std::env::args().skip(1);
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--json" => format = Some(parser::Format::Json),
+				"--rinha" => format = Some(parser::Format::Rinha),
+				"--memoize" => options.memoize = true,
+				"--emit" => emit = true,
+				"--backend" => match args.next() {
+					Some(name) => backend = name,
+					None => Self::usage("missing backend name"),
+				},
+				_ => file_path = Some(arg),
+			}
+		}
+
+		#[cfg(not(debug_assertions))]
+		let file_path = file_path.or_else(|| Some(env!("FILE_PATH").to_owned()));
+
+		let Some(file_path) = file_path else {
+			Self::usage("missing input file")
+		};
+
+		Self {
+			file_path,
+			format,
+			backend,
+			options,
+			emit,
+		}
+	}
+
+	fn usage(message: &str) -> ! {
+		fail(
+			&Source::new("rinha", None),
+			Diagnostic::error(format!("{message}\n{}", Self::USAGE), None).into(),
+		)
+	}
+}
+
+/// Prints the pending diagnostics and exits if any of them is an error.
+fn report(source: &Source, diagnostics: &mut Diagnostics) {
+	let diagnostics = std::mem::take(diagnostics);
+	diagnostics.emit(source, &mut std::io::stderr()).unwrap();
+
+	if diagnostics.has_errors() {
+		std::process::exit(1);
+	}
+}
+
+fn fail(source: &Source, mut diagnostics: Diagnostics) -> ! {
+	report(source, &mut diagnostics);
+	std::process::exit(1)
+}
 
 fn main() {
-	#[cfg(debug_assertions)]
-	let file_path = {
-		let mut args = std::env::args();
-		args.nth(1).unwrap()
+	let args = Args::parse();
+	let path = std::path::Path::new(&args.file_path);
+	let format = args
+		.format
+		.unwrap_or_else(|| parser::Format::from_path(path));
+
+	let mut diagnostics = Diagnostics::new();
+
+	let file = match parser::parse_as(path, format, &mut diagnostics) {
+		Ok(file) => file,
+		Err(err) => {
+			let source = Source::new(&args.file_path, std::fs::read_to_string(path).ok());
+			diagnostics.push(err.into());
+			fail(&source, diagnostics)
+		}
 	};
-	#[cfg(not(debug_assertions))]
-	let file_path = env!("FILE_PATH");
 
-	let file = parser::parse(file_path);
+	let source = match format {
+		parser::Format::Json => Source::for_ast(path, &file.name),
+		parser::Format::Rinha => Source::new(&args.file_path, std::fs::read_to_string(path).ok()),
+	};
+	report(&source, &mut diagnostics);
 
-	let mut code = codegen::Codegen::new().transpile(file.expr);
-	code.push_str("\nHVM_MAIN_CALL = Main");
+	let mut expr = file.expr;
+	resolve::resolve(&mut expr, &mut diagnostics);
+	report(&source, &mut diagnostics);
 
-	#[cfg(debug_assertions)]
-	std::fs::write("main.hvm", code.as_bytes()).unwrap();
-
-	let tids = hvm::runtime::default_heap_tids();
-
-	let file = hvm::language::syntax::read_file(&code).unwrap();
-
-	let book = hvm::language::rulebook::gen_rulebook(&file);
-	let mut prog = hvm::runtime::Program::new();
-	prog.add_book(&book);
-	// prog.add_function(
-	// 	"STD.stringify".to_owned(),
-	// 	hvm::runtime::Function::Compiled {
-	// 		smap: Box::new([true]),
-	// 		visit: |_| false,
-	// 		apply: |ctx| {
-	// 			let arg0 = hvm::runtime::get_loc(ctx.term, 0);
-	// 			if let Some(term) =
-	// 				hvm::language::readback::as_string(ctx.heap, ctx.prog, &[ctx.tid], arg0)
-	// 			{
-	// 				let text = hvm::runtime::make_string(ctx.heap, ctx.tid, &term);
-	// 				hvm::runtime::link(ctx.heap, *ctx.host, text);
-	// 				hvm::runtime::collect(
-	// 					ctx.heap,
-	// 					&ctx.prog.aris,
-	// 					ctx.tid,
-	// 					hvm::runtime::load_ptr(ctx.heap, arg0),
-	// 				);
-	// 				hvm::runtime::free(ctx.heap, ctx.tid, hvm::get_loc(ctx.term, 0), 1);
-	// 			};
-	//
-	// 			return true;
-	// 		},
-	// 	},
-	// );
-
-	let heap = hvm::runtime::new_heap(hvm::runtime::default_heap_size(), tids);
-	let tids = hvm::runtime::new_tids(tids);
-
-	hvm::runtime::link(
-		&heap,
-		0,
-		hvm::runtime::Fun(*book.name_to_id.get("HVM_MAIN_CALL").unwrap(), 0),
-	);
-
-	let host = 0;
-
-	hvm::runtime::normalize(&heap, &prog, &tids, host, false);
+	let program = backend::Program {
+		expr: optimize::optimize(expr),
+	};
 
-	#[cfg(debug_assertions)]
-	let code = format!("{}", hvm::language::readback::as_term(&heap, &prog, host));
+	let Some(mut backend) = backend::by_name(&args.backend, &args.options) else {
+		let message = format!(
+			"unknown backend `{}`, expected one of: {}",
+			args.backend,
+			backend::BACKENDS.join(", ")
+		);
+		fail(&source, Diagnostic::error(message, None).into())
+	};
+	backend.compile(&program, &mut diagnostics);
+	report(&source, &mut diagnostics);
+
+	if args.emit {
+		let Some(code) = backend.emit() else {
+			let message = format!("backend `{}` doesn't generate code", args.backend);
+			fail(&source, Diagnostic::error(message, None).into())
+		};
+		std::io::Write::write_all(&mut std::io::stdout(), code).unwrap();
+		return;
+	}
 
-	hvm::runtime::collect(
-		&heap,
-		&prog.aris,
-		tids[0],
-		hvm::runtime::load_ptr(&heap, host),
-	);
-	hvm::runtime::free(&heap, 0, 0, 1);
+	let result = backend.run(&mut std::io::stdout()).unwrap_or_else(|err| {
+		let diagnostic = match err {
+			backend::RunError::Invalid(err) => {
+				Diagnostic::error(format!("internal error: {err}"), None)
+			}
+			backend::RunError::Runtime { message, span } => {
+				Diagnostic::error(format!("runtime error: {message}"), span)
+			}
+		};
+		fail(&source, diagnostic.into())
+	});
 
 	#[cfg(debug_assertions)]
-	println!("{code}");
+	println!("{result}");
+	#[cfg(not(debug_assertions))]
+	let _ = result;
 }
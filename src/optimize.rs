@@ -0,0 +1,347 @@
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+};
+
+use crate::{
+	effects::{Effect, Effects},
+	expr::{BinOp, Binding, BindingId, Expr, ExprKind},
+};
+
+/// Constant folding over a resolved tree.
+///
+/// Literal `let`s are substituted into their uses and dropped, binary operations on
+/// literals are computed with the language's `i32` wrapping semantics, and `if`s with a
+/// constant condition are replaced by the branch they take. Anything that would fail at
+/// runtime, like a division by zero, is left for the runtime to report.
+#[derive(Default)]
+struct Folder {
+	constants: HashMap<BindingId, ExprKind>,
+}
+
+pub fn optimize(expr: Expr) -> Expr {
+	let expr = Folder::default().fold(expr);
+	remove_dead_lets(expr)
+}
+
+/// Drops `let`s whose binding is never used and whose value can't do anything when
+/// evaluated, like closures and tuples of variables, until there are none left.
+fn remove_dead_lets(mut expr: Expr) -> Expr {
+	loop {
+		// uses are collected over the whole tree, functions can be called before their `let`
+		let mut used = HashSet::new();
+		uses(&expr, &mut used);
+
+		let mut dead = DeadLets {
+			effects: Effects::analyze(&expr),
+			used,
+			removed: false,
+		};
+		expr = dead.remove(expr);
+		if !dead.removed {
+			return expr;
+		}
+	}
+}
+
+fn uses(expr: &Expr, used: &mut HashSet<BindingId>) {
+	if let ExprKind::Variable(var) = &expr.kind {
+		if let Binding::Local(id) = var.binding {
+			used.insert(id);
+		}
+	}
+	expr.children().for_each(|child| uses(child, used));
+}
+
+struct DeadLets {
+	effects: Effects,
+	used: HashSet<BindingId>,
+	removed: bool,
+}
+
+impl DeadLets {
+	fn remove(&mut self, expr: Expr) -> Expr {
+		let Expr { kind, span } = expr;
+
+		let kind = match kind {
+			ExprKind::Let { name, value, next } => {
+				if !self.used.contains(&name.id()) && self.effects.of(&value) == Effect::None {
+					self.removed = true;
+					return self.remove(*next);
+				}
+				ExprKind::Let {
+					name,
+					value: self.remove(*value).into(),
+					next: self.remove(*next).into(),
+				}
+			}
+			ExprKind::Binary { lhs, op, rhs } => ExprKind::Binary {
+				lhs: self.remove(*lhs).into(),
+				op,
+				rhs: self.remove(*rhs).into(),
+			},
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => ExprKind::If {
+				condition: self.remove(*condition).into(),
+				then: self.remove(*then).into(),
+				otherwise: self.remove(*otherwise).into(),
+			},
+			ExprKind::Tuple(first, second) => {
+				ExprKind::Tuple(self.remove(*first).into(), self.remove(*second).into())
+			}
+			ExprKind::Application { callee, args } => ExprKind::Application {
+				callee: self.remove(*callee).into(),
+				args: args.into_iter().map(|arg| self.remove(arg)).collect(),
+			},
+			ExprKind::Abstraction { args, body } => ExprKind::Abstraction {
+				args,
+				body: self.remove(*body).into(),
+			},
+			kind => kind,
+		};
+
+		Expr::new(kind, span)
+	}
+}
+
+#[inline]
+fn is_literal(kind: &ExprKind) -> bool {
+	matches!(
+		kind,
+		ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Str(_)
+	)
+}
+
+/// How two constant values compare, following `STD.compare`: values of different types are
+/// unordered, tuples are compared element by element. `None` if either side isn't known at
+/// compile time, or is a closure, which can't be compared.
+fn compare(lhs: &ExprKind, rhs: &ExprKind) -> Option<Option<Ordering>> {
+	use ExprKind::{Bool, Int, Str, Tuple};
+
+	if !is_constant(lhs) || !is_constant(rhs) {
+		return None;
+	}
+
+	Some(match (lhs, rhs) {
+		(Int(i1), Int(i2)) => Some(i1.cmp(i2)),
+		(Bool(b1), Bool(b2)) => Some(b1.cmp(b2)),
+		(Str(s1), Str(s2)) => Some(s1.cmp(s2)),
+		(Tuple(a, b), Tuple(c, d)) => match compare(&a.kind, &c.kind)? {
+			Some(Ordering::Equal) => compare(&b.kind, &d.kind)?,
+			ord => ord,
+		},
+		_ => None,
+	})
+}
+
+/// Whether `kind` is a literal, or a tuple of constants.
+fn is_constant(kind: &ExprKind) -> bool {
+	match kind {
+		ExprKind::Tuple(first, second) => is_constant(&first.kind) && is_constant(&second.kind),
+		_ => is_literal(kind),
+	}
+}
+
+/// Result of `lhs op rhs` on literals, if it can be known at compile time.
+fn binary(lhs: &ExprKind, op: &BinOp, rhs: &ExprKind) -> Option<ExprKind> {
+	use ExprKind::{Bool, Int, Str};
+
+	Some(match (lhs, op, rhs) {
+		(Int(i1), BinOp::Add, Int(i2)) => Int(i1.wrapping_add(*i2)),
+		(Int(i1), BinOp::Sub, Int(i2)) => Int(i1.wrapping_sub(*i2)),
+		(Int(i1), BinOp::Mul, Int(i2)) => Int(i1.wrapping_mul(*i2)),
+		(Int(_), BinOp::Div | BinOp::Rem, Int(0)) => return None,
+		(Int(i1), BinOp::Div, Int(i2)) => Int(i1.wrapping_div(*i2)),
+		(Int(i1), BinOp::Rem, Int(i2)) => Int(i1.wrapping_rem(*i2)),
+		// `&&` and `||` short-circuit, whatever is on the right is never evaluated
+		(Bool(false), BinOp::And, _) => Bool(false),
+		(Bool(true), BinOp::Or, _) => Bool(true),
+		(Bool(true), BinOp::And, Bool(b)) | (Bool(false), BinOp::Or, Bool(b)) => Bool(*b),
+		(Str(s1), BinOp::Add, Str(s2)) => Str(format!("{s1}{s2}")),
+		(Int(i1), BinOp::Add, Str(s2)) => Str(format!("{i1}{s2}")),
+		(Str(s1), BinOp::Add, Int(i2)) => Str(format!("{s1}{i2}")),
+		(_, BinOp::Eq, _) => Bool(compare(lhs, rhs)? == Some(Ordering::Equal)),
+		(_, BinOp::Neq, _) => Bool(compare(lhs, rhs)? != Some(Ordering::Equal)),
+		// ordering values of different types is left for the runtime to report
+		(_, BinOp::Lt, _) => Bool(compare(lhs, rhs)?? == Ordering::Less),
+		(_, BinOp::Lte, _) => Bool(compare(lhs, rhs)?? != Ordering::Greater),
+		(_, BinOp::Gt, _) => Bool(compare(lhs, rhs)?? == Ordering::Greater),
+		(_, BinOp::Gte, _) => Bool(compare(lhs, rhs)?? != Ordering::Less),
+		_ => return None,
+	})
+}
+
+impl Folder {
+	fn fold(&mut self, expr: Expr) -> Expr {
+		let Expr { kind, span } = expr;
+
+		let kind = match kind {
+			ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Str(_) => kind,
+			ExprKind::Variable(var) => match var.binding {
+				Binding::Local(id) if self.constants.contains_key(&id) => {
+					self.constants[&id].clone()
+				}
+				_ => ExprKind::Variable(var),
+			},
+			ExprKind::Binary { lhs, op, rhs } => {
+				let lhs = self.fold(*lhs);
+				let rhs = self.fold(*rhs);
+
+				match binary(&lhs.kind, &op, &rhs.kind) {
+					Some(kind) => kind,
+					None => ExprKind::Binary {
+						lhs: lhs.into(),
+						op,
+						rhs: rhs.into(),
+					},
+				}
+			}
+			ExprKind::Let { name, value, next } => {
+				let value = self.fold(*value);
+
+				// every use gets the literal, so the binding itself can go
+				if is_literal(&value.kind) {
+					self.constants.insert(name.id(), value.kind);
+					return self.fold(*next);
+				}
+
+				ExprKind::Let {
+					name,
+					value: value.into(),
+					next: self.fold(*next).into(),
+				}
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				let condition = self.fold(*condition);
+
+				match condition.kind {
+					ExprKind::Bool(true) => return self.fold(*then),
+					ExprKind::Bool(false) => return self.fold(*otherwise),
+					_ => ExprKind::If {
+						condition: condition.into(),
+						then: self.fold(*then).into(),
+						otherwise: self.fold(*otherwise).into(),
+					},
+				}
+			}
+			ExprKind::Tuple(first, second) => {
+				ExprKind::Tuple(self.fold(*first).into(), self.fold(*second).into())
+			}
+			ExprKind::Application { callee, args } => ExprKind::Application {
+				callee: self.fold(*callee).into(),
+				args: args.into_iter().map(|arg| self.fold(arg)).collect(),
+			},
+			ExprKind::Abstraction { args, body } => ExprKind::Abstraction {
+				args,
+				body: self.fold(*body).into(),
+			},
+		};
+
+		Expr::new(kind, span)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::optimize;
+	use crate::{
+		diagnostics::Diagnostics,
+		expr::{BinOp, Expr, ExprKind},
+		resolve, syntax,
+	};
+
+	fn fold(src: &str) -> Expr {
+		let mut expr = syntax::parse(src).unwrap();
+		resolve::resolve(&mut expr, &mut Diagnostics::new());
+		optimize(expr)
+	}
+
+	#[test]
+	fn fold_through_lets_and_ifs() {
+		let expr = fold("let x = 2; let y = x * 3; if (y > 5) { y + 1 } else { print(0) }");
+		assert_eq!(expr.kind, ExprKind::Int(7));
+
+		let expr = fold("let s = \"a\"; s + 1 + (2 == 2) + s");
+		let ExprKind::Binary { lhs, op, .. } = expr.kind else {
+			panic!("{expr:?}")
+		};
+		assert_eq!(op, BinOp::Add);
+		let ExprKind::Binary { lhs, rhs, .. } = lhs.kind else {
+			unreachable!()
+		};
+		assert_eq!(
+			(lhs.kind, rhs.kind),
+			(ExprKind::Str("a1".into()), ExprKind::Bool(true))
+		);
+	}
+
+	#[test]
+	fn fold_follows_runtime_semantics() {
+		assert_eq!(fold("2147483647 + 1").kind, ExprKind::Int(i32::MIN));
+		assert_eq!(fold("-2147483648 / -1").kind, ExprKind::Int(i32::MIN));
+		assert_eq!(fold("-7 % 2").kind, ExprKind::Int(-1));
+		assert_eq!(fold("false && print(1 / 0)").kind, ExprKind::Bool(false));
+		assert_eq!(fold("1 == 1 || 1 + true").kind, ExprKind::Bool(true));
+		assert!(matches!(
+			fold("true && 1").kind,
+			ExprKind::Binary { op: BinOp::And, .. }
+		));
+		assert!(matches!(
+			fold("let zero = 0; 1 / zero").kind,
+			ExprKind::Binary { op: BinOp::Div, .. }
+		));
+	}
+
+	#[test]
+	fn fold_comparisons() {
+		assert_eq!(
+			fold("(1, (\"a\", true)) == (1, (\"a\", true))").kind,
+			ExprKind::Bool(true)
+		);
+		assert_eq!(fold("(1, 2) == \"<nil>\"").kind, ExprKind::Bool(false));
+		assert_eq!(fold("(1, 2) != (1, -2)").kind, ExprKind::Bool(true));
+		assert_eq!(fold("\"ab\" < \"b\"").kind, ExprKind::Bool(true));
+		assert_eq!(fold("(1, \"b\") >= (1, \"ab\")").kind, ExprKind::Bool(true));
+		assert_eq!(fold("false < true").kind, ExprKind::Bool(true));
+		assert!(matches!(
+			fold("1 < \"a\"").kind,
+			ExprKind::Binary { op: BinOp::Lt, .. }
+		));
+		assert!(matches!(
+			fold("(1, fn () => 1) == (1, 2)").kind,
+			ExprKind::Binary { op: BinOp::Eq, .. }
+		));
+	}
+
+	#[test]
+	fn remove_dead_lets() {
+		let expr = fold("let f = fn (x) => print(x); let t = (f, f); let u = (1, 2); u");
+		let ExprKind::Let { name, next, .. } = expr.kind else {
+			panic!("{expr:?}")
+		};
+		assert_eq!(name.name, "u");
+		assert!(matches!(next.kind, ExprKind::Variable(_)));
+
+		// evaluating these may print or fail, so they stay even if unused
+		for src in [
+			"let x = print(1); 2",
+			"let x = 1 / (1 - 1); 2",
+			"let f = fn () => 1; let x = f(); 2",
+		] {
+			assert!(matches!(fold(src).kind, ExprKind::Let { .. }), "{src}");
+		}
+		// functions only used by an earlier function are kept
+		let expr = fold("let f = fn () => g(); let g = fn () => 1; f()");
+		let ExprKind::Let { next, .. } = expr.kind else {
+			panic!("{expr:?}")
+		};
+		assert!(matches!(next.kind, ExprKind::Let { .. }));
+	}
+}
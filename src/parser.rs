@@ -1,8 +1,10 @@
-use std::path::Path;
+use std::{fmt::Display, path::Path};
 
 use crate::{
-	expr::{BinOp, Expr, Ident},
-	json::{self, JsonValue},
+	diagnostics::{Diagnostic, Diagnostics},
+	expr::{Expr, ExprKind, Ident, Span},
+	json::{self, JsonKind, JsonValue, KindMismatch, Obj},
+	syntax::{self, SyntaxError},
 };
 
 pub struct File {
@@ -10,189 +12,506 @@ pub struct File {
 	pub expr: Expr,
 }
 
-pub fn parse(file_path: impl AsRef<Path>) -> File {
-	let data = std::fs::read_to_string(file_path).unwrap();
-	let file = json::run::<winnow::error::ErrorKind>(&mut data.as_str()).unwrap();
+/// Input formats understood by the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	/// The pre-parsed AST emitted by the reference implementation.
+	Json,
+	/// Plain `.rinha` source code.
+	Rinha,
+}
+
+impl Format {
+	/// Picks the format from the file extension, defaulting to `.rinha` source.
+	pub fn from_path(file_path: &Path) -> Self {
+		match file_path.extension() {
+			Some(ext) if ext == "json" => Self::Json,
+			_ => Self::Rinha,
+		}
+	}
+}
 
-	let name = file.extract_object_key(0).extract_str();
-	let expr = file.extract_object_key(1).extract_object();
-	let expr = parse_expr(&expr);
+#[derive(Debug)]
+pub enum ParseError {
+	Io(std::io::Error),
+	/// The input is not valid JSON; `offset` is where the reader gave up.
+	Json {
+		offset: usize,
+	},
+	/// The input is not valid `.rinha` source.
+	Syntax(SyntaxError),
+	/// A node in the AST has the wrong JSON kind.
+	Mismatch {
+		path: String,
+		expected: JsonKind,
+		found: JsonKind,
+	},
+	MissingField {
+		path: String,
+	},
+	UnexpectedField {
+		path: String,
+	},
+	DuplicateField {
+		path: String,
+	},
+	UnknownKind {
+		path: String,
+		kind: String,
+	},
+	UnknownOperator {
+		path: String,
+		op: String,
+	},
+	NegativeLocation {
+		path: String,
+		offset: i32,
+	},
+}
+
+impl ParseError {
+	/// Where the error is in the input file, when it can be pinned to a position.
+	pub fn span(&self) -> Option<Span> {
+		match self {
+			Self::Json { offset } => Some(Span::new(*offset, offset + 1)),
+			Self::Syntax(err) => Some(Span::new(err.start, err.end)),
+			_ => None,
+		}
+	}
+}
+
+impl From<ParseError> for Diagnostic {
+	fn from(err: ParseError) -> Self {
+		Diagnostic::error(err.to_string(), err.span())
+	}
+}
+
+impl Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(err) => write!(f, "{err}"),
+			Self::Json { .. } => write!(f, "invalid JSON"),
+			Self::Syntax(err) => write!(f, "{}", err.message),
+			Self::Mismatch {
+				path,
+				expected,
+				found,
+			} => write!(f, "expected {expected} at `{path}`, found {found}"),
+			Self::MissingField { path } => write!(f, "missing field `{path}`"),
+			Self::UnexpectedField { path } => write!(f, "unexpected field `{path}`"),
+			Self::DuplicateField { path } => write!(f, "duplicate field `{path}`"),
+			Self::UnknownKind { path, kind } => write!(f, "unknown kind {kind:?} at `{path}`"),
+			Self::UnknownOperator { path, op } => {
+				write!(f, "unknown operator {op:?} at `{path}`")
+			}
+			Self::NegativeLocation { path, offset } => {
+				write!(f, "negative location {offset} at `{path}`")
+			}
+		}
+	}
+}
+
+impl From<std::io::Error> for ParseError {
+	fn from(err: std::io::Error) -> Self {
+		Self::Io(err)
+	}
+}
+
+impl From<SyntaxError> for ParseError {
+	fn from(err: SyntaxError) -> Self {
+		Self::Syntax(err)
+	}
+}
+
+type PResult<T> = Result<T, ParseError>;
+
+#[derive(Clone, Copy)]
+enum Segment {
+	Key(&'static str),
+	Index(usize),
+}
+
+/// Location of a node inside the JSON document, e.g. `expression.value.parameters[1]`.
+///
+/// Paths are linked through the stack and only rendered when an error is reported.
+#[derive(Clone, Copy)]
+struct JsonPath<'p> {
+	parent: Option<&'p JsonPath<'p>>,
+	segment: Segment,
+}
+
+impl<'p> JsonPath<'p> {
+	fn root(key: &'static str) -> Self {
+		Self {
+			parent: None,
+			segment: Segment::Key(key),
+		}
+	}
 
-	File {
+	fn key(&'p self, key: &'static str) -> Self {
+		Self {
+			parent: Some(self),
+			segment: Segment::Key(key),
+		}
+	}
+
+	fn index(&'p self, idx: usize) -> Self {
+		Self {
+			parent: Some(self),
+			segment: Segment::Index(idx),
+		}
+	}
+
+	fn render(&self) -> String {
+		let mut path = self.parent.map(JsonPath::render).unwrap_or_default();
+		match self.segment {
+			Segment::Key(key) if path.is_empty() => path.push_str(key),
+			Segment::Key(key) => {
+				path.push('.');
+				path.push_str(key);
+			}
+			Segment::Index(idx) => path.push_str(&format!("[{idx}]")),
+		}
+		path
+	}
+
+	#[inline]
+	fn check<T>(&self, value: Result<T, KindMismatch>) -> PResult<T> {
+		value.map_err(|KindMismatch { expected, found }| ParseError::Mismatch {
+			path: self.render(),
+			expected,
+			found,
+		})
+	}
+}
+
+/// Parses a program, reporting anything suspicious but recoverable to `diagnostics`.
+pub fn parse_as(
+	file_path: impl AsRef<Path>,
+	format: Format,
+	diagnostics: &mut Diagnostics,
+) -> PResult<File> {
+	let data = std::fs::read_to_string(&file_path)?;
+
+	match format {
+		Format::Json => parse_json(&data, diagnostics),
+		Format::Rinha => Ok(File {
+			name: file_path.as_ref().display().to_string(),
+			expr: syntax::parse(&data)?,
+		}),
+	}
+}
+
+fn parse_json(data: &str, diagnostics: &mut Diagnostics) -> PResult<File> {
+	let mut input = data;
+	let file = json::run::<winnow::error::ErrorKind>(&mut input).map_err(|_| ParseError::Json {
+		offset: data.len() - input.len(),
+	})?;
+
+	if !input.is_empty() {
+		// spans refer to the `.rinha` program, not to the JSON text, so only the offset is given
+		diagnostics.warning(
+			format!(
+				"ignoring trailing data after the JSON document at byte {}",
+				data.len() - input.len()
+			),
+			None,
+		);
+	}
+
+	let root = JsonPath::root("file");
+	let file = root.check(file.extract_object())?;
+	expect_fields(file, &["name", "expression", "location"], None)?;
+
+	let name = JsonPath::root("name");
+	let name = name.check(field(file, "name", None)?.extract_str())?;
+	let expr = parse_field(file, "expression", None)?;
+
+	Ok(File {
 		name: name.to_owned(),
 		expr,
+	})
+}
+
+/// Path of member `key` of the object at `parent`, or of a top-level member.
+#[inline]
+fn member<'p>(parent: Option<&'p JsonPath<'p>>, key: &'static str) -> JsonPath<'p> {
+	match parent {
+		Some(parent) => parent.key(key),
+		None => JsonPath::root(key),
 	}
 }
 
-fn parse_expr(expr: &[JsonValue]) -> Expr {
-	let kind = expr[0].extract_str();
+#[inline]
+fn field<'a>(obj: &'a Obj, key: &'static str, path: Option<&JsonPath>) -> PResult<&'a JsonValue> {
+	json::get(obj, key).ok_or_else(|| ParseError::MissingField {
+		path: member(path, key).render(),
+	})
+}
+
+#[inline]
+fn str_field<'a>(obj: &'a Obj, key: &'static str, path: &JsonPath) -> PResult<&'a str> {
+	path.key(key)
+		.check(field(obj, key, Some(path))?.extract_str())
+}
+
+#[inline]
+fn num_field(obj: &Obj, key: &'static str, path: &JsonPath) -> PResult<i32> {
+	path.key(key)
+		.check(field(obj, key, Some(path))?.extract_num())
+}
 
-	match kind {
-		"Int" => Expr::Int(expr[1].extract_num()),
-		"Str" => Expr::Str(expr[1].extract_str().to_owned()),
-		"Bool" => Expr::Bool(expr[1].extract_bool()),
-		"Var" => parse_variable(&expr),
-		"Binary" => parse_binary(&expr),
-		"Let" => parse_let(&expr, parse_expr(&expr[2].extract_object()).into()),
-		"If" => parse_if(&expr),
-		"Tuple" => parse_tuple(&expr),
-		"Call" => parse_application(&expr),
-		"Function" => parse_abstraction(&expr),
-		"Print" => parse_native(&expr, "print"),
-		"First" => parse_native(&expr, "first"),
-		"Second" => parse_native(&expr, "second"),
-		_ => panic!("Unknown Kind"),
+/// Rejects members that aren't part of the node's shape, as well as repeated ones.
+fn expect_fields(obj: &Obj, allowed: &[&str], path: Option<&JsonPath>) -> PResult<()> {
+	let render = |key: &str| match path {
+		Some(path) => format!("{}.{key}", path.render()),
+		None => key.to_owned(),
+	};
+
+	for (idx, (key, _)) in obj.iter().enumerate() {
+		if !allowed.contains(&key.as_str()) {
+			return Err(ParseError::UnexpectedField { path: render(key) });
+		}
+		if obj[..idx].iter().any(|(k, _)| k == key) {
+			return Err(ParseError::DuplicateField { path: render(key) });
+		}
 	}
+
+	Ok(())
 }
 
+/// Parses the expression stored in member `key` of `parent`.
 #[inline]
-fn parse_param(value: &JsonValue) -> Ident {
-	Ident::from(value.extract_object_key(0).extract_str().to_owned())
+fn parse_field(parent: &Obj, key: &'static str, path: Option<&JsonPath>) -> PResult<Expr> {
+	let value = field(parent, key, path)?;
+	let path = member(path, key);
+	parse_expr(path.check(value.extract_object())?, &path)
 }
 
+fn parse_expr(expr: &Obj, path: &JsonPath) -> PResult<Expr> {
+	let kind = str_field(expr, "kind", path)?;
+
+	let fields: &[&str] = match kind {
+		"Int" | "Str" | "Bool" | "Print" | "First" | "Second" => &["value"],
+		"Var" => &["text"],
+		"Binary" => &["lhs", "op", "rhs"],
+		"Let" => &["name", "value", "next"],
+		"If" => &["condition", "then", "otherwise"],
+		"Tuple" => &["first", "second"],
+		"Call" => &["callee", "arguments"],
+		"Function" => &["parameters", "value"],
+		kind => {
+			return Err(ParseError::UnknownKind {
+				path: path.key("kind").render(),
+				kind: kind.to_owned(),
+			})
+		}
+	};
+	let allowed = [&["kind", "location"], fields].concat();
+	expect_fields(expr, &allowed, Some(path))?;
+
+	let span = parse_location(expr, path)?;
+	let value = path.key("value");
+
+	let kind = match kind {
+		"Int" => ExprKind::Int(num_field(expr, "value", path)?),
+		"Str" => ExprKind::Str(str_field(expr, "value", path)?.to_owned()),
+		"Bool" => ExprKind::Bool(value.check(field(expr, "value", Some(path))?.extract_bool())?),
+		"Var" => parse_variable(expr, path, span)?,
+		"Binary" => parse_binary(expr, path)?,
+		"Let" => parse_let(expr, path, parse_field(expr, "value", Some(path))?.into())?,
+		"If" => parse_if(expr, path)?,
+		"Tuple" => parse_tuple(expr, path)?,
+		"Call" => parse_application(expr, path)?,
+		"Function" => parse_abstraction(expr, path)?,
+		"Print" => return parse_native(expr, path, "print", span),
+		"First" => return parse_native(expr, path, "first", span),
+		_ => return parse_native(expr, path, "second", span),
+	};
+
+	Ok(Expr::new(kind, span))
+}
+
+/// Reads a node's `location { start, end, filename }`.
 #[inline]
-fn parse_variable(parent: &[JsonValue]) -> Expr {
-	Expr::Variable(Ident::from(parent[1].extract_str().to_owned()))
+fn parse_location(node: &Obj, path: &JsonPath) -> PResult<Span> {
+	let location = field(node, "location", Some(path))?;
+	let path = path.key("location");
+	let location = path.check(location.extract_object())?;
+	expect_fields(location, &["start", "end", "filename"], Some(&path))?;
+
+	let offset = |key| {
+		let offset = num_field(location, key, &path)?;
+		usize::try_from(offset).map_err(|_| ParseError::NegativeLocation {
+			path: path.key(key).render(),
+			offset,
+		})
+	};
+
+	Ok(Span::new(offset("start")?, offset("end")?))
 }
 
 #[inline]
-fn parse_binary(parent: &[JsonValue]) -> Expr {
-	let lhs: Box<Expr> = parse_expr(&parent[1].extract_object()).into();
-	let op = parent[2].extract_str().into();
-	let rhs: Box<Expr> = parse_expr(&parent[3].extract_object()).into();
-
-	match (lhs.as_ref(), op, rhs.as_ref()) {
-		(Expr::Int(i1), BinOp::Add, Expr::Int(i2)) => Expr::Int(i1 + i2),
-		(Expr::Int(i1), BinOp::Sub, Expr::Int(i2)) => Expr::Int(i1 - i2),
-		(Expr::Int(i1), BinOp::Div, Expr::Int(i2)) => Expr::Int(i1 / i2),
-		(Expr::Int(i1), BinOp::Mul, Expr::Int(i2)) => Expr::Int(i1 * i2),
-		(Expr::Int(i1), BinOp::Rem, Expr::Int(i2)) => Expr::Int(i1 % i2),
-		(Expr::Int(i1), BinOp::And, Expr::Int(i2)) => Expr::Int(i1 & i2),
-		(Expr::Int(i1), BinOp::Or, Expr::Int(i2)) => Expr::Int(i1 | i2),
-		(Expr::Int(i1), BinOp::Eq, Expr::Int(i2)) => Expr::Bool(i1 == i2),
-		(Expr::Int(i1), BinOp::Neq, Expr::Int(i2)) => Expr::Bool(i1 != i2),
-		(Expr::Int(i1), BinOp::Lt, Expr::Int(i2)) => Expr::Bool(i1 < i2),
-		(Expr::Int(i1), BinOp::Lte, Expr::Int(i2)) => Expr::Bool(i1 <= i2),
-		(Expr::Int(i1), BinOp::Gt, Expr::Int(i2)) => Expr::Bool(i1 > i2),
-		(Expr::Int(i1), BinOp::Gte, Expr::Int(i2)) => Expr::Bool(i1 >= i2),
-		(Expr::Bool(b1), BinOp::And, Expr::Bool(b2)) => Expr::Bool(b1 & b2),
-		(Expr::Bool(b1), BinOp::Or, Expr::Bool(b2)) => Expr::Bool(b1 | b2),
-		(Expr::Bool(b1), BinOp::Eq, Expr::Bool(b2)) => Expr::Bool(b1 == b2),
-		(Expr::Bool(b1), BinOp::Neq, Expr::Bool(b2)) => Expr::Bool(b1 != b2),
-		(Expr::Bool(b1), BinOp::Lt, Expr::Bool(b2)) => Expr::Bool(b1 < b2),
-		(Expr::Bool(b1), BinOp::Lte, Expr::Bool(b2)) => Expr::Bool(b1 <= b2),
-		(Expr::Bool(b1), BinOp::Gt, Expr::Bool(b2)) => Expr::Bool(b1 > b2),
-		(Expr::Bool(b1), BinOp::Gte, Expr::Bool(b2)) => Expr::Bool(b1 >= b2),
-		(Expr::Str(s1), BinOp::Add, Expr::Str(s2)) => Expr::Str(format!("{s1}{s2}")),
-		(Expr::Int(i1), BinOp::Add, Expr::Str(s2)) => Expr::Str(format!("{i1}{s2}")),
-		(Expr::Str(s1), BinOp::Add, Expr::Int(i2)) => Expr::Str(format!("{s1}{i2}")),
-		(Expr::Bool(b1), BinOp::Add, Expr::Str(s2)) => Expr::Str(format!("{b1}{s2}")),
-		(Expr::Str(s1), BinOp::Add, Expr::Bool(b2)) => Expr::Str(format!("{s1}{b2}")),
-		(_, op, _) => Expr::Binary { lhs, op, rhs },
-	}
+fn parse_param(value: &JsonValue, path: &JsonPath) -> PResult<Ident> {
+	let param = path.check(value.extract_object())?;
+	expect_fields(param, &["text", "location"], Some(path))?;
+	let name = str_field(param, "text", path)?;
+
+	Ok(Ident::new(name, parse_location(param, path)?))
 }
 
 #[inline]
-fn parse_let(parent: &[JsonValue], value: Box<Expr>) -> Expr {
-	let name = parse_param(&parent[1]);
-	let next = parse_expr(&parent[3].extract_object()).into();
+fn parse_variable(parent: &Obj, path: &JsonPath, span: Span) -> PResult<ExprKind> {
+	let name = str_field(parent, "text", path)?;
 
-	Expr::Let { name, value, next }
+	Ok(ExprKind::Variable(Ident::new(name, span)))
 }
 
 #[inline]
-fn parse_if(parent: &[JsonValue]) -> Expr {
-	let condition = parse_expr(&parent[1].extract_object()).into();
-	let then = parse_expr(&parent[2].extract_object()).into();
-	let otherwise = parse_expr(&parent[3].extract_object()).into();
+fn parse_binary(parent: &Obj, path: &JsonPath) -> PResult<ExprKind> {
+	let lhs = parse_field(parent, "lhs", Some(path))?.into();
+	let op = str_field(parent, "op", path)?;
+	let op = op.parse().map_err(|_| ParseError::UnknownOperator {
+		path: path.key("op").render(),
+		op: op.to_owned(),
+	})?;
+	let rhs = parse_field(parent, "rhs", Some(path))?.into();
 
-	Expr::If {
+	Ok(ExprKind::Binary { lhs, op, rhs })
+}
+
+#[inline]
+fn parse_let(parent: &Obj, path: &JsonPath, value: Box<Expr>) -> PResult<ExprKind> {
+	let name = parse_param(field(parent, "name", Some(path))?, &path.key("name"))?;
+	let next = parse_field(parent, "next", Some(path))?.into();
+
+	Ok(ExprKind::Let { name, value, next })
+}
+
+#[inline]
+fn parse_if(parent: &Obj, path: &JsonPath) -> PResult<ExprKind> {
+	let condition = parse_field(parent, "condition", Some(path))?.into();
+	let then = parse_field(parent, "then", Some(path))?.into();
+	let otherwise = parse_field(parent, "otherwise", Some(path))?.into();
+
+	Ok(ExprKind::If {
 		condition,
 		then,
 		otherwise,
-	}
+	})
 }
 
 #[inline]
-fn parse_tuple(parent: &[JsonValue]) -> Expr {
-	let first = parse_expr(&parent[1].extract_object()).into();
-	let second = parse_expr(&parent[2].extract_object()).into();
+fn parse_tuple(parent: &Obj, path: &JsonPath) -> PResult<ExprKind> {
+	let first = parse_field(parent, "first", Some(path))?.into();
+	let second = parse_field(parent, "second", Some(path))?.into();
 
-	Expr::Tuple(first, second)
+	Ok(ExprKind::Tuple(first, second))
 }
 
 #[inline]
-fn parse_application(parent: &[JsonValue]) -> Expr {
-	let callee = parse_expr(parent[1].extract_object()).into();
+fn parse_application(parent: &Obj, path: &JsonPath) -> PResult<ExprKind> {
+	let callee = parse_field(parent, "callee", Some(path))?.into();
 
-	let args = parent[2]
-		.extract_array()
+	let arguments = path.key("arguments");
+	let args = arguments
+		.check(field(parent, "arguments", Some(path))?.extract_array())?
 		.iter()
-		.map(|x| parse_expr(&JsonValue::extract_object(x)))
-		.collect();
+		.enumerate()
+		.map(|(idx, arg)| {
+			let path = arguments.index(idx);
+			parse_expr(path.check(arg.extract_object())?, &path)
+		})
+		.collect::<PResult<_>>()?;
 
-	Expr::Application { callee, args }
+	Ok(ExprKind::Application { callee, args })
 }
 
 #[inline]
-fn parse_abstraction(parent: &[JsonValue]) -> Expr {
-	let args = parent[1].extract_array().iter().map(parse_param).collect();
+fn parse_abstraction(parent: &Obj, path: &JsonPath) -> PResult<ExprKind> {
+	let parameters = path.key("parameters");
+	let args = parameters
+		.check(field(parent, "parameters", Some(path))?.extract_array())?
+		.iter()
+		.enumerate()
+		.map(|(idx, param)| parse_param(param, &parameters.index(idx)))
+		.collect::<PResult<_>>()?;
 
-	let body = parse_expr(&parent[2].extract_object()).into();
+	let body = parse_field(parent, "value", Some(path))?.into();
 
-	Expr::Abstraction { args, body }
+	Ok(ExprKind::Abstraction { args, body })
 }
 
-fn parse_native(expr: &[JsonValue], name: &'static str) -> Expr {
-	let value = parse_expr(&expr[1].extract_object());
+fn parse_native(expr: &Obj, path: &JsonPath, name: &'static str, span: Span) -> PResult<Expr> {
+	let value = parse_field(expr, "value", Some(path))?;
+	Ok(native(name, value, span))
+}
 
-	Expr::Application {
-		callee: Expr::Variable(Ident::from(name.to_string())).into(),
-		args: vec![value],
-	}
+/// Builtins are represented as calls to a variable with the builtin's name,
+/// the variable spanning the keyword at the start of the call.
+pub(crate) fn native(name: &'static str, value: Expr, span: Span) -> Expr {
+	let keyword = Span::new(span.start, span.start + name.len());
+	let callee = Expr::new(ExprKind::Variable(Ident::new(name, keyword)), keyword);
+
+	Expr::new(
+		ExprKind::Application {
+			callee: callee.into(),
+			args: vec![value],
+		},
+		span,
+	)
 }
 
 #[cfg(test)]
 mod tests {
-	use crate::expr::{BinOp, Expr, Ident};
+	use crate::expr::{BinOp, Expr, ExprKind, Ident, Span};
+
+	use super::{parse_as, parse_json, Format};
+	use crate::diagnostics::{Diagnostics, Severity};
 
-	use super::parse;
+	fn node(kind: ExprKind) -> Expr {
+		Expr::new(kind, Span::default())
+	}
+
+	fn ident(name: &str) -> Ident {
+		Ident::new(name, Span::default())
+	}
 
 	#[test]
 	fn parse_fib() {
-		let file = parse("test_files/fib.json");
+		let file = parse_as("test_files/fib.json", Format::Json, &mut Diagnostics::new()).unwrap();
 		assert_eq!(
-			file.expr,
-			Expr::Let {
-				name: Ident::from("fib".to_string()),
-				value: Expr::Abstraction {
-					args: vec![Ident::from("n".to_string())],
-					body: Expr::If {
-						condition: Expr::Binary {
-							lhs: Expr::Variable(Ident::from("n".to_string())).into(),
+			file.expr.without_spans(),
+			node(ExprKind::Let {
+				name: ident("fib"),
+				value: ExprKind::Abstraction {
+					args: vec![ident("n")],
+					body: ExprKind::If {
+						condition: ExprKind::Binary {
+							lhs: ExprKind::Variable(ident("n")).into(),
 							op: BinOp::Lt,
-							rhs: Expr::Int(2).into()
+							rhs: ExprKind::Int(2).into()
 						}
 						.into(),
-						then: Expr::Variable(Ident::from("n".to_string())).into(),
-						otherwise: Expr::Binary {
-							lhs: Expr::Application {
-								callee: Expr::Variable(Ident::from("fib".to_string())).into(),
-								args: vec![Expr::Binary {
-									lhs: Expr::Variable(Ident::from("n".to_string())).into(),
+						then: ExprKind::Variable(ident("n")).into(),
+						otherwise: ExprKind::Binary {
+							lhs: ExprKind::Application {
+								callee: ExprKind::Variable(ident("fib")).into(),
+								args: vec![node(ExprKind::Binary {
+									lhs: ExprKind::Variable(ident("n")).into(),
 									op: BinOp::Sub,
-									rhs: Expr::Int(1).into()
-								}]
+									rhs: ExprKind::Int(1).into()
+								})]
 							}
 							.into(),
 							op: BinOp::Add,
-							rhs: Expr::Application {
-								callee: Expr::Variable(Ident::from("fib".to_string())).into(),
-								args: vec![Expr::Binary {
-									lhs: Expr::Variable(Ident::from("n".to_string())).into(),
+							rhs: ExprKind::Application {
+								callee: ExprKind::Variable(ident("fib")).into(),
+								args: vec![node(ExprKind::Binary {
+									lhs: ExprKind::Variable(ident("n")).into(),
 									op: BinOp::Sub,
-									rhs: Expr::Int(2).into()
-								}]
+									rhs: ExprKind::Int(2).into()
+								})]
 							}
 							.into(),
 						}
@@ -201,15 +520,131 @@ mod tests {
 					.into()
 				}
 				.into(),
-				next: Expr::Application {
-					callee: Expr::Variable(Ident::from("print".to_string())).into(),
-					args: vec![Expr::Application {
-						callee: Expr::Variable(Ident::from("fib".to_string())).into(),
-						args: vec![Expr::Int(10)]
-					}]
+				next: ExprKind::Application {
+					callee: ExprKind::Variable(ident("print")).into(),
+					args: vec![node(ExprKind::Application {
+						callee: ExprKind::Variable(ident("fib")).into(),
+						args: vec![node(ExprKind::Int(10))]
+					})]
 				}
 				.into()
-			}
+			})
+		)
+	}
+
+	#[test]
+	fn parse_fib_spans() {
+		let file = parse_as("test_files/fib.json", Format::Json, &mut Diagnostics::new()).unwrap();
+		assert_eq!(file.expr.span, Span::new(0, 105));
+
+		let ExprKind::Let { name, value, .. } = file.expr.kind else {
+			panic!("expected a let");
+		};
+		assert_eq!(name.span, Span::new(4, 7));
+
+		let ExprKind::Abstraction { args, .. } = value.kind else {
+			panic!("expected a function");
+		};
+		assert_eq!(args[0].span, Span::new(14, 15));
+	}
+
+	#[test]
+	fn parse_reports_bad_nodes() {
+		let err = |json: &str| {
+			parse_json(json, &mut Diagnostics::new())
+				.err()
+				.unwrap()
+				.to_string()
+		};
+		let location = r#""location": { "start": 0, "end": 1, "filename": "a.rinha" }"#;
+
+		assert_eq!(
+			err(&format!(
+				r#"{{ "name": "a.rinha", "expression": {{ "kind": "Function", "parameters": [
+					{{ "text": "a", {location} }}, 1
+				], "value": {{ "kind": "Int", "value": 1, {location} }}, {location} }}, {location} }}"#
+			)),
+			"expected object at `expression.parameters[1]`, found number"
+		);
+		assert_eq!(
+			err(&format!(
+				r#"{{ "name": "a.rinha", "expression": {{ "kind": "Binary",
+					"lhs": {{ "kind": "Int", "value": 1, {location} }}, "op": "Pow",
+					"rhs": {{ "kind": "Int", "value": 2, {location} }}, {location} }}, {location} }}"#
+			)),
+			"unknown operator \"Pow\" at `expression.op`"
+		);
+		assert_eq!(
+			err(&format!(
+				r#"{{ "name": "a.rinha", "expression": {{ "kind": "Loop", {location} }}, {location} }}"#
+			)),
+			"unknown kind \"Loop\" at `expression.kind`"
+		);
+		assert_eq!(
+			err(&format!(
+				r#"{{ "name": "a.rinha", "expression": {{ "kind": "Tuple",
+					"first": {{ "kind": "Int", "value": 1, {location} }}, {location} }}, {location} }}"#
+			)),
+			"missing field `expression.second`"
+		);
+		assert_eq!(
+			err(&format!(
+				r#"{{ "name": "a.rinha", "expression": {{ "kind": "Var", "text": "x", "type": "int",
+					{location} }}, {location} }}"#
+			)),
+			"unexpected field `expression.type`"
+		);
+		assert_eq!(
+			err(&format!(
+				r#"{{ "name": "a.rinha", "expression": {{ "kind": "Int", "value": 1,
+					"location": {{ "start": -1, "end": 1, "filename": "a.rinha" }} }}, {location} }}"#
+			)),
+			"negative location -1 at `expression.location.start`"
+		);
+		assert!(matches!(
+			parse_json(r#"{ "name": "a.rinha", "#, &mut Diagnostics::new()),
+			Err(super::ParseError::Json { .. })
+		));
+	}
+
+	#[test]
+	fn parse_any_field_order() {
+		let file = parse_json(
+			r#"{
+				"location": { "filename": "a.rinha", "end": 5, "start": 0 },
+				"expression": {
+					"location": { "start": 0, "end": 5, "filename": "a.rinha" },
+					"rhs": { "value": 2, "kind": "Int", "location": { "start": 4, "end": 5, "filename": "a.rinha" } },
+					"op": "Sub",
+					"kind": "Binary",
+					"lhs": { "location": { "start": 0, "end": 1, "filename": "a.rinha" }, "text": "x", "kind": "Var" }
+				},
+				"name": "a.rinha"
+			}"#,
+			&mut Diagnostics::new(),
 		)
+		.unwrap();
+
+		assert_eq!(file.name, "a.rinha");
+		assert_eq!(
+			file.expr.without_spans(),
+			node(ExprKind::Binary {
+				lhs: ExprKind::Variable(ident("x")).into(),
+				op: BinOp::Sub,
+				rhs: ExprKind::Int(2).into(),
+			})
+		);
+	}
+
+	#[test]
+	fn parse_warns_on_trailing_data() {
+		let mut diagnostics = Diagnostics::new();
+		let file = parse_as("test_files/test.json", Format::Json, &mut diagnostics).unwrap();
+
+		assert_eq!(file.name, "source.rinha");
+		let warnings = diagnostics.iter().collect::<Vec<_>>();
+		assert_eq!(warnings.len(), 1);
+		assert_eq!(warnings[0].severity, Severity::Warning);
+		assert!(!diagnostics.has_errors());
 	}
 }
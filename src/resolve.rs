@@ -0,0 +1,350 @@
+use crate::{
+	diagnostics::Diagnostics,
+	expr::{Binding, BindingId, Builtin, Expr, ExprKind, Ident},
+};
+
+struct Local {
+	name: String,
+	id: BindingId,
+	used: bool,
+}
+
+/// A top-level `let` the resolver has not reached yet.
+struct Ahead {
+	name: String,
+	id: BindingId,
+	function: bool,
+}
+
+/// Binds every identifier in a tree to the binder it refers to.
+///
+/// Each binder gets a fresh [`BindingId`], so shadowed names end up with distinct ids.
+/// Unbound identifiers are reported and left [`Binding::Unresolved`].
+///
+/// Top-level functions are known before their `let` is reached, so function bodies can
+/// call functions defined further down, and mutually recursive functions work.
+struct Resolver<'d> {
+	diagnostics: &'d mut Diagnostics,
+	scope: Vec<Local>,
+	next_id: u32,
+	/// Top-level `let`s still to come, the nearest one last.
+	ahead: Vec<Ahead>,
+	/// Binders of `ahead` that were already referred to.
+	used_ahead: Vec<BindingId>,
+	/// Whether the expression being resolved continues the top-level `let` chain.
+	toplevel: bool,
+	/// How many function bodies deep the resolver is.
+	functions: usize,
+}
+
+/// Resolves `expr` in place, returning how many binders it has.
+pub fn resolve(expr: &mut Expr, diagnostics: &mut Diagnostics) -> u32 {
+	let mut resolver = Resolver {
+		diagnostics,
+		scope: vec![],
+		next_id: 0,
+		ahead: vec![],
+		used_ahead: vec![],
+		toplevel: true,
+		functions: 0,
+	};
+
+	let mut next = &*expr;
+	while let ExprKind::Let {
+		name,
+		value,
+		next: rest,
+	} = &next.kind
+	{
+		let id = resolver.fresh();
+		resolver.ahead.push(Ahead {
+			name: name.name.clone(),
+			id,
+			function: matches!(value.kind, ExprKind::Abstraction { .. }),
+		});
+		next = rest;
+	}
+	resolver.ahead.reverse();
+
+	resolver.resolve_expr(expr);
+	resolver.next_id
+}
+
+impl Resolver<'_> {
+	fn fresh(&mut self) -> BindingId {
+		let id = BindingId(self.next_id);
+		self.next_id += 1;
+		id
+	}
+
+	fn declare(&mut self, ident: &mut Ident, id: BindingId) {
+		ident.binding = Binding::Local(id);
+		self.scope.push(Local {
+			name: ident.name.clone(),
+			id,
+			used: self.used_ahead.contains(&id),
+		});
+	}
+
+	/// Resolves `ident`, called with `args` arguments if it is the callee of a call.
+	///
+	/// Builtins aren't values, so they must be called, with one argument.
+	fn lookup(&mut self, ident: &mut Ident, args: Option<usize>) {
+		if let Some(local) = self.scope.iter_mut().rev().find(|l| l.name == ident.name) {
+			local.used = true;
+			ident.binding = Binding::Local(local.id);
+		} else if let Some(builtin) = Builtin::ALL.into_iter().find(|b| b.name() == ident.name) {
+			match args {
+				Some(1) => ident.binding = Binding::Builtin(builtin),
+				Some(args) => self.diagnostics.error(
+					format!("`{}` takes 1 argument, but {args} were given", ident.name),
+					ident.span,
+				),
+				None => self.diagnostics.error(
+					format!("builtin `{}` can only be called", ident.name),
+					ident.span,
+				),
+			}
+		} else if let Some(ahead) = self.ahead.iter().rev().find(|a| a.name == ident.name) {
+			// only functions can be called before their definition, and only from
+			// function bodies, which don't run until the definition is reached
+			if ahead.function && self.functions > 0 {
+				ident.binding = Binding::Local(ahead.id);
+				self.used_ahead.push(ahead.id);
+			} else {
+				self.diagnostics.error(
+					format!("`{}` is used before its definition", ident.name),
+					ident.span,
+				);
+			}
+		} else {
+			self.diagnostics
+				.error(format!("unbound variable `{}`", ident.name), ident.span);
+		}
+	}
+
+	fn resolve_expr(&mut self, expr: &mut Expr) {
+		let toplevel = std::mem::take(&mut self.toplevel);
+
+		match &mut expr.kind {
+			ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Str(_) => {}
+			ExprKind::Variable(var) => self.lookup(var, None),
+			ExprKind::Binary { lhs, rhs, .. } => {
+				self.resolve_expr(lhs);
+				self.resolve_expr(rhs);
+			}
+			ExprKind::Let { name, value, next } => {
+				let id = match toplevel {
+					true => self.ahead.pop().unwrap().id,
+					false => self.fresh(),
+				};
+
+				// functions may refer to themselves, other values only see the outer scope
+				if matches!(value.kind, ExprKind::Abstraction { .. }) {
+					self.declare(name, id);
+					self.resolve_expr(value);
+				} else {
+					self.resolve_expr(value);
+					self.declare(name, id);
+				}
+
+				self.toplevel = toplevel;
+				self.resolve_expr(next);
+
+				let local = self.scope.pop().unwrap();
+				if !local.used && !local.name.starts_with('_') {
+					self.diagnostics
+						.warning(format!("unused variable `{}`", local.name), name.span);
+				}
+			}
+			ExprKind::If {
+				condition,
+				then,
+				otherwise,
+			} => {
+				self.resolve_expr(condition);
+				self.resolve_expr(then);
+				self.resolve_expr(otherwise);
+			}
+			ExprKind::Tuple(first, second) => {
+				self.resolve_expr(first);
+				self.resolve_expr(second);
+			}
+			ExprKind::Application { callee, args } => {
+				match &mut callee.kind {
+					ExprKind::Variable(var) => self.lookup(var, Some(args.len())),
+					_ => self.resolve_expr(callee),
+				}
+				for arg in args {
+					self.resolve_expr(arg);
+				}
+			}
+			ExprKind::Abstraction { args, body } => {
+				for (idx, arg) in args.iter().enumerate() {
+					if args[..idx].iter().any(|a| a.name == arg.name) {
+						self.diagnostics
+							.error(format!("duplicate parameter `{}`", arg.name), arg.span);
+					}
+				}
+
+				let depth = self.scope.len();
+				for arg in args.iter_mut() {
+					let id = self.fresh();
+					self.declare(arg, id);
+				}
+
+				self.functions += 1;
+				self.resolve_expr(body);
+				self.functions -= 1;
+				self.scope.truncate(depth);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::resolve;
+	use crate::{
+		diagnostics::{Diagnostics, Severity},
+		expr::{Binding, Builtin, Expr, ExprKind, Ident, Span},
+		syntax,
+	};
+
+	#[test]
+	fn resolve_shadowing() {
+		let mut expr =
+			syntax::parse("let x = 1; let x = x + 1; let f = fn(x) => x; print(f(x))").unwrap();
+		let mut diagnostics = Diagnostics::new();
+		assert_eq!(resolve(&mut expr, &mut diagnostics), 4);
+		assert!(diagnostics.iter().next().is_none());
+
+		let ExprKind::Let { name: x0, next, .. } = &expr.kind else {
+			unreachable!()
+		};
+		let ExprKind::Let {
+			name: x1,
+			value,
+			next,
+		} = &next.kind
+		else {
+			unreachable!()
+		};
+		let ExprKind::Binary { lhs, .. } = &value.kind else {
+			unreachable!()
+		};
+		let ExprKind::Variable(use0) = &lhs.kind else {
+			unreachable!()
+		};
+		assert_eq!(use0.binding, x0.binding);
+		assert_ne!(x0.binding, x1.binding);
+
+		let ExprKind::Let { next, .. } = &next.kind else {
+			unreachable!()
+		};
+		let ExprKind::Application { callee, args } = &next.kind else {
+			unreachable!()
+		};
+		let ExprKind::Variable(print) = &callee.kind else {
+			unreachable!()
+		};
+		assert_eq!(print.binding, Binding::Builtin(Builtin::Print));
+
+		let ExprKind::Application { args, .. } = &args[0].kind else {
+			unreachable!()
+		};
+		let ExprKind::Variable(use1) = &args[0].kind else {
+			unreachable!()
+		};
+		assert_eq!(use1.binding, x1.binding);
+	}
+
+	#[test]
+	fn resolve_reports_every_unbound_name() {
+		let mut expr =
+			syntax::parse("let f = fn(a) => a + b; let _ = f(c); let unused = 1; f(a)").unwrap();
+		let mut diagnostics = Diagnostics::new();
+		resolve(&mut expr, &mut diagnostics);
+
+		let reported = diagnostics
+			.iter()
+			.map(|d| (d.severity, d.message.as_str(), d.span.unwrap().start))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			reported,
+			[
+				(Severity::Error, "unbound variable `b`", 21),
+				(Severity::Error, "unbound variable `c`", 34),
+				(Severity::Error, "unbound variable `a`", 56),
+				(Severity::Warning, "unused variable `unused`", 42),
+			]
+		);
+	}
+
+	#[test]
+	fn resolve_forward_references() {
+		let mut expr = syntax::parse(
+			"let f = fn(n) => g(n) + x; let y = g(1); let x = 1; let g = fn(n) => f(n); y",
+		)
+		.unwrap();
+		let mut diagnostics = Diagnostics::new();
+		resolve(&mut expr, &mut diagnostics);
+
+		let reported = diagnostics
+			.iter()
+			.map(|d| (d.message.as_str(), d.span.unwrap().start))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			reported,
+			[
+				("`x` is used before its definition", 24),
+				("`g` is used before its definition", 35),
+				("unused variable `x`", 45),
+			]
+		);
+	}
+
+	#[test]
+	fn resolve_builtins_only_as_callees() {
+		// `.rinha` source always calls builtins, JSON ASTs can name them anywhere
+		let var = |name: &str, start| {
+			let span = Span::new(start, start + name.len());
+			Expr::new(ExprKind::Variable(Ident::new(name, span)), span)
+		};
+		let call = |callee: Expr, args: Vec<Expr>| {
+			let span = callee.span;
+			Expr::new(
+				ExprKind::Application {
+					callee: callee.into(),
+					args,
+				},
+				span,
+			)
+		};
+
+		// let p = print; p(first(1, 2))
+		let first = call(var("first", 17), vec![var("p", 23), var("p", 26)]);
+		let mut expr = Expr::new(
+			ExprKind::Let {
+				name: Ident::new("p", Span::new(4, 5)),
+				value: var("print", 8).into(),
+				next: call(var("p", 15), vec![first]).into(),
+			},
+			Span::new(0, 30),
+		);
+		let mut diagnostics = Diagnostics::new();
+		resolve(&mut expr, &mut diagnostics);
+
+		let reported = diagnostics
+			.iter()
+			.map(|d| (d.message.as_str(), d.span.unwrap().start))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			reported,
+			[
+				("builtin `print` can only be called", 8),
+				("`first` takes 1 argument, but 2 were given", 17),
+			]
+		);
+	}
+}
@@ -0,0 +1,378 @@
+use std::fmt::Display;
+
+use crate::{
+	expr::{BinOp, Expr, ExprKind, Ident, Span},
+	lexer::{Lexeme, Lexer, Token},
+	parser,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+	pub message: String,
+	pub start: usize,
+	pub end: usize,
+}
+
+impl Display for SyntaxError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} at {}..{}", self.message, self.start, self.end)
+	}
+}
+
+/// Parses `.rinha` source code into the same tree the JSON AST reader produces.
+pub fn parse(src: &str) -> Result<Expr, SyntaxError> {
+	let tokens = Lexer::new(src).tokenize()?;
+	let mut parser = Parser { tokens, pos: 0 };
+
+	let expr = parser.term()?;
+	parser.expect(Token::Eof)?;
+
+	Ok(expr)
+}
+
+fn found(expected: &str, lexeme: &Lexeme) -> SyntaxError {
+	let Lexeme { token, start, end } = lexeme;
+	SyntaxError {
+		message: format!("expected {expected}, found {token}"),
+		start: *start,
+		end: *end,
+	}
+}
+
+struct Parser {
+	tokens: Vec<Lexeme>,
+	pos: usize,
+}
+
+impl Parser {
+	#[inline]
+	fn peek(&self) -> &Token {
+		&self.tokens[self.pos].token
+	}
+
+	/// Start of the next token.
+	#[inline]
+	fn start(&self) -> usize {
+		self.tokens[self.pos].start
+	}
+
+	/// End of the last consumed token.
+	#[inline]
+	fn end(&self) -> usize {
+		self.pos
+			.checked_sub(1)
+			.map_or(0, |prev| self.tokens[prev].end)
+	}
+
+	#[inline]
+	fn span_from(&self, start: usize) -> Span {
+		Span::new(start, self.end())
+	}
+
+	fn bump(&mut self) -> Lexeme {
+		let lexeme = self.tokens[self.pos].clone();
+		if lexeme.token != Token::Eof {
+			self.pos += 1;
+		}
+		lexeme
+	}
+
+	fn eat(&mut self, token: Token) -> bool {
+		if *self.peek() == token {
+			self.bump();
+			true
+		} else {
+			false
+		}
+	}
+
+	fn expect(&mut self, token: Token) -> Result<Lexeme, SyntaxError> {
+		if *self.peek() == token {
+			Ok(self.bump())
+		} else {
+			Err(self.unexpected(&token.to_string()))
+		}
+	}
+
+	fn unexpected(&self, expected: &str) -> SyntaxError {
+		found(expected, &self.tokens[self.pos])
+	}
+
+	fn ident(&mut self) -> Result<Ident, SyntaxError> {
+		match self.peek().clone() {
+			Token::Ident(name) => {
+				let lexeme = self.bump();
+				Ok(Ident::new(name, Span::new(lexeme.start, lexeme.end)))
+			}
+			_ => Err(self.unexpected("identifier")),
+		}
+	}
+
+	fn term(&mut self) -> Result<Expr, SyntaxError> {
+		let start = self.start();
+		if !self.eat(Token::Let) {
+			return self.binary(0);
+		}
+
+		let name = self.ident()?;
+		self.expect(Token::Assign)?;
+		let value = self.term()?.into();
+		self.expect(Token::Semicolon)?;
+		let next = self.term()?.into();
+
+		Ok(Expr::new(
+			ExprKind::Let { name, value, next },
+			self.span_from(start),
+		))
+	}
+
+	/// Precedence climbing over the binary operators, loosest first.
+	fn binary(&mut self, level: usize) -> Result<Expr, SyntaxError> {
+		const LEVELS: &[&[(Token, BinOp)]] = &[
+			&[(Token::OrOr, BinOp::Or)],
+			&[(Token::AndAnd, BinOp::And)],
+			&[(Token::EqEq, BinOp::Eq), (Token::NotEq, BinOp::Neq)],
+			&[
+				(Token::Lt, BinOp::Lt),
+				(Token::Gt, BinOp::Gt),
+				(Token::Lte, BinOp::Lte),
+				(Token::Gte, BinOp::Gte),
+			],
+			&[(Token::Plus, BinOp::Add), (Token::Minus, BinOp::Sub)],
+			&[
+				(Token::Star, BinOp::Mul),
+				(Token::Slash, BinOp::Div),
+				(Token::Percent, BinOp::Rem),
+			],
+		];
+
+		let Some(ops) = LEVELS.get(level) else {
+			return self.call();
+		};
+
+		let mut lhs = self.binary(level + 1)?;
+		while let Some((_, op)) = ops.iter().find(|(token, _)| token == self.peek()) {
+			self.bump();
+			let rhs = self.binary(level + 1)?;
+			let span = lhs.span.to(rhs.span);
+			let kind = ExprKind::Binary {
+				lhs: lhs.into(),
+				op: op.clone(),
+				rhs: rhs.into(),
+			};
+			lhs = Expr::new(kind, span);
+		}
+
+		Ok(lhs)
+	}
+
+	fn call(&mut self) -> Result<Expr, SyntaxError> {
+		let mut callee = self.primary()?;
+
+		while self.eat(Token::LParen) {
+			let mut args = vec![];
+			if !self.eat(Token::RParen) {
+				loop {
+					args.push(self.term()?);
+					if self.eat(Token::RParen) {
+						break;
+					}
+					self.expect(Token::Comma)?;
+				}
+			}
+
+			let span = self.span_from(callee.span.start);
+			callee = Expr::new(
+				ExprKind::Application {
+					callee: callee.into(),
+					args,
+				},
+				span,
+			);
+		}
+
+		Ok(callee)
+	}
+
+	fn primary(&mut self) -> Result<Expr, SyntaxError> {
+		let lexeme = self.bump();
+		let start = lexeme.start;
+
+		let kind = match lexeme.token {
+			Token::Int(n) => match i32::try_from(n) {
+				Ok(i) => ExprKind::Int(i),
+				Err(_) => {
+					return Err(SyntaxError {
+						message: "integer literal out of range".to_owned(),
+						start,
+						end: lexeme.end,
+					})
+				}
+			},
+			Token::Minus => match *self.peek() {
+				Token::Int(n) => {
+					self.bump();
+					ExprKind::Int((n as i32).wrapping_neg())
+				}
+				_ => return Err(self.unexpected("integer literal")),
+			},
+			Token::Str(s) => ExprKind::Str(s),
+			Token::True => ExprKind::Bool(true),
+			Token::False => ExprKind::Bool(false),
+			Token::Ident(name) => {
+				let span = Span::new(lexeme.start, lexeme.end);
+				ExprKind::Variable(Ident::new(name, span))
+			}
+			Token::LParen => {
+				let first = self.term()?;
+				if self.eat(Token::RParen) {
+					return Ok(first);
+				}
+
+				self.expect(Token::Comma)?;
+				let second = self.term()?;
+				self.expect(Token::RParen)?;
+
+				ExprKind::Tuple(first.into(), second.into())
+			}
+			Token::LBrace => {
+				let expr = self.term()?;
+				self.expect(Token::RBrace)?;
+				return Ok(expr);
+			}
+			Token::Fn => {
+				self.expect(Token::LParen)?;
+				let mut args = vec![];
+				if !self.eat(Token::RParen) {
+					loop {
+						args.push(self.ident()?);
+						if self.eat(Token::RParen) {
+							break;
+						}
+						self.expect(Token::Comma)?;
+					}
+				}
+				self.expect(Token::Arrow)?;
+				let body = self.term()?.into();
+
+				ExprKind::Abstraction { args, body }
+			}
+			Token::If => {
+				self.expect(Token::LParen)?;
+				let condition = self.term()?.into();
+				self.expect(Token::RParen)?;
+				self.expect(Token::LBrace)?;
+				let then = self.term()?.into();
+				self.expect(Token::RBrace)?;
+				self.expect(Token::Else)?;
+				self.expect(Token::LBrace)?;
+				let otherwise = self.term()?.into();
+				self.expect(Token::RBrace)?;
+
+				ExprKind::If {
+					condition,
+					then,
+					otherwise,
+				}
+			}
+			Token::Print | Token::First | Token::Second => {
+				let name = match lexeme.token {
+					Token::Print => "print",
+					Token::First => "first",
+					_ => "second",
+				};
+
+				self.expect(Token::LParen)?;
+				let value = self.term()?;
+				self.expect(Token::RParen)?;
+
+				return Ok(parser::native(name, value, self.span_from(start)));
+			}
+			_ => return Err(found("expression", &lexeme)),
+		};
+
+		Ok(Expr::new(kind, self.span_from(start)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		diagnostics::Diagnostics,
+		expr::{BinOp, ExprKind, Ident, Span},
+		parser,
+	};
+
+	use super::parse;
+
+	#[test]
+	fn parse_matches_json() {
+		for (rinha, json) in [
+			("test_files/tco.rinha", "test_files/tco.json"),
+			("test_files/ll.rinha", "test_files/list.json"),
+		] {
+			let mut diagnostics = Diagnostics::new();
+			let native = parser::parse_as(rinha, parser::Format::Rinha, &mut diagnostics).unwrap();
+			let json = parser::parse_as(json, parser::Format::Json, &mut diagnostics).unwrap();
+			assert_eq!(native.expr, json.expr, "{rinha}");
+		}
+	}
+
+	#[test]
+	fn parse_precedence() {
+		let expr = parse("a || b && c == 1 + 2 * x").unwrap();
+		let var = |name: &str| ExprKind::Variable(Ident::new(name, Span::default())).into();
+
+		assert_eq!(
+			expr.without_spans().kind,
+			ExprKind::Binary {
+				lhs: var("a"),
+				op: BinOp::Or,
+				rhs: ExprKind::Binary {
+					lhs: var("b"),
+					op: BinOp::And,
+					rhs: ExprKind::Binary {
+						lhs: var("c"),
+						op: BinOp::Eq,
+						rhs: ExprKind::Binary {
+							lhs: ExprKind::Int(1).into(),
+							op: BinOp::Add,
+							rhs: ExprKind::Binary {
+								lhs: ExprKind::Int(2).into(),
+								op: BinOp::Mul,
+								rhs: var("x"),
+							}
+							.into(),
+						}
+						.into(),
+					}
+					.into(),
+				}
+				.into(),
+			}
+		);
+	}
+
+	#[test]
+	fn parse_errors() {
+		let err = parse("let x = 1 x").unwrap_err();
+		assert_eq!(err.message, "expected `;`, found `x`");
+		assert_eq!((err.start, err.end), (10, 11));
+
+		assert_eq!(parse("-2147483648").unwrap().kind, ExprKind::Int(i32::MIN));
+		for src in ["2147483648", "1 - 2147483648"] {
+			let err = parse(src).unwrap_err();
+			assert_eq!(err.message, "integer literal out of range");
+			assert_eq!(&src[err.start..err.end], "2147483648");
+		}
+
+		for src in ["", "let x = "] {
+			let err = parse(src).unwrap_err();
+			assert_eq!(err.message, "expected expression, found end of file");
+			assert_eq!((err.start, err.end), (src.len(), src.len()));
+		}
+
+		assert!(parse("\"unterminated").is_err());
+		assert!(parse("if (x) { 1 }").is_err());
+	}
+}
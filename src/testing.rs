@@ -0,0 +1,24 @@
+use crate::{
+	codegen::{normalize, Codegen},
+	diagnostics::Diagnostics,
+	expr::Expr,
+	resolve, syntax,
+};
+
+/// Parses and resolves `src`, which must be valid.
+pub fn parse(src: &str) -> Expr {
+	let mut expr = syntax::parse(src).unwrap();
+	let mut diagnostics = Diagnostics::new();
+	resolve::resolve(&mut expr, &mut diagnostics);
+	assert!(!diagnostics.has_errors(), "{diagnostics:?}");
+	expr
+}
+
+/// The value of `src` as HVM shows it, what the other backends are checked against.
+pub fn hvm(src: &str) -> String {
+	let code = Codegen::new(&mut Diagnostics::new())
+		.show(true)
+		.transpile(parse(src));
+	let shown = normalize(&code, 1 << 24, &mut std::io::sink()).unwrap();
+	shown.trim_matches('"').to_owned()
+}
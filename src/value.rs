@@ -0,0 +1,195 @@
+use std::{cmp::Ordering, fmt::Display, io::Write, rc::Rc};
+
+use crate::expr::{BinOp, Builtin, Span};
+
+/// A runtime value of the backends that run programs in Rust, generic over how each of
+/// them represents closures.
+#[derive(Debug)]
+pub enum Value<C> {
+	Int(i32),
+	Bool(bool),
+	Str(Rc<str>),
+	Tuple(Rc<(Value<C>, Value<C>)>),
+	Closure(Rc<C>),
+}
+
+// derived `Clone` would require `C: Clone`, but closures are only ever shared
+impl<C> Clone for Value<C> {
+	fn clone(&self) -> Self {
+		match self {
+			Self::Int(i) => Self::Int(*i),
+			Self::Bool(b) => Self::Bool(*b),
+			Self::Str(s) => Self::Str(s.clone()),
+			Self::Tuple(tuple) => Self::Tuple(tuple.clone()),
+			Self::Closure(closure) => Self::Closure(closure.clone()),
+		}
+	}
+}
+
+impl<C> Value<C> {
+	/// Name of the type of the value, as used in runtime errors.
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Self::Int(_) => "int",
+			Self::Bool(_) => "bool",
+			Self::Str(_) => "string",
+			Self::Tuple(_) => "tuple",
+			Self::Closure(_) => "closure",
+		}
+	}
+}
+
+/// Formats values the way `print` shows them, like `STD.show` does.
+impl<C> Display for Value<C> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Int(i) => write!(f, "{i}"),
+			Self::Bool(b) => write!(f, "{b}"),
+			Self::Str(s) => write!(f, "{s}"),
+			Self::Tuple(tuple) => write!(f, "({}, {})", tuple.0, tuple.1),
+			Self::Closure(_) => write!(f, "<#closure>"),
+		}
+	}
+}
+
+/// A runtime error, with the same messages the HVM runtime reports.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+	pub message: String,
+	pub span: Span,
+}
+
+impl Error {
+	pub fn new(message: impl Into<String>, span: Span) -> Self {
+		Self {
+			message: message.into(),
+			span,
+		}
+	}
+
+	pub fn expected<C>(what: &str, found: &Value<C>, span: Span) -> Self {
+		Self::new(
+			format!("expected {what}, found {}", found.type_name()),
+			span,
+		)
+	}
+
+	pub fn mismatch<C>(op: &BinOp, lhs: &Value<C>, rhs: &Value<C>, span: Span) -> Self {
+		Self::new(
+			format!(
+				"cannot apply `{}` to {} and {}",
+				op.symbol(),
+				lhs.type_name(),
+				rhs.type_name()
+			),
+			span,
+		)
+	}
+
+	pub fn arity(arity: usize, given: usize, span: Span) -> Self {
+		Self::new(
+			format!("closure takes {arity} arguments, but {given} were given"),
+			span,
+		)
+	}
+}
+
+/// How two values compare, following `STD.compare`: tuples are compared element by
+/// element, and the first pair of values of different types makes them unordered.
+enum Compared<'v, C> {
+	Order(Ordering),
+	Unordered(&'v Value<C>, &'v Value<C>),
+}
+
+fn compare<'v, C>(
+	lhs: &'v Value<C>,
+	rhs: &'v Value<C>,
+	span: Span,
+) -> Result<Compared<'v, C>, Error> {
+	Ok(match (lhs, rhs) {
+		(Value::Closure(_), _) | (_, Value::Closure(_)) => {
+			return Err(Error::new("cannot compare closures", span))
+		}
+		(Value::Int(x), Value::Int(y)) => Compared::Order(x.cmp(y)),
+		(Value::Bool(x), Value::Bool(y)) => Compared::Order(x.cmp(y)),
+		(Value::Str(x), Value::Str(y)) => Compared::Order(x.cmp(y)),
+		(Value::Tuple(x), Value::Tuple(y)) => match compare(&x.0, &y.0, span)? {
+			Compared::Order(Ordering::Equal) => compare(&x.1, &y.1, span)?,
+			compared => compared,
+		},
+		_ => Compared::Unordered(lhs, rhs),
+	})
+}
+
+/// Result of `lhs op rhs`, for every operator but the short-circuiting `&&` and `||`.
+pub fn binary<C>(
+	lhs: &Value<C>,
+	op: &BinOp,
+	rhs: &Value<C>,
+	span: Span,
+) -> Result<Value<C>, Error> {
+	use Value::{Bool, Int, Str};
+
+	Ok(match (lhs, op, rhs) {
+		(Int(x), BinOp::Add, Int(y)) => Int(x.wrapping_add(*y)),
+		(Str(x), BinOp::Add, Str(y)) => Str(format!("{x}{y}").into()),
+		(Int(x), BinOp::Add, Str(y)) => Str(format!("{x}{y}").into()),
+		(Str(x), BinOp::Add, Int(y)) => Str(format!("{x}{y}").into()),
+		(Int(x), BinOp::Sub, Int(y)) => Int(x.wrapping_sub(*y)),
+		(Int(x), BinOp::Mul, Int(y)) => Int(x.wrapping_mul(*y)),
+		(Int(_), BinOp::Div | BinOp::Rem, Int(0)) => {
+			return Err(Error::new("division by zero", span))
+		}
+		(Int(x), BinOp::Div, Int(y)) => Int(x.wrapping_div(*y)),
+		(Int(x), BinOp::Rem, Int(y)) => Int(x.wrapping_rem(*y)),
+		(_, BinOp::Eq | BinOp::Neq, _) => {
+			let equal = match compare(lhs, rhs, span)? {
+				Compared::Order(ord) => ord == Ordering::Equal,
+				Compared::Unordered(..) => false,
+			};
+			Bool(equal == (*op == BinOp::Eq))
+		}
+		(_, BinOp::Lt | BinOp::Lte | BinOp::Gt | BinOp::Gte, _) => {
+			let ord = match compare(lhs, rhs, span)? {
+				Compared::Order(ord) => ord,
+				Compared::Unordered(x, y) => return Err(Error::mismatch(op, x, y, span)),
+			};
+			Bool(match op {
+				BinOp::Lt => ord == Ordering::Less,
+				BinOp::Lte => ord != Ordering::Greater,
+				BinOp::Gt => ord == Ordering::Greater,
+				_ => ord != Ordering::Less,
+			})
+		}
+		_ => return Err(Error::mismatch(op, lhs, rhs, span)),
+	})
+}
+
+/// Calls a builtin, `print` writing to `out`.
+pub fn builtin<C>(
+	builtin: Builtin,
+	mut args: Vec<Value<C>>,
+	out: &mut dyn Write,
+	span: Span,
+) -> Result<Value<C>, Error> {
+	if args.len() != 1 {
+		return Err(Error::new(
+			format!(
+				"`{}` takes 1 argument, but {} were given",
+				builtin.name(),
+				args.len()
+			),
+			span,
+		));
+	}
+
+	match (builtin, args.pop().unwrap()) {
+		(Builtin::Print, value) => {
+			writeln!(out, "{value}").map_err(|err| Error::new(err.to_string(), span))?;
+			Ok(value)
+		}
+		(Builtin::First, Value::Tuple(tuple)) => Ok(tuple.0.clone()),
+		(Builtin::Second, Value::Tuple(tuple)) => Ok(tuple.1.clone()),
+		(_, value) => Err(Error::expected("a tuple", &value, span)),
+	}
+}
@@ -0,0 +1,252 @@
+use std::{io::Write, rc::Rc};
+
+use crate::{
+	bytecode::{Op, Program},
+	expr::Span,
+	value::{self, Error},
+};
+
+#[derive(Debug)]
+pub struct Closure {
+	function: u32,
+	captures: Vec<Value>,
+}
+
+pub type Value = value::Value<Closure>;
+
+struct Frame {
+	closure: Rc<Closure>,
+	pc: usize,
+	/// Where the slots of the frame start on the stack.
+	base: usize,
+}
+
+/// Runs [`crate::bytecode`] on a stack, sequentially.
+///
+/// Every frame keeps its slots at the bottom of its part of the stack, with the
+/// operands it is working on above them. Frames live on the heap, so deep recursion doesn't
+/// nest Rust calls, and tail calls reuse the frame of the caller. Values don't, showing,
+/// comparing and dropping a tuple recurse into its fields, so a long list of nested tuples
+/// still needs a big Rust stack, which [`crate::backend::Bytecode`] runs it on.
+pub struct Vm<'p, 'o> {
+	program: &'p Program,
+	out: &'o mut dyn Write,
+	stack: Vec<Value>,
+	frames: Vec<Frame>,
+	globals: Vec<Option<Value>>,
+}
+
+impl<'p, 'o> Vm<'p, 'o> {
+	pub fn new(program: &'p Program, out: &'o mut dyn Write) -> Self {
+		Self {
+			program,
+			out,
+			stack: vec![],
+			frames: vec![],
+			globals: vec![None; program.globals.len()],
+		}
+	}
+
+	pub fn run(&mut self) -> Result<Value, Error> {
+		let main = Rc::new(Closure {
+			function: Program::MAIN,
+			captures: vec![],
+		});
+		self.enter(main, 0);
+
+		loop {
+			let frame = self.frames.last_mut().unwrap();
+			let function = &self.program.functions[frame.closure.function as usize];
+			let pc = frame.pc;
+			let span = function.spans[pc];
+			frame.pc += 1;
+
+			match &function.code[pc] {
+				Op::Int(i) => self.stack.push(Value::Int(*i)),
+				Op::Bool(b) => self.stack.push(Value::Bool(*b)),
+				Op::Str(s) => self.stack.push(Value::Str(s.clone())),
+				Op::Local(slot) => {
+					let value = self.stack[frame.base + *slot as usize].clone();
+					self.stack.push(value);
+				}
+				Op::SetLocal(slot) => {
+					let slot = frame.base + *slot as usize;
+					self.stack[slot] = self.stack.pop().unwrap();
+				}
+				Op::Capture(index) => {
+					let value = frame.closure.captures[*index as usize].clone();
+					self.stack.push(value);
+				}
+				Op::Global(global) => match &self.globals[*global as usize] {
+					Some(value) => self.stack.push(value.clone()),
+					None => {
+						let name = &self.program.globals[*global as usize];
+						return Err(Error::new(
+							format!("`{name}` is used before its definition"),
+							span,
+						));
+					}
+				},
+				Op::SetGlobal(global) => {
+					self.globals[*global as usize] = self.stack.pop();
+				}
+				Op::Current => self.stack.push(Value::Closure(frame.closure.clone())),
+				Op::Binary(op) => {
+					let rhs = self.stack.pop().unwrap();
+					let lhs = self.stack.pop().unwrap();
+					self.stack.push(value::binary(&lhs, op, &rhs, span)?);
+				}
+				Op::Jump(target) => frame.pc = *target as usize,
+				Op::JumpUnless(target) => match self.stack.pop().unwrap() {
+					Value::Bool(true) => {}
+					Value::Bool(false) => frame.pc = *target as usize,
+					value => return Err(Error::expected("a bool", &value, span)),
+				},
+				Op::Branch { value, target } => match self.stack.last().unwrap() {
+					Value::Bool(b) if b == value => frame.pc = *target as usize,
+					Value::Bool(_) => {
+						self.stack.pop();
+					}
+					value => return Err(Error::expected("a bool", value, span)),
+				},
+				Op::CheckBool => match self.stack.last().unwrap() {
+					Value::Bool(_) => {}
+					value => return Err(Error::expected("a bool", value, span)),
+				},
+				Op::Tuple => {
+					let second = self.stack.pop().unwrap();
+					let first = self.stack.pop().unwrap();
+					self.stack.push(Value::Tuple(Rc::new((first, second))));
+				}
+				Op::Closure { function, captures } => {
+					let captures = self.stack.split_off(self.stack.len() - *captures as usize);
+					self.stack.push(Value::Closure(Rc::new(Closure {
+						function: *function,
+						captures,
+					})));
+				}
+				Op::Call(argc) => {
+					let closure = self.callee(*argc as usize, span)?;
+					let base = self.stack.len() - *argc as usize;
+					self.stack.remove(base - 1);
+					self.enter(closure, base - 1);
+				}
+				Op::TailCall(argc) => {
+					let base = frame.base;
+					let closure = self.callee(*argc as usize, span)?;
+					let args = self.stack.len() - *argc as usize;
+					self.stack.drain(base..args);
+					self.frames.pop();
+					self.enter(closure, base);
+				}
+				Op::Builtin(builtin, argc) => {
+					let args = self.stack.split_off(self.stack.len() - *argc as usize);
+					let value = value::builtin(*builtin, args, self.out, span)?;
+					self.stack.push(value);
+				}
+				Op::Return => {
+					let value = self.stack.pop().unwrap();
+					let frame = self.frames.pop().unwrap();
+					self.stack.truncate(frame.base);
+					if self.frames.is_empty() {
+						return Ok(value);
+					}
+					self.stack.push(value);
+				}
+			}
+		}
+	}
+
+	/// The closure below `argc` arguments on the stack, if it takes that many of them.
+	fn callee(&self, argc: usize, span: Span) -> Result<Rc<Closure>, Error> {
+		match &self.stack[self.stack.len() - argc - 1] {
+			Value::Closure(closure) => {
+				let arity = self.program.functions[closure.function as usize].arity as usize;
+				if arity != argc {
+					return Err(Error::arity(arity, argc, span));
+				}
+				Ok(closure.clone())
+			}
+			value => Err(Error::expected("a closure", value, span)),
+		}
+	}
+
+	/// Pushes a frame for `closure`, whose arguments are on the stack from `base` on.
+	fn enter(&mut self, closure: Rc<Closure>, base: usize) {
+		let locals = self.program.functions[closure.function as usize].locals as usize;
+		self.stack.resize(base + locals, Value::Int(0));
+		self.frames.push(Frame {
+			closure,
+			pc: 0,
+			base,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Vm;
+	use crate::{bytecode, testing::parse, value::Error};
+
+	fn run(src: &str) -> (String, Result<String, Error>) {
+		let program = bytecode::compile(&parse(src));
+		let mut out = vec![];
+		let result = Vm::new(&program, &mut out)
+			.run()
+			.map(|value| value.to_string());
+		(String::from_utf8(out).unwrap(), result)
+	}
+
+	#[test]
+	fn vm_programs() {
+		let src = "
+			let fib = fn (n) => if (n < 2) { n } else { fib(n - 1) + fib(n - 2) };
+			let count = fn (n, acc) => if (n == 0) { acc } else { count(n - 1, acc + 1) };
+			let sum = fn (n) => if (n == 0) { 0 } else { n + sum(n - 1) };
+			let is_even = fn (n) => if (n == 0) { true } else { is_odd(n - 1) };
+			let adder = fn (x) => fn (y) => x + y;
+			let is_odd = fn (n) => if (n == 0) { false } else { is_even(n - 1) };
+			let range = fn (lo, hi) => {
+				let go = fn (i, acc) => if (i < lo) { acc } else { go(i - 1, (i, acc)) };
+				go(hi, \"<nil>\")
+			};
+			let _ = print((1, (\"a\", adder)));
+			let x = print(\"fib: \" + fib(15));
+			(x, (count(1000000, 0), (sum(100000), (is_even(7) || false, (adder(2)(3), range(1, 3))))))
+		";
+		let (out, result) = run(src);
+		assert_eq!(out, "(1, (a, <#closure>))\nfib: 610\n");
+		assert_eq!(
+			result.unwrap(),
+			"(fib: 610, (1000000, (705082704, (false, (5, (1, (2, (3, <nil>))))))))"
+		);
+	}
+
+	#[test]
+	fn vm_runtime_errors() {
+		let error = |src: &str| {
+			let Err(Error { message, span }) = run(src).1 else {
+				panic!("{src} didn't fail")
+			};
+			(message, src[span.start..span.end].to_owned())
+		};
+
+		assert_eq!(
+			error("let f = fn (x) => 10 / x; f(5) + f(0)"),
+			("division by zero".to_owned(), "10 / x".to_owned())
+		);
+		assert_eq!(
+			error("let f = fn (a, b) => a; let g = f; g(1)"),
+			(
+				"closure takes 2 arguments, but 1 were given".to_owned(),
+				"g(1)".to_owned()
+			)
+		);
+		assert_eq!(
+			error("let f = fn () => g(); let x = f(); let g = fn () => 1; x").0,
+			"`g` is used before its definition"
+		);
+		assert_eq!(error("true && 1").0, "expected a bool, found int");
+		assert_eq!(error("1(2)").0, "expected a closure, found int");
+	}
+}
@@ -0,0 +1,901 @@
+use std::collections::HashMap;
+
+/// Opcodes of the instructions [`Module::decode`] understands, the ones
+/// [`crate::codegen_wasm`] emits.
+pub mod op {
+	pub const UNREACHABLE: u8 = 0x00;
+	pub const NOP: u8 = 0x01;
+	pub const BLOCK: u8 = 0x02;
+	pub const LOOP: u8 = 0x03;
+	pub const IF: u8 = 0x04;
+	pub const ELSE: u8 = 0x05;
+	pub const END: u8 = 0x0b;
+	pub const BR: u8 = 0x0c;
+	pub const BR_IF: u8 = 0x0d;
+	pub const RETURN: u8 = 0x0f;
+	pub const CALL: u8 = 0x10;
+	pub const CALL_INDIRECT: u8 = 0x11;
+	pub const DROP: u8 = 0x1a;
+	pub const SELECT: u8 = 0x1b;
+	pub const LOCAL_GET: u8 = 0x20;
+	pub const LOCAL_SET: u8 = 0x21;
+	pub const LOCAL_TEE: u8 = 0x22;
+	pub const GLOBAL_GET: u8 = 0x23;
+	pub const GLOBAL_SET: u8 = 0x24;
+	pub const I32_LOAD: u8 = 0x28;
+	pub const I64_LOAD: u8 = 0x29;
+	pub const I32_LOAD8_U: u8 = 0x2d;
+	pub const I32_STORE: u8 = 0x36;
+	pub const I64_STORE: u8 = 0x37;
+	pub const I32_STORE8: u8 = 0x3a;
+	pub const MEMORY_SIZE: u8 = 0x3f;
+	pub const MEMORY_GROW: u8 = 0x40;
+	pub const I32_CONST: u8 = 0x41;
+	pub const I64_CONST: u8 = 0x42;
+
+	pub const I32_EQZ: u8 = 0x45;
+	pub const I32_EQ: u8 = 0x46;
+	pub const I32_NE: u8 = 0x47;
+	pub const I32_LT_S: u8 = 0x48;
+	pub const I32_LT_U: u8 = 0x49;
+	pub const I32_GT_S: u8 = 0x4a;
+	pub const I32_GT_U: u8 = 0x4b;
+	pub const I32_LE_S: u8 = 0x4c;
+	pub const I32_LE_U: u8 = 0x4d;
+	pub const I32_GE_S: u8 = 0x4e;
+	pub const I32_GE_U: u8 = 0x4f;
+	pub const I64_EQZ: u8 = 0x50;
+	pub const I64_EQ: u8 = 0x51;
+	pub const I64_NE: u8 = 0x52;
+	pub const I64_LT_S: u8 = 0x53;
+
+	pub const I32_ADD: u8 = 0x6a;
+	pub const I32_SUB: u8 = 0x6b;
+	pub const I32_MUL: u8 = 0x6c;
+	pub const I32_DIV_S: u8 = 0x6d;
+	pub const I32_REM_S: u8 = 0x6f;
+	pub const I32_AND: u8 = 0x71;
+	pub const I32_OR: u8 = 0x72;
+	pub const I32_XOR: u8 = 0x73;
+	pub const I32_SHL: u8 = 0x74;
+	pub const I32_SHR_U: u8 = 0x76;
+	pub const I64_SUB: u8 = 0x7d;
+	pub const I64_DIV_U: u8 = 0x80;
+	pub const I64_REM_U: u8 = 0x82;
+	pub const I64_OR: u8 = 0x84;
+	pub const I64_XOR: u8 = 0x85;
+	pub const I64_SHL: u8 = 0x86;
+	pub const I64_SHR_U: u8 = 0x88;
+
+	pub const I32_WRAP_I64: u8 = 0xa7;
+	pub const I64_EXTEND_I32_S: u8 = 0xac;
+	pub const I64_EXTEND_I32_U: u8 = 0xad;
+}
+
+pub const I32: u8 = 0x7f;
+pub const I64: u8 = 0x7e;
+/// Block type of blocks without a result.
+pub const EMPTY: u8 = 0x40;
+pub const FUNCREF: u8 = 0x70;
+
+pub const PAGE_SIZE: usize = 1 << 16;
+/// Pages memory can grow to, so addresses and sizes fit in an i32.
+const MAX_PAGES: usize = (1 << 15) - 1;
+const MAX_FRAMES: usize = 1 << 22;
+
+#[derive(Debug)]
+pub struct Trap(pub String);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncType {
+	pub params: Vec<u8>,
+	pub results: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Import {
+	pub module: String,
+	pub name: String,
+	pub ty: u32,
+}
+
+/// Calls to the imported functions, given the memory of the instance.
+pub type Host<'h> = dyn FnMut(&Import, &mut [u8], &[u64]) -> Result<Option<u64>, Trap> + 'h;
+
+#[derive(Debug, Clone, Copy)]
+enum Instr {
+	Unreachable,
+	Nop,
+	Block {
+		arity: u8,
+		end: u32,
+	},
+	Loop,
+	/// `r#else` is `end` when there is no `else` branch.
+	If {
+		arity: u8,
+		r#else: u32,
+		end: u32,
+	},
+	Else {
+		end: u32,
+	},
+	End,
+	Br(u32),
+	BrIf(u32),
+	Return,
+	Call(u32),
+	CallIndirect(u32),
+	Drop,
+	Select,
+	LocalGet(u32),
+	LocalSet(u32),
+	LocalTee(u32),
+	GlobalGet(u32),
+	GlobalSet(u32),
+	Load(u8, u32),
+	Store(u8, u32),
+	MemorySize,
+	MemoryGrow,
+	Const(u64),
+	Numeric(u8),
+}
+
+#[derive(Debug)]
+struct Function {
+	ty: u32,
+	locals: usize,
+	code: Vec<Instr>,
+}
+
+/// A decoded WebAssembly module, of the subset [`crate::codegen_wasm`] emits: a single
+/// memory and table, functions over i32 and i64, and active segments at constant offsets.
+#[derive(Debug, Default)]
+pub struct Module {
+	types: Vec<FuncType>,
+	pub imports: Vec<Import>,
+	functions: Vec<Function>,
+	table: Vec<Option<u32>>,
+	pages: usize,
+	globals: Vec<u64>,
+	exports: HashMap<String, u32>,
+	data: Vec<(usize, Vec<u8>)>,
+}
+
+struct Reader<'b> {
+	bytes: &'b [u8],
+	pos: usize,
+}
+
+impl<'b> Reader<'b> {
+	fn byte(&mut self) -> Result<u8, Trap> {
+		let byte = *self
+			.bytes
+			.get(self.pos)
+			.ok_or_else(|| Trap("unexpected end of module".to_owned()))?;
+		self.pos += 1;
+		Ok(byte)
+	}
+
+	fn bytes(&mut self, len: usize) -> Result<&'b [u8], Trap> {
+		let bytes = self
+			.bytes
+			.get(self.pos..self.pos + len)
+			.ok_or_else(|| Trap("unexpected end of module".to_owned()))?;
+		self.pos += len;
+		Ok(bytes)
+	}
+
+	fn leb(&mut self, bits: u32, signed: bool) -> Result<u64, Trap> {
+		let mut value = 0u64;
+		let mut shift = 0;
+		loop {
+			let byte = self.byte()?;
+			value |= ((byte & 0x7f) as u64) << shift;
+			shift += 7;
+			if byte & 0x80 == 0 {
+				if signed && shift < 64 && byte & 0x40 != 0 {
+					value |= !0 << shift;
+				}
+				break;
+			}
+			if shift >= bits + 7 {
+				return Err(Trap("integer too long".to_owned()));
+			}
+		}
+		Ok(value)
+	}
+
+	fn u32(&mut self) -> Result<u32, Trap> {
+		Ok(self.leb(32, false)? as u32)
+	}
+
+	fn name(&mut self) -> Result<String, Trap> {
+		let len = self.u32()? as usize;
+		String::from_utf8(self.bytes(len)?.to_vec()).map_err(|err| Trap(err.to_string()))
+	}
+
+	/// A constant expression, the initial value of a global or the offset of a segment.
+	fn constant(&mut self) -> Result<u64, Trap> {
+		let value = match self.byte()? {
+			op::I32_CONST => self.leb(32, true)? as u32 as u64,
+			op::I64_CONST => self.leb(64, true)?,
+			op => return Err(Trap(format!("unsupported constant expression {op:#x}"))),
+		};
+		match self.byte()? {
+			op::END => Ok(value),
+			_ => Err(Trap("expected the end of a constant expression".to_owned())),
+		}
+	}
+
+	fn block_type(&mut self) -> Result<u8, Trap> {
+		match self.byte()? {
+			EMPTY => Ok(0),
+			I32 | I64 => Ok(1),
+			ty => Err(Trap(format!("unsupported block type {ty:#x}"))),
+		}
+	}
+
+	fn code(&mut self, end: usize) -> Result<Vec<Instr>, Trap> {
+		let mut code = vec![];
+		// blocks not closed yet, to point them at their `end`
+		let mut open = vec![];
+
+		while self.pos < end {
+			let at = code.len();
+			let instr = match self.byte()? {
+				op::UNREACHABLE => Instr::Unreachable,
+				op::NOP => Instr::Nop,
+				op::BLOCK => {
+					open.push(at);
+					Instr::Block {
+						arity: self.block_type()?,
+						end: 0,
+					}
+				}
+				op::LOOP => {
+					self.block_type()?;
+					open.push(at);
+					Instr::Loop
+				}
+				op::IF => {
+					open.push(at);
+					Instr::If {
+						arity: self.block_type()?,
+						r#else: 0,
+						end: 0,
+					}
+				}
+				op::ELSE => {
+					let Some(&start) = open.last() else {
+						return Err(Trap("`else` outside of `if`".to_owned()));
+					};
+					match &mut code[start] {
+						Instr::If { r#else, .. } => *r#else = at as u32,
+						_ => return Err(Trap("`else` outside of `if`".to_owned())),
+					}
+					open.push(at);
+					Instr::Else { end: 0 }
+				}
+				op::END => {
+					let at = at as u32;
+					if let Some(start) = open.pop() {
+						if let Instr::Else { end } = &mut code[start] {
+							*end = at;
+							let Some(start) = open.pop() else {
+								unreachable!("`else` is only pushed inside `if`")
+							};
+							if let Instr::If { end, .. } = &mut code[start] {
+								*end = at;
+							}
+						} else if let Instr::Block { end, .. } = &mut code[start] {
+							*end = at;
+						} else if let Instr::If { r#else, end, .. } = &mut code[start] {
+							*r#else = at;
+							*end = at;
+						}
+					}
+					Instr::End
+				}
+				op::BR => Instr::Br(self.u32()?),
+				op::BR_IF => Instr::BrIf(self.u32()?),
+				op::RETURN => Instr::Return,
+				op::CALL => Instr::Call(self.u32()?),
+				op::CALL_INDIRECT => {
+					let ty = self.u32()?;
+					self.u32()?;
+					Instr::CallIndirect(ty)
+				}
+				op::DROP => Instr::Drop,
+				op::SELECT => Instr::Select,
+				op::LOCAL_GET => Instr::LocalGet(self.u32()?),
+				op::LOCAL_SET => Instr::LocalSet(self.u32()?),
+				op::LOCAL_TEE => Instr::LocalTee(self.u32()?),
+				op::GLOBAL_GET => Instr::GlobalGet(self.u32()?),
+				op::GLOBAL_SET => Instr::GlobalSet(self.u32()?),
+				op @ (op::I32_LOAD | op::I64_LOAD | op::I32_LOAD8_U) => {
+					self.u32()?;
+					Instr::Load(op, self.u32()?)
+				}
+				op @ (op::I32_STORE | op::I64_STORE | op::I32_STORE8) => {
+					self.u32()?;
+					Instr::Store(op, self.u32()?)
+				}
+				op::MEMORY_SIZE => {
+					self.byte()?;
+					Instr::MemorySize
+				}
+				op::MEMORY_GROW => {
+					self.byte()?;
+					Instr::MemoryGrow
+				}
+				op::I32_CONST => Instr::Const(self.leb(32, true)? as u32 as u64),
+				op::I64_CONST => Instr::Const(self.leb(64, true)?),
+				op @ (op::I32_EQZ..=op::I64_LT_S
+				| op::I32_ADD..=op::I32_SHR_U
+				| op::I64_SUB..=op::I64_SHR_U
+				| op::I32_WRAP_I64
+				| op::I64_EXTEND_I32_S
+				| op::I64_EXTEND_I32_U) => Instr::Numeric(op),
+				op => return Err(Trap(format!("unsupported instruction {op:#x}"))),
+			};
+			code.push(instr);
+		}
+		Ok(code)
+	}
+}
+
+impl Module {
+	pub fn decode(bytes: &[u8]) -> Result<Self, Trap> {
+		let mut reader = Reader { bytes, pos: 0 };
+		if reader.bytes(8)? != b"\0asm\x01\0\0\0" {
+			return Err(Trap("not a WebAssembly module".to_owned()));
+		}
+
+		let mut module = Module::default();
+		let mut functions = vec![];
+		while reader.pos < bytes.len() {
+			let id = reader.byte()?;
+			let size = reader.u32()? as usize;
+			let end = reader.pos + size;
+			let count = reader.u32()?;
+
+			for _ in 0..count {
+				match id {
+					1 => {
+						if reader.byte()? != 0x60 {
+							return Err(Trap("expected a function type".to_owned()));
+						}
+						let len = reader.u32()? as usize;
+						let params = reader.bytes(len)?.to_vec();
+						let len = reader.u32()? as usize;
+						let results = reader.bytes(len)?.to_vec();
+						module.types.push(FuncType { params, results });
+					}
+					2 => {
+						let import = Import {
+							module: reader.name()?,
+							name: reader.name()?,
+							ty: match reader.byte()? {
+								0 => reader.u32()?,
+								_ => return Err(Trap("only functions can be imported".to_owned())),
+							},
+						};
+						module.imports.push(import);
+					}
+					3 => functions.push(reader.u32()?),
+					4 => {
+						reader.byte()?;
+						let flags = reader.byte()?;
+						module.table = vec![None; reader.u32()? as usize];
+						if flags == 1 {
+							reader.u32()?;
+						}
+					}
+					5 => {
+						let flags = reader.byte()?;
+						module.pages = reader.u32()? as usize;
+						if flags == 1 {
+							reader.u32()?;
+						}
+					}
+					6 => {
+						reader.bytes(2)?;
+						let init = reader.constant()?;
+						module.globals.push(init);
+					}
+					7 => {
+						let name = reader.name()?;
+						let kind = reader.byte()?;
+						let index = reader.u32()?;
+						if kind == 0 {
+							module.exports.insert(name, index);
+						}
+					}
+					9 => {
+						if reader.u32()? != 0 {
+							return Err(Trap("unsupported element segment".to_owned()));
+						}
+						let offset = reader.constant()? as usize;
+						for i in 0..reader.u32()? as usize {
+							let function = reader.u32()?;
+							*module
+								.table
+								.get_mut(offset + i)
+								.ok_or_else(|| Trap("element out of the table".to_owned()))? = Some(function);
+						}
+					}
+					10 => {
+						let size = reader.u32()? as usize;
+						let end = reader.pos + size;
+						let mut locals = 0;
+						for _ in 0..reader.u32()? {
+							locals += reader.u32()? as usize;
+							reader.byte()?;
+						}
+						let ty = functions
+							.get(module.functions.len())
+							.copied()
+							.ok_or_else(|| Trap("function without a type".to_owned()))?;
+						let code = reader.code(end)?;
+						module.functions.push(Function { ty, locals, code });
+					}
+					11 => {
+						if reader.u32()? != 0 {
+							return Err(Trap("unsupported data segment".to_owned()));
+						}
+						let offset = reader.constant()? as usize;
+						let len = reader.u32()? as usize;
+						module.data.push((offset, reader.bytes(len)?.to_vec()));
+					}
+					_ => return Err(Trap(format!("unsupported section {id}"))),
+				}
+			}
+			if reader.pos != end {
+				return Err(Trap(format!("malformed section {id}")));
+			}
+		}
+		Ok(module)
+	}
+
+	fn func_type(&self, function: u32) -> Option<&FuncType> {
+		let ty = match self.imports.get(function as usize) {
+			Some(import) => import.ty,
+			None => {
+				self.functions
+					.get(function as usize - self.imports.len())?
+					.ty
+			}
+		};
+		self.types.get(ty as usize)
+	}
+}
+
+struct Label {
+	/// Where branching to the label continues.
+	target: usize,
+	arity: usize,
+	height: usize,
+	looping: bool,
+}
+
+struct Frame {
+	function: usize,
+	pc: usize,
+	locals: usize,
+	height: usize,
+	labels: usize,
+}
+
+/// Runs a [`Module`], interpreting its instructions one by one.
+///
+/// Frames, labels and locals live on stacks of their own, so deep recursion doesn't need a
+/// big Rust stack. Values are kept as u64, i32s zero-extended.
+pub struct Instance<'m> {
+	module: &'m Module,
+	pub memory: Vec<u8>,
+	globals: Vec<u64>,
+	stack: Vec<u64>,
+	locals: Vec<u64>,
+	labels: Vec<Label>,
+	frames: Vec<Frame>,
+}
+
+fn trap<T>(message: &str) -> Result<T, Trap> {
+	Err(Trap(message.to_owned()))
+}
+
+impl<'m> Instance<'m> {
+	pub fn new(module: &'m Module) -> Result<Self, Trap> {
+		let mut memory = vec![0; module.pages * PAGE_SIZE];
+		for (offset, bytes) in &module.data {
+			memory
+				.get_mut(*offset..offset + bytes.len())
+				.ok_or_else(|| Trap("data out of the memory".to_owned()))?
+				.copy_from_slice(bytes);
+		}
+
+		Ok(Self {
+			module,
+			memory,
+			globals: module.globals.clone(),
+			stack: vec![],
+			locals: vec![],
+			labels: vec![],
+			frames: vec![],
+		})
+	}
+
+	/// Calls the exported function `name`, returning its result.
+	pub fn invoke(
+		&mut self,
+		name: &str,
+		args: &[u64],
+		host: &mut Host,
+	) -> Result<Option<u64>, Trap> {
+		let Some(&function) = self.module.exports.get(name) else {
+			return Err(Trap(format!("no function `{name}` is exported")));
+		};
+		self.stack.extend_from_slice(args);
+		self.call(function, host)?;
+		let base = self.frames.len() - 1;
+		self.run(base, host)?;
+		Ok(self.stack.pop())
+	}
+
+	fn pop(&mut self) -> u64 {
+		self.stack
+			.pop()
+			.expect("the stack of a valid module doesn't underflow")
+	}
+
+	fn address(&mut self, offset: u32, len: usize) -> Result<usize, Trap> {
+		let address = self.pop() as u32 as usize + offset as usize;
+		match address + len <= self.memory.len() {
+			true => Ok(address),
+			false => trap("out of bounds memory access"),
+		}
+	}
+
+	fn call(&mut self, function: u32, host: &mut Host) -> Result<(), Trap> {
+		let ty = self
+			.module
+			.func_type(function)
+			.ok_or_else(|| Trap("call to an undefined function".to_owned()))?;
+		let args = self.stack.len() - ty.params.len();
+
+		let imports = self.module.imports.len();
+		if let Some(import) = self.module.imports.get(function as usize) {
+			let result = host(import, &mut self.memory, &self.stack[args..])?;
+			self.stack.truncate(args);
+			self.stack.extend(result);
+			return Ok(());
+		}
+
+		if self.frames.len() == MAX_FRAMES {
+			return trap("call stack exhausted");
+		}
+		let index = function as usize - imports;
+		let locals = self.locals.len();
+		self.locals.extend(self.stack.drain(args..));
+		self.locals
+			.resize(self.locals.len() + self.module.functions[index].locals, 0);
+		self.frames.push(Frame {
+			function: index,
+			pc: 0,
+			locals,
+			height: self.stack.len(),
+			labels: self.labels.len(),
+		});
+		Ok(())
+	}
+
+	/// Leaves the current frame, keeping its results on the stack.
+	fn ret(&mut self) {
+		let frame = self.frames.pop().unwrap();
+		let function = &self.module.functions[frame.function];
+		let results = self.module.types[function.ty as usize].results.len();
+		let from = self.stack.len() - results;
+		self.stack.drain(frame.height..from);
+		self.locals.truncate(frame.locals);
+		self.labels.truncate(frame.labels);
+	}
+
+	fn branch(&mut self, depth: u32) {
+		let frame = self.frames.last_mut().unwrap();
+		let Some(index) = (self.labels.len() - 1)
+			.checked_sub(depth as usize)
+			.filter(|index| *index >= frame.labels)
+		else {
+			return self.ret();
+		};
+
+		let label = &self.labels[index];
+		let from = self.stack.len() - label.arity;
+		self.stack.drain(label.height..from);
+		frame.pc = label.target;
+		let keep = index + label.looping as usize;
+		self.labels.truncate(keep);
+	}
+
+	/// Runs until the frame at `base` returns.
+	fn run(&mut self, base: usize, host: &mut Host) -> Result<(), Trap> {
+		use op::*;
+
+		while self.frames.len() > base {
+			let frame = self.frames.last_mut().unwrap();
+			let pc = frame.pc;
+			let locals = frame.locals;
+			frame.pc += 1;
+
+			match self.module.functions[frame.function].code[pc] {
+				Instr::Unreachable => return trap("unreachable"),
+				Instr::Nop => {}
+				Instr::Block { arity, end } => self.labels.push(Label {
+					target: end as usize + 1,
+					arity: arity as usize,
+					height: self.stack.len(),
+					looping: false,
+				}),
+				Instr::Loop => self.labels.push(Label {
+					target: pc + 1,
+					arity: 0,
+					height: self.stack.len(),
+					looping: true,
+				}),
+				Instr::If { arity, r#else, end } => {
+					let condition = self.pop() as u32;
+					self.labels.push(Label {
+						target: end as usize + 1,
+						arity: arity as usize,
+						height: self.stack.len(),
+						looping: false,
+					});
+					if condition == 0 {
+						let frame = self.frames.last_mut().unwrap();
+						frame.pc = match r#else == end {
+							true => end as usize,
+							false => r#else as usize + 1,
+						};
+					}
+				}
+				Instr::Else { end } => frame.pc = end as usize,
+				Instr::End => {
+					if self.labels.len() == frame.labels {
+						self.ret();
+					} else {
+						self.labels.pop();
+					}
+				}
+				Instr::Br(depth) => self.branch(depth),
+				Instr::BrIf(depth) => {
+					if self.pop() as u32 != 0 {
+						self.branch(depth);
+					}
+				}
+				Instr::Return => self.ret(),
+				Instr::Call(function) => self.call(function, host)?,
+				Instr::CallIndirect(ty) => {
+					let index = self.pop() as u32 as usize;
+					let Some(Some(function)) = self.module.table.get(index).copied() else {
+						return trap("undefined element");
+					};
+					if self.module.func_type(function) != self.module.types.get(ty as usize) {
+						return trap("indirect call type mismatch");
+					}
+					self.call(function, host)?;
+				}
+				Instr::Drop => {
+					self.pop();
+				}
+				Instr::Select => {
+					let condition = self.pop() as u32;
+					let second = self.pop();
+					let first = self.pop();
+					self.stack.push(if condition != 0 { first } else { second });
+				}
+				Instr::LocalGet(local) => self.stack.push(self.locals[locals + local as usize]),
+				Instr::LocalSet(local) => self.locals[locals + local as usize] = self.pop(),
+				Instr::LocalTee(local) => {
+					self.locals[locals + local as usize] = *self.stack.last().unwrap()
+				}
+				Instr::GlobalGet(global) => self.stack.push(self.globals[global as usize]),
+				Instr::GlobalSet(global) => self.globals[global as usize] = self.pop(),
+				Instr::Load(op, offset) => {
+					let value = match op {
+						I32_LOAD => {
+							let at = self.address(offset, 4)?;
+							u32::from_le_bytes(self.memory[at..at + 4].try_into().unwrap()) as u64
+						}
+						I64_LOAD => {
+							let at = self.address(offset, 8)?;
+							u64::from_le_bytes(self.memory[at..at + 8].try_into().unwrap())
+						}
+						_ => {
+							let at = self.address(offset, 1)?;
+							self.memory[at] as u64
+						}
+					};
+					self.stack.push(value);
+				}
+				Instr::Store(op, offset) => {
+					let value = self.pop();
+					match op {
+						I32_STORE => {
+							let at = self.address(offset, 4)?;
+							self.memory[at..at + 4].copy_from_slice(&(value as u32).to_le_bytes());
+						}
+						I64_STORE => {
+							let at = self.address(offset, 8)?;
+							self.memory[at..at + 8].copy_from_slice(&value.to_le_bytes());
+						}
+						_ => {
+							let at = self.address(offset, 1)?;
+							self.memory[at] = value as u8;
+						}
+					}
+				}
+				Instr::MemorySize => self.stack.push((self.memory.len() / PAGE_SIZE) as u64),
+				Instr::MemoryGrow => {
+					let pages = self.memory.len() / PAGE_SIZE;
+					let grow = self.pop() as u32 as usize;
+					if pages + grow > MAX_PAGES {
+						self.stack.push(u32::MAX as u64);
+					} else {
+						self.memory.resize((pages + grow) * PAGE_SIZE, 0);
+						self.stack.push(pages as u64);
+					}
+				}
+				Instr::Const(value) => self.stack.push(value),
+				Instr::Numeric(op) => self.numeric(op)?,
+			}
+		}
+		Ok(())
+	}
+
+	fn numeric(&mut self, op: u8) -> Result<(), Trap> {
+		use op::*;
+
+		let unary = |x: u64| -> u64 {
+			match op {
+				I32_EQZ => (x as u32 == 0) as u64,
+				I64_EQZ => (x == 0) as u64,
+				I32_WRAP_I64 => x as u32 as u64,
+				I64_EXTEND_I32_S => x as u32 as i32 as i64 as u64,
+				_ => x as u32 as u64,
+			}
+		};
+		if matches!(
+			op,
+			I32_EQZ | I64_EQZ | I32_WRAP_I64 | I64_EXTEND_I32_S | I64_EXTEND_I32_U
+		) {
+			let x = self.pop();
+			self.stack.push(unary(x));
+			return Ok(());
+		}
+
+		let y = self.pop();
+		let x = self.pop();
+		let (a, b) = (x as u32, y as u32);
+		let (sa, sb) = (a as i32, b as i32);
+		let value = match op {
+			I32_EQ => (a == b) as u64,
+			I32_NE => (a != b) as u64,
+			I32_LT_S => (sa < sb) as u64,
+			I32_LT_U => (a < b) as u64,
+			I32_GT_S => (sa > sb) as u64,
+			I32_GT_U => (a > b) as u64,
+			I32_LE_S => (sa <= sb) as u64,
+			I32_LE_U => (a <= b) as u64,
+			I32_GE_S => (sa >= sb) as u64,
+			I32_GE_U => (a >= b) as u64,
+			I64_EQ => (x == y) as u64,
+			I64_NE => (x != y) as u64,
+			I64_LT_S => ((x as i64) < (y as i64)) as u64,
+			I32_ADD => a.wrapping_add(b) as u64,
+			I32_SUB => a.wrapping_sub(b) as u64,
+			I32_MUL => a.wrapping_mul(b) as u64,
+			I32_DIV_S | I32_REM_S if b == 0 => return trap("integer divide by zero"),
+			I32_DIV_S => match sa.checked_div(sb) {
+				Some(value) => value as u32 as u64,
+				None => return trap("integer overflow"),
+			},
+			I32_REM_S => sa.wrapping_rem(sb) as u32 as u64,
+			I32_AND => (a & b) as u64,
+			I32_OR => (a | b) as u64,
+			I32_XOR => (a ^ b) as u64,
+			I32_SHL => a.wrapping_shl(b) as u64,
+			I32_SHR_U => a.wrapping_shr(b) as u64,
+			I64_SUB => x.wrapping_sub(y),
+			I64_DIV_U | I64_REM_U if y == 0 => return trap("integer divide by zero"),
+			I64_DIV_U => x / y,
+			I64_REM_U => x % y,
+			I64_OR => x | y,
+			I64_XOR => x ^ y,
+			I64_SHL => x.wrapping_shl(y as u32),
+			I64_SHR_U => x.wrapping_shr(y as u32),
+			op => return Err(Trap(format!("unsupported instruction {op:#x}"))),
+		};
+		self.stack.push(value);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{op, Instance, Module, Trap, I32};
+
+	/// A module exporting a recursive `fact(n)` and `div(x, y)`, of i32s.
+	fn module() -> Vec<u8> {
+		let section = |id: u8, content: &[u8]| [&[id, content.len() as u8], content].concat();
+		let fact = [
+			&[
+				0,
+				op::LOCAL_GET,
+				0,
+				op::I32_EQZ,
+				op::IF,
+				I32,
+				op::I32_CONST,
+				1,
+			][..],
+			&[
+				op::ELSE,
+				op::LOCAL_GET,
+				0,
+				op::LOCAL_GET,
+				0,
+				op::I32_CONST,
+				1,
+			],
+			&[op::I32_SUB, op::CALL, 0, op::I32_MUL, op::END, op::END],
+		]
+		.concat();
+		let div = [
+			0,
+			op::LOCAL_GET,
+			0,
+			op::LOCAL_GET,
+			1,
+			op::I32_DIV_S,
+			op::END,
+		];
+		let code = [&[2, fact.len() as u8][..], &fact, &[div.len() as u8], &div].concat();
+		[
+			&b"\0asm\x01\0\0\0"[..],
+			&section(1, &[2, 0x60, 1, I32, 1, I32, 0x60, 2, I32, I32, 1, I32]),
+			&section(3, &[2, 0, 1]),
+			&section(
+				7,
+				&[
+					2, 4, b'f', b'a', b'c', b't', 0, 0, 3, b'd', b'i', b'v', 0, 1,
+				],
+			),
+			&section(10, &code),
+		]
+		.concat()
+	}
+
+	#[test]
+	fn calls_and_traps() {
+		let module = Module::decode(&module()).unwrap();
+		let mut instance = Instance::new(&module).unwrap();
+		let mut host = |_: &_, _: &mut _, _: &_| unreachable!("nothing is imported");
+
+		assert_eq!(
+			instance.invoke("fact", &[10], &mut host).unwrap(),
+			Some(3628800)
+		);
+		assert_eq!(
+			instance
+				.invoke("div", &[-7i32 as u32 as u64, 2], &mut host)
+				.unwrap(),
+			Some(-3i32 as u32 as u64)
+		);
+		let Err(Trap(err)) = instance.invoke("div", &[1, 0], &mut host) else {
+			panic!("dividing by zero doesn't trap")
+		};
+		assert_eq!(err, "integer divide by zero");
+		assert!(Module::decode(b"\0asm\x02\0\0\0").is_err());
+	}
+}
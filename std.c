@@ -0,0 +1,344 @@
+// runtime of the code `codegen_c` generates, the C counterpart of std.hvm
+// ints are kept in the pointer itself, with its lowest bit set. Other values are boxed and
+// tagged, bools are shared. Closures are a code pointer followed by the values they captured.
+// Boxed values live in an arena that is never freed, so a program's memory grows with every
+// tuple, string and closure it makes, loops over ints alone don't allocate.
+// functions that can fail take the packed span of the expression as a last `uint64_t loc`
+// argument, see `codegen::location`, and `rt_fail` unpacks it
+#include <inttypes.h>
+#include <pthread.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct Value *V;
+typedef V (*Code)(V self, V *args);
+
+enum Tag { INT, BOOL, STR, TUPLE, CLOSURE };
+
+struct Value {
+	enum Tag tag;
+	union {
+		bool b;
+		struct {
+			uint32_t len;
+			const char *data;
+		} s;
+		struct {
+			V first, second;
+		} t;
+		struct {
+			Code code;
+			uint32_t arity;
+		} c;
+	};
+	V env[];
+};
+
+static struct Value rt_true = {BOOL, .b = true};
+static struct Value rt_false = {BOOL, .b = false};
+
+static char *rt_arena, *rt_arena_end;
+
+static void *rt_alloc(size_t size) {
+	size = (size + 15) & ~(size_t)15;
+	if (!rt_arena || (size_t)(rt_arena_end - rt_arena) < size) {
+		size_t chunk = size > ((size_t)1 << 24) ? size : ((size_t)1 << 24);
+		rt_arena = malloc(chunk);
+		if (!rt_arena) {
+			fputs("out of memory\n", stderr);
+			exit(1);
+		}
+		rt_arena_end = rt_arena + chunk;
+	}
+	void *ptr = rt_arena;
+	rt_arena += size;
+	return ptr;
+}
+
+// boxed values are aligned, so their lowest bit is never set
+static V rt_int(int32_t i) {
+	return (V)(((uintptr_t)(uint32_t)i << 1) | 1);
+}
+
+static int32_t rt_i(V x) {
+	return (int32_t)(uint32_t)((uintptr_t)x >> 1);
+}
+
+static enum Tag rt_tag(V x) {
+	return (uintptr_t)x & 1 ? INT : x->tag;
+}
+
+static V rt_bool_value(bool b) {
+	return b ? &rt_true : &rt_false;
+}
+
+static V rt_tuple(V first, V second) {
+	V x = rt_alloc(sizeof(struct Value));
+	x->tag = TUPLE;
+	x->t.first = first;
+	x->t.second = second;
+	return x;
+}
+
+// the captured values are left for the caller to fill
+static V rt_closure(Code code, uint32_t arity, uint32_t captures) {
+	V x = rt_alloc(sizeof(struct Value) + captures * sizeof(V));
+	x->tag = CLOSURE;
+	x->c.code = code;
+	x->c.arity = arity;
+	return x;
+}
+
+static const char *rt_type(V x) {
+	switch (rt_tag(x)) {
+		case INT: return "int";
+		case BOOL: return "bool";
+		case STR: return "string";
+		case TUPLE: return "tuple";
+		default: return "closure";
+	}
+}
+
+static _Noreturn void rt_fail(const char *message, uint64_t loc) {
+	fflush(stdout);
+	fprintf(stderr, "runtime error: %s (at %" PRIu64 "..%" PRIu64 ")", message, loc >> 30,
+		loc & ((1 << 30) - 1));
+	exit(1);
+}
+
+static _Noreturn void rt_expected(const char *what, V x, uint64_t loc) {
+	char message[64];
+	snprintf(message, sizeof message, "expected %s, found %s", what, rt_type(x));
+	rt_fail(message, loc);
+}
+
+static _Noreturn void rt_mismatch(const char *op, V x, V y, uint64_t loc) {
+	char message[64];
+	snprintf(message, sizeof message, "cannot apply `%s` to %s and %s", op, rt_type(x), rt_type(y));
+	rt_fail(message, loc);
+}
+
+struct Buffer {
+	char *data;
+	size_t len, cap;
+};
+
+static void rt_push(struct Buffer *buffer, const char *data, size_t len) {
+	if (!len) return;
+	if (buffer->len + len > buffer->cap) {
+		buffer->cap = (buffer->len + len) * 2;
+		buffer->data = realloc(buffer->data, buffer->cap);
+		if (!buffer->data) {
+			fputs("out of memory\n", stderr);
+			exit(1);
+		}
+	}
+	memcpy(buffer->data + buffer->len, data, len);
+	buffer->len += len;
+}
+
+static void rt_show(struct Buffer *buffer, V x) {
+	switch (rt_tag(x)) {
+		case INT: {
+			char digits[16];
+			int len = snprintf(digits, sizeof digits, "%" PRId32, rt_i(x));
+			rt_push(buffer, digits, len);
+			break;
+		}
+		case BOOL:
+			if (x->b) rt_push(buffer, "true", 4);
+			else rt_push(buffer, "false", 5);
+			break;
+		case STR: rt_push(buffer, x->s.data, x->s.len); break;
+		case TUPLE:
+			rt_push(buffer, "(", 1);
+			rt_show(buffer, x->t.first);
+			rt_push(buffer, ", ", 2);
+			rt_show(buffer, x->t.second);
+			rt_push(buffer, ")", 1);
+			break;
+		case CLOSURE: rt_push(buffer, "<#closure>", 10); break;
+	}
+}
+
+static V rt_print(V x) {
+	struct Buffer buffer = {0};
+	rt_show(&buffer, x);
+	rt_push(&buffer, "\n", 1);
+	fwrite(buffer.data, 1, buffer.len, stdout);
+	free(buffer.data);
+	return x;
+}
+
+static V rt_first(V x, uint64_t loc) {
+	if (rt_tag(x) != TUPLE) rt_expected("a tuple", x, loc);
+	return x->t.first;
+}
+
+static V rt_second(V x, uint64_t loc) {
+	if (rt_tag(x) != TUPLE) rt_expected("a tuple", x, loc);
+	return x->t.second;
+}
+
+static V rt_call(V f, uint32_t argc, V *args, uint64_t loc) {
+	if (rt_tag(f) != CLOSURE) rt_expected("a closure", f, loc);
+	if (f->c.arity != argc) {
+		char message[96];
+		snprintf(message, sizeof message, "closure takes %" PRIu32 " arguments, but %" PRIu32 " were given",
+			f->c.arity, argc);
+		rt_fail(message, loc);
+	}
+	return f->c.code(f, args);
+}
+
+static bool rt_bool(V x, uint64_t loc) {
+	if (rt_tag(x) != BOOL) rt_expected("a bool", x, loc);
+	return x->b;
+}
+
+// a top-level `let` read by a function, which may run before it is defined
+static V rt_global(V x, const char *name, uint64_t loc) {
+	if (!x) {
+		char message[128];
+		snprintf(message, sizeof message, "`%s` is used before its definition", name);
+		rt_fail(message, loc);
+	}
+	return x;
+}
+
+static V rt_add(V x, V y, uint64_t loc) {
+	if (rt_tag(x) == INT && rt_tag(y) == INT) return rt_int((int32_t)((uint32_t)rt_i(x) + (uint32_t)rt_i(y)));
+	if ((rt_tag(x) != INT && rt_tag(x) != STR) || (rt_tag(y) != INT && rt_tag(y) != STR)) rt_mismatch("+", x, y, loc);
+
+	struct Buffer buffer = {0};
+	rt_show(&buffer, x);
+	rt_show(&buffer, y);
+	V str = rt_alloc(sizeof(struct Value));
+	str->tag = STR;
+	str->s.len = buffer.len;
+	str->s.data = buffer.data;
+	return str;
+}
+
+static V rt_sub(V x, V y, uint64_t loc) {
+	if (rt_tag(x) != INT || rt_tag(y) != INT) rt_mismatch("-", x, y, loc);
+	return rt_int((int32_t)((uint32_t)rt_i(x) - (uint32_t)rt_i(y)));
+}
+
+static V rt_mul(V x, V y, uint64_t loc) {
+	if (rt_tag(x) != INT || rt_tag(y) != INT) rt_mismatch("*", x, y, loc);
+	return rt_int((int32_t)((uint32_t)rt_i(x) * (uint32_t)rt_i(y)));
+}
+
+static V rt_div(V x, V y, uint64_t loc) {
+	if (rt_tag(x) != INT || rt_tag(y) != INT) rt_mismatch("/", x, y, loc);
+	if (rt_i(y) == 0) rt_fail("division by zero", loc);
+	// INT32_MIN / -1 overflows
+	if (rt_i(y) == -1) return rt_int((int32_t)(0u - (uint32_t)rt_i(x)));
+	return rt_int(rt_i(x) / rt_i(y));
+}
+
+static V rt_rem(V x, V y, uint64_t loc) {
+	if (rt_tag(x) != INT || rt_tag(y) != INT) rt_mismatch("%", x, y, loc);
+	if (rt_i(y) == 0) rt_fail("division by zero", loc);
+	if (rt_i(y) == -1) return rt_int(0);
+	return rt_int(rt_i(x) % rt_i(y));
+}
+
+#define RT_UNORDERED 2
+
+// -1, 0 or 1 for less, equal and greater, or RT_UNORDERED with the first pair of values of
+// different types in `lhs` and `rhs`
+static int rt_compare(V x, V y, V *lhs, V *rhs, uint64_t loc) {
+	if (rt_tag(x) == CLOSURE || rt_tag(y) == CLOSURE) rt_fail("cannot compare closures", loc);
+	if (rt_tag(x) != rt_tag(y)) {
+		*lhs = x;
+		*rhs = y;
+		return RT_UNORDERED;
+	}
+	switch (rt_tag(x)) {
+		case INT: return (rt_i(x) > rt_i(y)) - (rt_i(x) < rt_i(y));
+		case BOOL: return (x->b > y->b) - (x->b < y->b);
+		case STR: {
+			uint32_t len = x->s.len < y->s.len ? x->s.len : y->s.len;
+			int ord = len ? memcmp(x->s.data, y->s.data, len) : 0;
+			if (ord) return (ord > 0) - (ord < 0);
+			return (x->s.len > y->s.len) - (x->s.len < y->s.len);
+		}
+		default: {
+			int first = rt_compare(x->t.first, y->t.first, lhs, rhs, loc);
+			return first == 0 ? rt_compare(x->t.second, y->t.second, lhs, rhs, loc) : first;
+		}
+	}
+}
+
+static V rt_eq(V x, V y, uint64_t loc) {
+	if (rt_tag(x) == INT && rt_tag(y) == INT) return rt_bool_value(rt_i(x) == rt_i(y));
+	V lhs, rhs;
+	return rt_bool_value(rt_compare(x, y, &lhs, &rhs, loc) == 0);
+}
+
+static V rt_neq(V x, V y, uint64_t loc) {
+	return rt_bool_value(!rt_eq(x, y, loc)->b);
+}
+
+static int rt_order(const char *op, V x, V y, uint64_t loc) {
+	if (rt_tag(x) == INT && rt_tag(y) == INT) return (rt_i(x) > rt_i(y)) - (rt_i(x) < rt_i(y));
+	V lhs, rhs;
+	int ord = rt_compare(x, y, &lhs, &rhs, loc);
+	if (ord == RT_UNORDERED) rt_mismatch(op, lhs, rhs, loc);
+	return ord;
+}
+
+static V rt_lt(V x, V y, uint64_t loc) {
+	return rt_bool_value(rt_order("<", x, y, loc) < 0);
+}
+
+static V rt_lte(V x, V y, uint64_t loc) {
+	return rt_bool_value(rt_order("<=", x, y, loc) <= 0);
+}
+
+static V rt_gt(V x, V y, uint64_t loc) {
+	return rt_bool_value(rt_order(">", x, y, loc) > 0);
+}
+
+static V rt_gte(V x, V y, uint64_t loc) {
+	return rt_bool_value(rt_order(">=", x, y, loc) >= 0);
+}
+
+struct Main {
+	V (*main)(void);
+	V result;
+};
+
+static void *rt_thread(void *arg) {
+	struct Main *main = arg;
+	main->result = main->main();
+	return NULL;
+}
+
+// runs the program on a thread with a big stack, as calls not in tail position recurse in C
+// too, with `--result` writing its value to stderr
+static int rt_run(V (*main)(void), int argc, char **argv) {
+	struct Main run = {main, NULL};
+	pthread_attr_t attr;
+	pthread_t thread;
+	pthread_attr_init(&attr);
+	pthread_attr_setstacksize(&attr, (size_t)1 << 30);
+	if (pthread_create(&thread, &attr, rt_thread, &run) == 0) pthread_join(thread, NULL);
+	else rt_thread(&run);
+	fflush(stdout);
+
+	for (int i = 1; i < argc; i++) {
+		if (strcmp(argv[i], "--result") != 0) continue;
+		struct Buffer buffer = {0};
+		rt_show(&buffer, run.result);
+		fputs("result: ", stderr);
+		if (buffer.len) fwrite(buffer.data, 1, buffer.len, stderr);
+		free(buffer.data);
+	}
+	return 0;
+}
@@ -0,0 +1,172 @@
+// runtime of the code `codegen_js` generates, the JavaScript counterpart of std.hvm
+// values are numbers for ints, booleans, strings, [first, second] arrays for tuples and
+// functions for closures. Ints are kept as i32s with `| 0` after every operation.
+// functions that can fail take the packed span of the expression as a last `loc` argument,
+// see `codegen::location`, and throw it in a `$Error` that `$run` unpacks
+"use strict";
+
+class $Error extends Error {
+	constructor(message, loc) {
+		super(message);
+		this.loc = loc;
+	}
+}
+
+function $type(x) {
+	switch (typeof x) {
+		case "number": return "int";
+		case "boolean": return "bool";
+		case "string": return "string";
+		case "function": return "closure";
+		default: return "tuple";
+	}
+}
+
+function $show(x) {
+	switch (typeof x) {
+		case "function": return "<#closure>";
+		case "object": return `(${$show(x[0])}, ${$show(x[1])})`;
+		default: return String(x);
+	}
+}
+
+function $expected(what, x, loc) {
+	return new $Error(`expected ${what}, found ${$type(x)}`, loc);
+}
+
+function $mismatch(op, x, y, loc) {
+	return new $Error(`cannot apply \`${op}\` to ${$type(x)} and ${$type(y)}`, loc);
+}
+
+function $print(x) {
+	process.stdout.write($show(x) + "\n");
+	return x;
+}
+
+function $first(x, loc) {
+	if (typeof x !== "object") throw $expected("a tuple", x, loc);
+	return x[0];
+}
+
+function $second(x, loc) {
+	if (typeof x !== "object") throw $expected("a tuple", x, loc);
+	return x[1];
+}
+
+function $call(f, args, loc) {
+	if (typeof f !== "function") throw $expected("a closure", f, loc);
+	if (f.length !== args.length) {
+		throw new $Error(`closure takes ${f.length} arguments, but ${args.length} were given`, loc);
+	}
+	return f(...args);
+}
+
+// a `let` read by a function, which may run before it is defined
+function $defined(x, name, loc) {
+	if (x === undefined) throw new $Error(`\`${name}\` is used before its definition`, loc);
+	return x;
+}
+
+function $bool(x, loc) {
+	if (typeof x !== "boolean") throw $expected("a bool", x, loc);
+	return x;
+}
+
+function $add(x, y, loc) {
+	const tx = typeof x, ty = typeof y;
+	if (tx === "number" && ty === "number") return (x + y) | 0;
+	if ((tx === "string" || tx === "number") && (ty === "string" || ty === "number")) return `${x}${y}`;
+	throw $mismatch("+", x, y, loc);
+}
+
+function $sub(x, y, loc) {
+	if (typeof x !== "number" || typeof y !== "number") throw $mismatch("-", x, y, loc);
+	return (x - y) | 0;
+}
+
+function $mul(x, y, loc) {
+	if (typeof x !== "number" || typeof y !== "number") throw $mismatch("*", x, y, loc);
+	return Math.imul(x, y);
+}
+
+function $div(x, y, loc) {
+	if (typeof x !== "number" || typeof y !== "number") throw $mismatch("/", x, y, loc);
+	if (y === 0) throw new $Error("division by zero", loc);
+	return (x / y) | 0;
+}
+
+function $rem(x, y, loc) {
+	if (typeof x !== "number" || typeof y !== "number") throw $mismatch("%", x, y, loc);
+	if (y === 0) throw new $Error("division by zero", loc);
+	return (x % y) | 0;
+}
+
+// -1, 0 or 1 for less, equal and greater, or the first pair of values of different types
+function $compare(x, y, loc) {
+	if (typeof x === "function" || typeof y === "function") {
+		throw new $Error("cannot compare closures", loc);
+	}
+	if (typeof x !== typeof y) return [x, y];
+	if (typeof x === "object") {
+		const first = $compare(x[0], y[0], loc);
+		return first === 0 ? $compare(x[1], y[1], loc) : first;
+	}
+	return x < y ? -1 : x > y ? 1 : 0;
+}
+
+function $eq(x, y, loc) {
+	if (typeof x === "number" && typeof y === "number") return x === y;
+	return $compare(x, y, loc) === 0;
+}
+
+function $neq(x, y, loc) {
+	return !$eq(x, y, loc);
+}
+
+function $order(op, x, y, loc) {
+	const ord = $compare(x, y, loc);
+	if (typeof ord === "object") throw $mismatch(op, ord[0], ord[1], loc);
+	return ord;
+}
+
+function $lt(x, y, loc) {
+	return $order("<", x, y, loc) < 0;
+}
+
+function $lte(x, y, loc) {
+	return $order("<=", x, y, loc) <= 0;
+}
+
+function $gt(x, y, loc) {
+	return $order(">", x, y, loc) > 0;
+}
+
+function $gte(x, y, loc) {
+	return $order(">=", x, y, loc) >= 0;
+}
+
+// runs the program, with `--result` writing its value to stderr. Calls that aren't in tail
+// position nest JavaScript calls, so it runs on a thread with a stack as big as the one of
+// the interpreter, which starts the whole `$program` again.
+function $run(main) {
+	const { Worker, isMainThread } = require("node:worker_threads");
+	if (isMainThread) {
+		const worker = new Worker(`(${$program})()`, {
+			eval: true,
+			argv: process.argv.slice(2),
+			resourceLimits: { stackSizeMb: 1024 },
+		});
+		worker.on("exit", (code) => (process.exitCode = code));
+		return;
+	}
+
+	try {
+		const result = main();
+		if (process.argv.includes("--result")) process.stderr.write(`result: ${$show(result)}`);
+	} catch (e) {
+		if (!(e instanceof $Error)) throw e;
+		const start = Math.floor(e.loc / 0x40000000), end = e.loc % 0x40000000;
+		process.stderr.write(`runtime error: ${e.message} (at ${start}..${end})`);
+		process.exitCode = 1;
+	}
+}